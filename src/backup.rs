@@ -0,0 +1,315 @@
+//! The names of the backup files `backup` makes, and making them.
+
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Seek};
+use std::path::{Component, Path, PathBuf};
+
+use crate::error::{coded, ErrorCode};
+use crate::fs_path;
+use crate::nls::{gettext, tr};
+use crate::temp::TempFile;
+
+/// How many times to look for a new number when another program took the
+/// one found first.
+const ATTEMPTS: usize = 100;
+
+/// How the backup files of [`ConversionOptions::backup`] are named, and
+/// where they go.
+///
+/// [`ConversionOptions::backup`]: crate::ConversionOptions::backup
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BackupNaming {
+    /// Appended to the name of the file. Defaults to `~`, which makes the
+    /// backup of `notes.txt` `notes.txt~`. A backup made again replaces the
+    /// one before.
+    pub suffix: String,
+    /// Number the backups GNU style instead, as `notes.txt.~1~`,
+    /// `notes.txt.~2~` and so on, each taking the number after the highest
+    /// one there is, so that no backup is ever replaced. Overrides `suffix`.
+    /// Defaults to `false`.
+    pub numbered: bool,
+    /// Put the backups below this directory, at the same relative path as
+    /// the file, instead of next to it. A root or drive prefix and `.`
+    /// components are dropped, and a path with `..` is refused. Defaults to
+    /// `None`.
+    pub dir: Option<PathBuf>,
+}
+
+impl Default for BackupNaming {
+    fn default() -> Self {
+        BackupNaming {
+            suffix: String::from("~"),
+            numbered: false,
+            dir: None,
+        }
+    }
+}
+
+impl BackupNaming {
+    /// The name the next backup of `input_path` gets. For numbered backups
+    /// this looks at the backups there are.
+    pub fn path(&self, input_path: &Path) -> io::Result<PathBuf> {
+        let location = match &self.dir {
+            Some(dir) => mirror(dir, input_path)?,
+            None => input_path.to_path_buf(),
+        };
+        let mut name = location.as_os_str().to_os_string();
+        if self.numbered {
+            name.push(format!(".~{}~", next_number(&location)?));
+        } else {
+            name.push(&self.suffix);
+        }
+        let path = PathBuf::from(name);
+        if path == input_path {
+            return Err(coded(
+                io::ErrorKind::InvalidInput,
+                ErrorCode::Usage,
+                tr!(
+                    "the backup of '{}' would be the file itself",
+                    input_path.display()
+                ),
+            ));
+        }
+        Ok(path)
+    }
+}
+
+/// Copies `input`, the file opened from `input_path`, to `backup`, the
+/// [`BackupNaming::path`] for it. Directories below [`BackupNaming::dir`]
+/// are created as needed. The copy is written to a temporary file first and
+/// then moved into place, so that no partial backup is left, and nothing is
+/// written through a link at `backup`. A numbered backup that another
+/// program made in the meantime is left alone for the next number.
+pub(crate) fn create(
+    naming: &BackupNaming,
+    input_path: &Path,
+    mut input: &File,
+    backup: &Path,
+) -> io::Result<()> {
+    let mut backup_fs = fs_path(backup)?;
+    if let (Some(_), Some(parent)) = (&naming.dir, backup_fs.parent()) {
+        fs::create_dir_all(parent)?;
+    }
+    let mut temp = TempFile::create(&backup_fs)?;
+    input.rewind()?;
+    io::copy(&mut input, temp.file())?;
+    temp.file()
+        .set_permissions(input.metadata()?.permissions())?;
+    if !naming.numbered {
+        return temp.persist(&backup_fs);
+    }
+    for _ in 0..ATTEMPTS {
+        match temp.persist_new(&backup_fs) {
+            Ok(()) => return Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => {
+                backup_fs = fs_path(&naming.path(input_path)?)?;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Err(io::Error::new(
+        io::ErrorKind::AlreadyExists,
+        tr!("no unused backup number for '{}'", input_path.display()),
+    ))
+}
+
+/// `path` below `dir`, at the same relative path.
+fn mirror(dir: &Path, path: &Path) -> io::Result<PathBuf> {
+    let mut mirrored = dir.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => mirrored.push(name),
+            Component::ParentDir => {
+                return Err(coded(
+                    io::ErrorKind::InvalidInput,
+                    ErrorCode::Usage,
+                    gettext("a path with '..' cannot be mirrored into the backup directory").into(),
+                ))
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+    Ok(mirrored)
+}
+
+/// One more than the highest number of the backups `location.~N~` there
+/// are, or 1 if there are none.
+fn next_number(location: &Path) -> io::Result<u64> {
+    let dir = match location.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    };
+    let mut prefix = OsString::from(location.file_name().unwrap_or_default());
+    prefix.push(".~");
+    let prefix = prefix.to_string_lossy().into_owned();
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        // The backup directory is created with the first backup
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(1),
+        Err(e) => return Err(e),
+    };
+    let mut highest = 0;
+    for entry in entries {
+        let name = entry?.file_name();
+        let number = name
+            .to_string_lossy()
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix('~'))
+            .and_then(|number| number.parse::<u64>().ok());
+        if let Some(number) = number {
+            highest = highest.max(number);
+        }
+    }
+    Ok(highest + 1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ErrorCode;
+
+    /// A directory of its own for the test `name`.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dos2unix-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn numbered() -> BackupNaming {
+        BackupNaming {
+            numbered: true,
+            ..BackupNaming::default()
+        }
+    }
+
+    #[test]
+    fn numbers_follow_the_highest_backup() {
+        let dir = scratch("backup-numbers");
+        let file = dir.join("notes.txt");
+        let first = next_number(&file).unwrap();
+        fs::write(dir.join("notes.txt.~1~"), "").unwrap();
+        let second = next_number(&file).unwrap();
+        fs::write(dir.join("notes.txt.~7~"), "").unwrap();
+        fs::write(dir.join("notes.txt.~x~"), "").unwrap();
+        fs::write(dir.join("other.txt.~9~"), "").unwrap();
+        let eighth = numbered().path(&file);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!((first, second), (1, 2));
+        assert_eq!(eighth.unwrap(), dir.join("notes.txt.~8~"));
+    }
+
+    #[test]
+    fn numbered_backups_take_the_next_number() {
+        let dir = scratch("backup-create");
+        let file = dir.join("notes.txt");
+        fs::write(&file, "a\r\n").unwrap();
+        let input = File::open(&file).unwrap();
+        for _ in 0..2 {
+            let backup = numbered().path(&file).unwrap();
+            create(&numbered(), &file, &input, &backup).unwrap();
+        }
+        // Taken by another program after the name was found
+        let taken = numbered().path(&file).unwrap();
+        fs::write(&taken, "other").unwrap();
+        create(&numbered(), &file, &input, &taken).unwrap();
+        let backups = [3, 4].map(|number| fs::read(dir.join(format!("notes.txt.~{}~", number))));
+        let count = fs::read_dir(&dir).unwrap().count();
+        let first = fs::read(dir.join("notes.txt.~1~"));
+        let second = fs::read(dir.join("notes.txt.~2~"));
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(first.unwrap(), b"a\r\n");
+        assert_eq!(second.unwrap(), b"a\r\n");
+        let [third, fourth] = backups;
+        assert_eq!(third.unwrap(), b"other");
+        assert_eq!(fourth.unwrap(), b"a\r\n");
+        assert_eq!(count, 5);
+    }
+
+    #[test]
+    fn the_backup_is_of_the_file_opened() {
+        let dir = scratch("backup-opened");
+        let file = dir.join("notes.txt");
+        fs::write(&file, "a\r\n").unwrap();
+        let input = File::open(&file).unwrap();
+        // Replaced once it was opened
+        fs::remove_file(&file).unwrap();
+        fs::write(&file, "replaced").unwrap();
+        let backup = BackupNaming::default().path(&file).unwrap();
+        create(&BackupNaming::default(), &file, &input, &backup).unwrap();
+        let copied = fs::read(&backup);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(copied.unwrap(), b"a\r\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_link_at_the_backup_is_replaced_not_written_through() {
+        let dir = scratch("backup-link");
+        let (file, elsewhere) = (dir.join("notes.txt"), dir.join("elsewhere"));
+        fs::write(&file, "a\r\n").unwrap();
+        fs::write(&elsewhere, "kept").unwrap();
+        let backup = BackupNaming::default().path(&file).unwrap();
+        std::os::unix::fs::symlink(&elsewhere, &backup).unwrap();
+        let input = File::open(&file).unwrap();
+        create(&BackupNaming::default(), &file, &input, &backup).unwrap();
+        let is_link = fs::symlink_metadata(&backup).unwrap().is_symlink();
+        let (copied, kept) = (fs::read(&backup), fs::read(&elsewhere));
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(!is_link);
+        assert_eq!(copied.unwrap(), b"a\r\n");
+        assert_eq!(kept.unwrap(), b"kept");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_backup_that_fails_is_removed() {
+        let dir = scratch("backup-failed");
+        // Opened, but failing to be read
+        let input = dir.join("input");
+        fs::create_dir(&input).unwrap();
+        let backup = numbered().path(&input).unwrap();
+        let result = create(&numbered(), &input, &File::open(&input).unwrap(), &backup);
+        let left = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.is_err());
+        // Neither the backup nor its temporary file
+        assert_eq!(left, 1);
+    }
+
+    #[test]
+    fn paths_are_mirrored_below_the_backup_dir() {
+        let dir = Path::new("backups");
+        assert_eq!(
+            mirror(dir, Path::new("src/./lib.rs")).unwrap(),
+            Path::new("backups/src/lib.rs")
+        );
+        let absolute = std::env::current_dir().unwrap().join("notes.txt");
+        let mut expected = dir.to_path_buf();
+        expected.extend(
+            absolute
+                .components()
+                .filter_map(|component| match component {
+                    Component::Normal(name) => Some(name),
+                    _ => None,
+                }),
+        );
+        assert_eq!(mirror(dir, &absolute).unwrap(), expected);
+        #[cfg(unix)]
+        assert_eq!(
+            mirror(dir, Path::new("/etc/hosts")).unwrap(),
+            Path::new("backups/etc/hosts")
+        );
+        #[cfg(windows)]
+        assert_eq!(
+            mirror(dir, Path::new(r"C:\etc\hosts")).unwrap(),
+            Path::new(r"backups\etc\hosts")
+        );
+    }
+
+    #[test]
+    fn paths_with_parent_dirs_are_refused() {
+        let error = mirror(Path::new("backups"), Path::new("src/../../notes.txt")).unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::Usage);
+    }
+}
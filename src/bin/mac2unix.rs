@@ -0,0 +1,6 @@
+// src/bin/mac2unix.rs
+use dos2unix_lib::cli::{self, Tool};
+
+fn main() {
+    std::process::exit(cli::run_as_invoked(Tool::Mac2Unix));
+}
@@ -0,0 +1,6 @@
+// src/bin/unix2mac.rs
+use dos2unix_lib::cli::{self, Tool};
+
+fn main() {
+    std::process::exit(cli::run_as_invoked(Tool::Unix2Mac));
+}
@@ -0,0 +1,147 @@
+//! The policy deciding whether a file is binary.
+
+use crate::units::CodeUnit;
+
+/// Which characters make a file count as binary.
+///
+/// Besides LF and CR, which are always allowed, a control character (below
+/// 0x20) marks a file as binary unless it is in `allowed_controls`. NUL is
+/// governed by `nul_threshold` instead.
+///
+/// With `utf8_aware`, a file whose content is well-formed Unicode is judged
+/// by the density of disallowed control characters rather than by their
+/// mere presence, so that text with the occasional escape sequence, such as
+/// a colored log, is not rejected. Malformed content is judged strictly.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct BinaryPolicy {
+    /// Control characters allowed in text, one bit per character: bit 9 set
+    /// means TAB is allowed. Defaults to TAB and form feed.
+    pub allowed_controls: u32,
+    /// Number of NUL characters tolerated before the file counts as binary.
+    /// Defaults to 0.
+    pub nul_threshold: usize,
+    /// Only examine the first this many bytes of the file. Defaults to
+    /// `None`, which examines all of it.
+    pub sample_size: Option<usize>,
+    /// Tolerate a low density of control characters in well-formed text.
+    /// Defaults to `false`.
+    pub utf8_aware: bool,
+}
+
+/// In well-formed text judged by [`BinaryPolicy::utf8_aware`], the file is
+/// binary once more than one in this many units is a disallowed control
+/// character.
+const CONTROL_DENSITY: usize = 10;
+
+impl Default for BinaryPolicy {
+    fn default() -> Self {
+        BinaryPolicy {
+            allowed_controls: (1 << b'\t') | (1 << 0x0C),
+            nul_threshold: 0,
+            sample_size: None,
+            utf8_aware: false,
+        }
+    }
+}
+
+impl BinaryPolicy {
+    /// Whether the control character `value` may appear in text.
+    pub fn allows_control(&self, value: u8) -> bool {
+        value == b'\n' || value == b'\r' || self.allowed_controls & (1 << value) != 0
+    }
+
+    /// Position of the first unit of `content` that makes it binary, or
+    /// `None` if it is text.
+    pub(crate) fn find_binary<T: CodeUnit>(&self, content: &[T]) -> Option<usize> {
+        let sample_len = self
+            .sample_size
+            .map_or(content.len(), |size| size / std::mem::size_of::<T>());
+        let sample = &content[..sample_len.min(content.len())];
+        let tolerate_controls = self.utf8_aware && T::is_valid_prefix(sample);
+        let mut nuls = 0;
+        let mut controls = 0;
+        let mut first_control = None;
+        for (idx, unit) in sample.iter().enumerate() {
+            match unit.value() {
+                0 => {
+                    nuls += 1;
+                    if nuls > self.nul_threshold {
+                        return Some(idx);
+                    }
+                }
+                value if value < 32 && !self.allows_control(value as u8) => {
+                    if !tolerate_controls {
+                        return Some(idx);
+                    }
+                    controls += 1;
+                    first_control.get_or_insert(idx);
+                }
+                _ => {}
+            }
+        }
+        first_control.filter(|_| controls * CONTROL_DENSITY > sample.len())
+    }
+}
+
+/// The search of [`BinaryPolicy::find_binary`], done a unit at a time as the
+/// content is converted so that it is read only once. The content may be
+/// converted in pieces, one after the other. A `utf8_aware` policy needs the
+/// whole content up front, and is not supported.
+pub(crate) struct BinaryScan {
+    policy: BinaryPolicy,
+    /// Units of the content before the piece being converted.
+    base: usize,
+    /// NULs found so far.
+    nuls: usize,
+    /// Set once something binary was found, or there is nothing to look for.
+    done: bool,
+}
+
+impl BinaryScan {
+    pub(crate) fn new(policy: BinaryPolicy) -> Self {
+        BinaryScan {
+            policy,
+            base: 0,
+            nuls: 0,
+            done: false,
+        }
+    }
+
+    /// A scan that finds nothing, for content that was already checked or
+    /// is converted whatever it holds.
+    pub(crate) fn finished() -> Self {
+        BinaryScan {
+            done: true,
+            ..BinaryScan::new(BinaryPolicy::default())
+        }
+    }
+
+    /// Whether `unit`, at `idx` in the piece being converted, makes the
+    /// content binary. Only the first such unit is reported.
+    #[inline]
+    pub(crate) fn check<T: CodeUnit>(&mut self, unit: T, idx: usize) -> bool {
+        let value = unit.value();
+        if self.done || value >= 32 {
+            return false;
+        }
+        if let Some(size) = self.policy.sample_size {
+            if self.base + idx >= size / std::mem::size_of::<T>() {
+                self.done = true;
+                return false;
+            }
+        }
+        let binary = if value == 0 {
+            self.nuls += 1;
+            self.nuls > self.policy.nul_threshold
+        } else {
+            !self.policy.allows_control(value as u8)
+        };
+        self.done = binary;
+        binary
+    }
+
+    /// Moves on past `len` units of the piece being converted.
+    pub(crate) fn advance(&mut self, len: usize) {
+        self.base += len;
+    }
+}
@@ -0,0 +1,93 @@
+use crate::units::encode_units;
+
+/// A Unicode byte order mark.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BomKind {
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl BomKind {
+    /// Identifies the BOM at the start of `content`, if any.
+    pub fn detect(content: &[u8]) -> Option<BomKind> {
+        // UTF-32LE must be checked before UTF-16LE, whose BOM is its prefix.
+        [
+            BomKind::Utf8,
+            BomKind::Utf32Le,
+            BomKind::Utf32Be,
+            BomKind::Utf16Le,
+            BomKind::Utf16Be,
+        ]
+        .into_iter()
+        .find(|bom| content.starts_with(bom.bytes()))
+    }
+
+    /// The encoded BOM.
+    pub fn bytes(self) -> &'static [u8] {
+        match self {
+            BomKind::Utf8 => &[0xEF, 0xBB, 0xBF],
+            BomKind::Utf16Le => &[0xFF, 0xFE],
+            BomKind::Utf16Be => &[0xFE, 0xFF],
+            BomKind::Utf32Le => &[0xFF, 0xFE, 0x00, 0x00],
+            BomKind::Utf32Be => &[0x00, 0x00, 0xFE, 0xFF],
+        }
+    }
+
+    /// Size in bytes of one code unit of the encoding this BOM announces.
+    pub fn unit_size(self) -> usize {
+        match self {
+            BomKind::Utf8 => 1,
+            BomKind::Utf16Le | BomKind::Utf16Be => 2,
+            BomKind::Utf32Le | BomKind::Utf32Be => 4,
+        }
+    }
+
+    /// Whether code units are stored least significant byte first.
+    pub fn is_little_endian(self) -> bool {
+        matches!(self, BomKind::Utf16Le | BomKind::Utf32Le)
+    }
+
+    /// Parses an encoding name such as `utf8`, `UTF-8` or `utf16le`.
+    pub fn from_name(name: &str) -> Option<BomKind> {
+        match name.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "utf8" => Some(BomKind::Utf8),
+            "utf16le" => Some(BomKind::Utf16Le),
+            "utf16be" => Some(BomKind::Utf16Be),
+            "utf32le" => Some(BomKind::Utf32Le),
+            "utf32be" => Some(BomKind::Utf32Be),
+            _ => None,
+        }
+    }
+
+    /// Appends `text` encoded in the encoding this BOM announces, without the
+    /// BOM itself.
+    pub(crate) fn encode(self, text: &str, output: &mut Vec<u8>) {
+        let little_endian = self.is_little_endian();
+        match self.unit_size() {
+            1 => output.extend_from_slice(text.as_bytes()),
+            2 => encode_units(
+                &text.encode_utf16().collect::<Vec<_>>(),
+                little_endian,
+                output,
+            ),
+            _ => encode_units(
+                &text.chars().map(u32::from).collect::<Vec<_>>(),
+                little_endian,
+                output,
+            ),
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            BomKind::Utf8 => "UTF-8",
+            BomKind::Utf16Le => "UTF-16LE",
+            BomKind::Utf16Be => "UTF-16BE",
+            BomKind::Utf32Le => "UTF-32LE",
+            BomKind::Utf32Be => "UTF-32BE",
+        }
+    }
+}
@@ -0,0 +1,95 @@
+//! Conversion of reference-counted [`Bytes`] buffers, for the `bytes`
+//! feature.
+
+use std::io;
+
+use bytes::{Bytes, BytesMut};
+
+use crate::binary::BinaryScan;
+use crate::bom::BomKind;
+use crate::{
+    convert_scanning, convert_with_options, finish_conversion, is_clean, is_plain, ConversionMode,
+    ConversionOptions, Progress,
+};
+
+/// How much of the buffer [`convert_in_place`] converts at a time.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// Converts `input` with `options` as [`convert_with_options`] does, copying
+/// as little as it can.
+///
+/// Input that needs no conversion is returned as it is, which only bumps a
+/// reference count. Unix and Mac conversions, which never make a line break
+/// longer, are done in the buffer of `input` itself when it is not shared
+/// and the options ask for nothing but converting line breaks. Anything
+/// else is converted into a new buffer, handed over without another copy.
+///
+/// On an error, such as binary content without `force`, what was in
+/// `input` is lost if its buffer was being converted in place.
+pub fn convert_bytes(input: Bytes, options: &ConversionOptions) -> io::Result<Bytes> {
+    if is_clean(&input, options) {
+        return Ok(input);
+    }
+    let shrinks = options.conversion_mode != ConversionMode::ToDos
+        && is_plain(options)
+        && BomKind::detect(&input).is_none();
+    let input = if shrinks {
+        match input.try_into_mut() {
+            Ok(mut buffer) => {
+                convert_in_place(&mut buffer, options)?;
+                return Ok(buffer.freeze());
+            }
+            Err(input) => input,
+        }
+    } else {
+        input
+    };
+    let converted = convert_with_options(&input, options)?;
+    if converted == input {
+        return Ok(input);
+    }
+    Ok(Bytes::from(converted))
+}
+
+/// Converts `buffer` a chunk at a time, writing each converted chunk over
+/// the input already read. That stays behind the input still to be read
+/// only as long as no line break gets longer, so `options` must convert
+/// plain content to Unix or Mac line breaks.
+fn convert_in_place(buffer: &mut BytesMut, options: &ConversionOptions) -> io::Result<()> {
+    let mut scan = if options.force && options.verbose < 2 {
+        BinaryScan::finished()
+    } else {
+        BinaryScan::new(options.binary_policy)
+    };
+    let mut progress = Progress::new();
+    let mut output = Vec::with_capacity(CHUNK_SIZE);
+    let mut line_number = 1;
+    let mut read = 0;
+    let mut written = 0;
+    while read < buffer.len() {
+        let mut end = buffer.len().min(read + CHUNK_SIZE);
+        // A chunk must not end with a CR, which may be half of a CRLF
+        if end < buffer.len() && buffer[end - 1] == b'\r' {
+            end -= 1;
+        }
+        let chunk = &buffer[read..end];
+        output.clear();
+        convert_scanning(
+            chunk,
+            &mut output,
+            options,
+            &mut progress,
+            &mut scan,
+            line_number,
+        )?;
+        line_number += chunk.iter().filter(|&&byte| byte == b'\n').count();
+        buffer[written..written + output.len()].copy_from_slice(&output);
+        written += output.len();
+        read = end;
+    }
+    buffer.truncate(written);
+    output.clear();
+    finish_conversion(&mut output, options, progress);
+    buffer.extend_from_slice(&output);
+    Ok(())
+}
@@ -0,0 +1,3470 @@
+//! Command-line front end shared by the `dos2unix` and `unix2dos` binaries.
+
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+use std::env;
+use std::ffi::OsString;
+use std::fs::{self, File, Metadata};
+use std::io::{self, Read, Seek, Write};
+use std::mem;
+use std::num::NonZeroUsize;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime};
+
+use clap::builder::Styles;
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
+use clap::{value_parser, Arg, ArgAction, ArgMatches, Command};
+use clap_complete::Shell;
+
+use crate::color::{paint, ColorChoice, Style};
+use crate::config::{self, config_files};
+use crate::diff::write_diff;
+use crate::error::coded;
+use crate::gitattributes::GitAttributes;
+use crate::glob;
+use crate::input::{open_input, read_file, FileContent};
+use crate::json::JsonObject;
+use crate::nls::{self, gettext, tr};
+use crate::progress::ProgressBar;
+use crate::signal;
+use crate::stream::{convert_pipe, scan_file};
+use crate::transcode;
+use crate::walk::{walk_files, PathFilter};
+use crate::{
+    check_utf8, convert_with_options, detect_line_endings, is_binary_with, is_plain, is_stdin_tty,
+    localize_messages, mixed_line_endings_message, process_file_with_options, refuse_clobber,
+    BackupNaming, BinaryPolicy, BomKind, CharConversion, CodePage, ConversionMode,
+    ConversionOptions, ErrorCode, FileOutcome, LineEndingStats, ProcessedFile, Reporter,
+    StderrReporter, Summary, TargetNewline, DEFAULT_BUFFER_SIZE, DEFAULT_RETRY_DELAY,
+    DEFAULT_SKIP_EXTENSIONS,
+};
+
+/// How often `--watch` looks for changed files.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Subcommands and the option each stands for, given as the first argument.
+const SUBCOMMANDS: [(&str, Option<&str>); 4] = [
+    ("convert", None),
+    ("info", Some("--info")),
+    ("check", Some("--check")),
+    ("watch", Some("--watch")),
+];
+
+/// The command-line tools built on this library.
+#[derive(Copy, Clone)]
+pub enum Tool {
+    Dos2Unix,
+    Unix2Dos,
+    Mac2Unix,
+    Unix2Mac,
+}
+
+impl Tool {
+    /// The tool named by the program name `path`, such as `unix2dos` for
+    /// `/usr/bin/unix2dos` or `UNIX2DOS.EXE`.
+    pub fn from_program_name(path: &Path) -> Option<Tool> {
+        let name = path.file_stem()?.to_string_lossy().to_ascii_lowercase();
+        [
+            Tool::Dos2Unix,
+            Tool::Unix2Dos,
+            Tool::Mac2Unix,
+            Tool::Unix2Mac,
+        ]
+        .into_iter()
+        .find(|tool| tool.name() == name)
+    }
+
+    fn description(self) -> &'static str {
+        match self {
+            Tool::Dos2Unix => {
+                gettext("Converts text files with DOS or Mac line endings to Unix line endings.")
+            }
+            Tool::Unix2Dos => {
+                gettext("Converts text files with Unix or Mac line endings to DOS line endings.")
+            }
+            Tool::Mac2Unix => {
+                gettext("Converts text files with Mac line endings to Unix line endings.")
+            }
+            Tool::Unix2Mac => {
+                gettext("Converts text files with Unix line endings to Mac line endings.")
+            }
+        }
+    }
+
+    fn mac_help(self) -> &'static str {
+        match self {
+            Tool::Dos2Unix => gettext("Convert Mac line endings (CR) to Unix (LF)."),
+            Tool::Unix2Dos => {
+                gettext("Write Mac line endings (CR) instead of DOS ones, as unix2mac does.")
+            }
+            Tool::Mac2Unix => {
+                gettext("Convert Mac line endings (CR) to Unix (LF), as is always done.")
+            }
+            Tool::Unix2Mac => gettext("Write Mac line endings (CR), as is always done."),
+        }
+    }
+
+    /// The name of the tool, and of its table in configuration files.
+    fn name(self) -> &'static str {
+        match self {
+            Tool::Dos2Unix => "dos2unix",
+            Tool::Unix2Dos => "unix2dos",
+            Tool::Mac2Unix => "mac2unix",
+            Tool::Unix2Mac => "unix2mac",
+        }
+    }
+
+    fn version_name(self) -> &'static str {
+        match self {
+            Tool::Dos2Unix => "dos2unix-rust",
+            Tool::Unix2Dos => "unix2dos-rust",
+            Tool::Mac2Unix => "mac2unix-rust",
+            Tool::Unix2Mac => "unix2mac-rust",
+        }
+    }
+
+    /// The environment variable holding default options for the tool.
+    fn options_variable(self) -> &'static str {
+        match self {
+            Tool::Dos2Unix => "DOS2UNIX_OPTS",
+            Tool::Unix2Dos => "UNIX2DOS_OPTS",
+            Tool::Mac2Unix => "MAC2UNIX_OPTS",
+            Tool::Unix2Mac => "UNIX2MAC_OPTS",
+        }
+    }
+
+    fn conversion_mode(self, mac_mode: bool) -> ConversionMode {
+        match (self, mac_mode) {
+            (Tool::Dos2Unix, true) => ConversionMode::ToUnix, // Convert Mac line endings to Unix
+            (Tool::Dos2Unix, false) => ConversionMode::ToUnix,
+            (Tool::Unix2Dos, true) => ConversionMode::ToMac, // Write Mac line endings instead
+            (Tool::Unix2Dos, false) => ConversionMode::ToDos,
+            (Tool::Mac2Unix, _) => ConversionMode::ToUnix,
+            (Tool::Unix2Mac, _) => ConversionMode::ToMac,
+        }
+    }
+}
+
+/// The layout of `--help`, which is that of clap with headings that can be
+/// translated.
+fn help_template() -> String {
+    let styles = Styles::default();
+    let (usage, header) = (styles.get_usage(), styles.get_header());
+    format!(
+        "{{about-with-newline}}\n{usage}{}{usage:#} {{usage}}\n\n\
+         {header}{}{header:#}\n{{positionals}}\n\n\
+         {header}{}{header:#}\n{{options}}{{after-help}}",
+        gettext("Usage:"),
+        gettext("Arguments:"),
+        gettext("Options:")
+    )
+}
+
+/// The command-line syntax of `tool`, run as `progname`.
+fn command(tool: Tool, progname: &str) -> Command {
+    let flag = |name: &'static str, help: &'static str| {
+        Arg::new(name)
+            .long(name)
+            .action(ArgAction::SetTrue)
+            .help(help)
+    };
+    let option = |name: &'static str, value_name: &'static str, help: &'static str| {
+        Arg::new(name).long(name).value_name(value_name).help(help)
+    };
+    let bom_options = ["keep-bom", "remove-bom", "add-bom", "bom"];
+    Command::new(progname.to_string())
+        .about(tool.description())
+        .override_usage(format!(
+            "{0} [options] [FILE ...] [-n INFILE OUTFILE]\n       \
+             {0} convert|info|check|watch [options] [FILE ...]",
+            progname
+        ))
+        .after_help(tr!(
+            "The subcommands convert, info, check and watch, given first, are the same \
+             as no option, --info, --check and --watch.\n\n\
+             The forms -ascii, -iso and -gb of GNU dos2unix are accepted as well, and \
+             -437, -850, -860, -863, -865 and -1252 select that DOS or Windows code \
+             page for --iso.\n\n\
+             Arguments after -- are file names even if they start with '-', as in \
+             '{} -- -notes.txt'.",
+            progname
+        ))
+        .help_template(help_template())
+        .disable_help_flag(true)
+        .disable_version_flag(true)
+        .args_override_self(true)
+        .infer_long_args(true)
+        .arg(flag("backup", gettext("Make a backup of each file.")).short('b'))
+        .arg(
+            option(
+                "backup-suffix",
+                "SUF",
+                gettext("Name backups by appending SUF instead of ~; implies -b."),
+            )
+            .value_parser(|value: &str| {
+                if value.is_empty() || value.chars().any(std::path::is_separator) {
+                    return Err(gettext("expected a suffix without path separators, such as .bak"));
+                }
+                Ok(value.to_string())
+            }),
+        )
+        .arg(
+            flag(
+                "backup-numbered",
+                gettext("Number backups as file.~1~, file.~2~ and so on, never replacing an \
+                 earlier one; implies -b."),
+            )
+            .conflicts_with("backup-suffix"),
+        )
+        .arg(
+            option(
+                "backup-dir",
+                "DIR",
+                gettext("Put backups at the same paths below DIR, creating directories as \
+                 needed, instead of next to the files; implies -b."),
+            )
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(flag(
+            "keep-date",
+            gettext("Keep the modification and access times of the input."),
+        ))
+        .arg(
+            flag(
+                "allow-chown",
+                gettext("Convert files in place even if their owner cannot be preserved."),
+            )
+            .overrides_with("no-allow-chown"),
+        )
+        .arg(
+            flag(
+                "no-allow-chown",
+                gettext("Leave files whose owner cannot be preserved alone (default)."),
+            )
+            .overrides_with("allow-chown"),
+        )
+        .arg(
+            flag(
+                "follow-symlink",
+                gettext("Convert the target of a symbolic link, keeping the link."),
+            )
+            .short('F')
+            .overrides_with("skip-symlink"),
+        )
+        .arg(
+            flag(
+                "skip-symlink",
+                gettext("Leave symbolic links and their targets alone (default)."),
+            )
+            .short('S')
+            .overrides_with("follow-symlink"),
+        )
+        .arg(flag(
+            "sync",
+            gettext("Flush each converted file to disk before replacing the original."),
+        ))
+        .arg(flag(
+            "in-place",
+            gettext("Rewrite each file through its own descriptor, keeping its inode and hard \
+             links, instead of replacing it."),
+        ))
+        .arg(flag(
+            "force-readonly",
+            gettext("Convert read-only files, keeping them read-only (Windows)."),
+        ))
+        .arg(flag(
+            "force-special",
+            gettext("Read FIFOs, devices and sockets, which are otherwise refused."),
+        ))
+        .arg(flag(
+            "lock",
+            gettext("Hold an advisory lock on each file while converting it, so that other \
+             runs with --lock leave it alone until it is done."),
+        ))
+        .arg(
+            option(
+                "retry",
+                "N",
+                gettext("Try a file that another program has locked up to N more times before \
+                 skipping it (default 0)."),
+            )
+            .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            option(
+                "retry-delay",
+                "MS",
+                gettext("Wait MS milliseconds before each retry (200)."),
+            )
+            .value_parser(value_parser!(u64)),
+        )
+        .arg(flag(
+            "stream",
+            gettext("Convert files a chunk at a time instead of reading them into memory whole, \
+             as is done above 64 MiB."),
+        ))
+        .arg(flag(
+            "mmap",
+            gettext("Map input files into memory instead of reading them."),
+        ))
+        .arg(
+            option(
+                "buffer-size",
+                "SIZE",
+                gettext("Read and write SIZE bytes at a time when streaming (1M)."),
+            )
+            .value_parser(|value: &str| match parse_size(value) {
+                Some(size) if size > 0 => Ok(size),
+                _ => Err(gettext("expected a size in bytes, such as 64k or 4M")),
+            }),
+        )
+        .arg(flag("force", gettext("Force conversion of binary files.")).short('f'))
+        .arg(flag(
+            "skip-binary",
+            gettext("Skip binary files with a notice instead of failing."),
+        ))
+        .arg(flag(
+            "fail-fast",
+            gettext("Stop at the first file that fails, leaving the rest alone."),
+        ))
+        .arg(flag(
+            "no-sniff",
+            gettext("Do not recognize well-known binary formats (PNG, ZIP, ELF, ...) by their \
+             first bytes; scan them like any file."),
+        ))
+        .arg(
+            option(
+                "skip-ext",
+                "LIST",
+                gettext("Also skip files with these comma-separated extensions without opening \
+                 them. An empty LIST clears the list, including the built-in one (png, \
+                 jpg, zip, exe, ...)."),
+            )
+            .action(ArgAction::Append),
+        )
+        .arg(
+            option(
+                "allow-control",
+                "LIST",
+                gettext("Control characters allowed in text files, as a comma-separated list of \
+                 codes (default 9,12 for TAB and FF)."),
+            )
+            .value_parser(|value: &str| {
+                parse_controls(value).ok_or(gettext("expected codes of control characters, such as 9,12"))
+            }),
+        )
+        .arg(flag(
+            "smart-binary",
+            gettext("Accept well-formed UTF-8 text with a few control characters."),
+        ))
+        .arg(
+            option(
+                "nul-threshold",
+                "N",
+                gettext("Number of NUL bytes allowed in text files (default 0)."),
+            )
+            .value_parser(value_parser!(usize)),
+        )
+        .arg(
+            option(
+                "binary-sample",
+                "SIZE",
+                gettext("Only look for binary content in the first SIZE bytes; accepts k and M \
+                 suffixes (default: whole file)."),
+            )
+            .value_parser(|value: &str| {
+                parse_size(value).ok_or(gettext("expected a size in bytes, such as 64k or 4M"))
+            }),
+        )
+        .arg(
+            option(
+                "info",
+                "FLAGS",
+                gettext("Display file information and exit without converting. FLAGS select \
+                 columns: d (DOS), u (Unix), m (Mac), b (BOM), e (text/binary), c (only \
+                 files that would be converted)."),
+            )
+            .short('i')
+            .num_args(0..=1)
+            .require_equals(true)
+            .default_missing_value("")
+            .value_parser(|value: &str| {
+                InfoFlags::parse(value).map_err(|flag| tr!("wrong flag '{}'", flag))
+            }),
+        )
+        .arg(
+            option(
+                "format",
+                "FORMAT",
+                gettext("Output format for file reports: text (default), json, or csv and tsv for \
+                 --info."),
+            )
+            .value_parser(|value: &str| {
+                OutputFormat::parse(value).ok_or(gettext("expected text, json, csv or tsv"))
+            }),
+        )
+        .arg(
+            option(
+                "color",
+                "WHEN",
+                gettext("Color messages and --info columns: auto (on a terminal, the default), \
+                 always or never."),
+            )
+            .value_parser(|value: &str| {
+                ColorChoice::parse(value).ok_or(gettext("expected auto, always or never"))
+            }),
+        )
+        .arg(
+            flag(
+                "porcelain",
+                gettext("Print one tab-separated record per file on stdout, in a format that stays \
+                 the same from release to release, for scripts."),
+            )
+            .conflicts_with("format"),
+        )
+        .arg(
+            option(
+                "messages-to",
+                "STREAM",
+                gettext("Print informational messages to stderr (the default) or stdout. Errors \
+                 always go to stderr."),
+            )
+            .value_parser(["stderr", "stdout"])
+            .conflicts_with_all(["to-stdout", "format", "porcelain", "print0"]),
+        )
+        .arg(
+            flag(
+                "print0",
+                gettext("Print the path of each file the conversion modified on stdout, followed by \
+                 a NUL, for 'xargs -0'."),
+            )
+            .conflicts_with_all([
+                "to-stdout",
+                "format",
+                "porcelain",
+                "check",
+                "info",
+                "diff",
+                "dry-run",
+            ]),
+        )
+        .arg(
+            option(
+                "report",
+                "FILE",
+                gettext("Write a JSON report of the run to FILE: what was done to each file, its \
+                 line breaks, any error and how long it took."),
+            )
+            .value_parser(value_parser!(PathBuf))
+            .conflicts_with_all(["check", "info", "diff", "dry-run", "watch"]),
+        )
+        .arg(flag(
+            "check",
+            gettext("List files that need conversion and exit non-zero if there are any, without \
+             writing anything."),
+        ))
+        .arg(
+            flag(
+                "watch",
+                gettext("After converting the files, keep converting each one again whenever it \
+                 changes, until interrupted."),
+            )
+            .conflicts_with_all(["check", "info", "diff", "dry-run"]),
+        )
+        .arg(flag(
+            "check-utf8",
+            gettext("Report files that are not valid UTF-8, and do not convert them."),
+        ))
+        .arg(flag(
+            "strict",
+            gettext("Refuse to convert files with mixed line endings, and report them with \
+             --check."),
+        ))
+        .arg(flag(
+            "diff",
+            gettext("Show the changes as a unified diff instead of writing them."),
+        ))
+        .arg(flag(
+            "dry-run",
+            gettext("Show what would be done without writing anything."),
+        ))
+        .arg(
+            flag(
+                "interactive",
+                gettext("Show what each file holds and ask whether to convert it: y(es), n(o), \
+                 a(ll the rest) or q(uit)."),
+            )
+            .conflicts_with_all(["check", "info", "diff", "dry-run", "watch"]),
+        )
+        .arg(
+            flag(
+                "add-bom",
+                gettext("Add a UTF-8 Byte Order Mark (BOM) if there is none."),
+            )
+            .overrides_with_all(bom_options),
+        )
+        .arg(
+            option(
+                "bom",
+                "ENCODING",
+                gettext("Write the output in ENCODING with its BOM: utf8, utf16le, utf16be, \
+                 utf32le or utf32be."),
+            )
+            .overrides_with_all(bom_options)
+            .value_parser(|value: &str| {
+                BomKind::from_name(value)
+                    .ok_or(gettext("expected utf8, utf16le, utf16be, utf32le or utf32be"))
+            }),
+        )
+        .arg(flag("7", gettext("Clear the 8th bit of every byte.")).short('7'))
+        .arg(flag("ascii", gettext("Convert only line breaks (default).")))
+        .arg(flag(
+            "iso",
+            gettext("Convert between the DOS code page (CP437 by default) and ISO-8859-1."),
+        ))
+        .arg(
+            option(
+                "convmode",
+                "MODE",
+                gettext("Conversion mode of GNU dos2unix: ascii, 7bit, iso or mac, the same as \
+                 --ascii, -7, --iso and -m."),
+            )
+            .short('c')
+            .action(ArgAction::Append)
+            .value_parser(["ascii", "7bit", "iso", "mac"]),
+        )
+        .arg(
+            option(
+                "code-page",
+                "CP",
+                gettext("Use this DOS or Windows code page for --iso."),
+            )
+            .hide(true)
+            .value_parser(|value: &str| {
+                CodePage::from_number(value).ok_or(gettext("expected 437, 850, 860, 863, 865 or 1252"))
+            }),
+        )
+        .arg(
+            flag("keep-bom", gettext("Keep the Byte Order Mark (BOM)."))
+                .short('k')
+                .overrides_with_all(bom_options),
+        )
+        .arg(flag("mac", tool.mac_help()).short('m'))
+        .arg(
+            option(
+                "newline",
+                "EOL",
+                gettext("Convert to lf, crlf or cr line breaks, whatever the tool."),
+            )
+            .value_parser(|value: &str| {
+                TargetNewline::from_name(value).ok_or(gettext("expected lf, crlf or cr"))
+            }),
+        )
+        .arg(
+            // Every occurrence is kept, as where each is matters
+            Arg::new("oldfile")
+                .short('o')
+                .long("oldfile")
+                .num_args(0)
+                .default_missing_value("true")
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(bool))
+                .help(gettext("Convert the files that follow in place again after -n (the default).")),
+        )
+        .arg(
+            option(
+                "output-dir",
+                "DIR",
+                gettext("Write converted copies to the same paths below DIR, creating directories \
+                 as needed, and keep the originals."),
+            )
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            flag(
+                "to-stdout",
+                gettext("Write the conversion of each file to stdout, one after another, and keep \
+                 the originals."),
+            )
+            .short('O')
+            .conflicts_with("output-dir"),
+        )
+        .arg(
+            option(
+                "jobs",
+                "N",
+                gettext("Convert up to N files at the same time, or split a large file between N \
+                 threads (default: one per CPU)."),
+            )
+            .short('j')
+            .value_parser(|value: &str| match value.parse() {
+                Ok(jobs) if jobs > 0 => Ok::<usize, _>(jobs),
+                _ => Err(gettext("expected a positive number")),
+            }),
+        )
+        .arg(
+            flag(
+                "recursive",
+                gettext("Convert the files in directories and their subdirectories."),
+            )
+            .short('R'),
+        )
+        .arg(
+            option(
+                "max-depth",
+                "N",
+                gettext("With -R, descend at most N levels: 1 converts just the files in the \
+                 directories given."),
+            )
+            .value_parser(|value: &str| match value.parse() {
+                Ok(depth) if depth > 0 => Ok::<usize, _>(depth),
+                _ => Err(gettext("expected a positive number")),
+            }),
+        )
+        .arg(
+            option(
+                "include",
+                "GLOB",
+                gettext("With -R, only convert files matching GLOB. Repeatable."),
+            )
+            .action(ArgAction::Append),
+        )
+        .arg(
+            option(
+                "exclude",
+                "GLOB",
+                gettext("With -R, skip files and directories matching GLOB, such as 'target/' or \
+                 '.git/'. Repeatable."),
+            )
+            .action(ArgAction::Append),
+        )
+        .arg(flag(
+            "hidden",
+            gettext("With -R, also convert hidden files and the files in hidden directories."),
+        ))
+        .arg(flag(
+            "no-ignore",
+            gettext("With -R, also convert what .gitignore and .ignore files ignore, and files in \
+             .git directories."),
+        ))
+        .arg(
+            option(
+                "files-from",
+                "FILE",
+                gettext("Also convert the files listed in FILE, one per line, or on stdin if FILE \
+                 is '-'."),
+            )
+            .value_parser(value_parser!(PathBuf)),
+        )
+        .arg(
+            flag(
+                "null",
+                gettext("Entries of --files-from are separated by NUL characters, as written by \
+                 'find -print0' and 'git ls-files -z'."),
+            )
+            .short('0'),
+        )
+        .arg(flag(
+            "gitattributes",
+            gettext("Only convert files that .gitattributes marks as text, or with the eol this \
+             tool writes; skip -text and binary."),
+        ))
+        .arg(
+            option(
+                "ext",
+                "LIST",
+                gettext("Only convert files with these comma-separated extensions found in \
+                 directories, which are searched even without -R."),
+            )
+            .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("newfile")
+                .short('n')
+                .long("newfile")
+                .num_args(2)
+                .value_names(["INFILE", "OUTFILE"])
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(PathBuf))
+                .help(gettext("Write the conversion of INFILE to OUTFILE. Repeatable.")),
+        )
+        .arg(
+            flag(
+                "no-clobber",
+                gettext("Do not overwrite an existing OUTFILE of -n, or a file in the directory \
+                 of --output-dir."),
+            )
+            .conflicts_with("watch"),
+        )
+        .arg(flag(
+            "normalize",
+            gettext("Convert every line break, including lone CRs, to the target line break."),
+        ))
+        .arg(flag(
+            "auto",
+            gettext("Convert the line breaks of each file to its most common kind of line break."),
+        ))
+        .arg(flag(
+            "unicode-breaks",
+            gettext("Also convert the Unicode line breaks NEL, LS and PS."),
+        ))
+        .arg(flag(
+            "strip-cr",
+            gettext("Remove CRs that are not part of a CRLF (dos2unix only)."),
+        ))
+        .arg(flag("add-eol", gettext("Add missing end-of-line at end of file.")))
+        .arg(
+            flag(
+                "remove-bom",
+                gettext("Remove the BOM, including that of UTF-16 and UTF-32 files."),
+            )
+            .short('r')
+            .overrides_with_all(bom_options),
+        )
+        .arg(flag("quiet", gettext("Quiet mode. Suppress all messages except errors.")).short('q'))
+        .arg(flag("to-utf8", gettext("Convert UTF-16 input to UTF-8 output.")).short('u'))
+        .arg(flag("gb18030", gettext("Convert UTF-16 input to GB18030 output.")))
+        .arg(
+            option(
+                "from-encoding",
+                "ENCODING",
+                gettext("Read the input in ENCODING, such as shift_jis."),
+            )
+            .value_parser(encoding_name),
+        )
+        .arg(
+            option(
+                "to-encoding",
+                "ENCODING",
+                gettext("Write the output in ENCODING (UTF-8 by default)."),
+            )
+            .value_parser(encoding_name),
+        )
+        .arg(
+            flag(
+                "verbose",
+                gettext("Print more about each file, such as its line break statistics; -vv reports \
+                 each line break."),
+            )
+            .short('v')
+            .action(ArgAction::Count),
+        )
+        .arg(
+            flag("help", gettext("Display this help and exit."))
+                .short('h')
+                .action(ArgAction::Help),
+        )
+        .arg(flag("version", gettext("Output version information and exit.")))
+        .arg(flag(
+            "no-config",
+            gettext("Ignore configuration files."),
+        ))
+        .arg(
+            option(
+                "completions",
+                "SHELL",
+                gettext("Print a completion script for SHELL and exit."),
+            )
+            .value_parser(value_parser!(Shell)),
+        )
+        .arg(
+            Arg::new("files")
+                .value_name("FILE")
+                .help(gettext("Files to convert, where '-' is stdin to stdout. Without any, stdin is converted to stdout."))
+                .num_args(0..)
+                .action(ArgAction::Append)
+                .value_parser(value_parser!(PathBuf)),
+        )
+}
+
+/// Checks that `value` names an encoding `--from-encoding` and
+/// `--to-encoding` can handle.
+fn encoding_name(value: &str) -> Result<String, String> {
+    if transcode::is_supported(value) {
+        Ok(value.to_string())
+    } else {
+        Err(gettext("unsupported encoding").to_string())
+    }
+}
+
+/// Rewrites the single-dash options GNU dos2unix has, which clap would take
+/// for clusters of short options, into long ones: `-ascii`, `-iso`, `-gb`,
+/// the code pages such as `-850`, and `-iFLAGS`. Arguments after `--` are
+/// file names, and are left alone.
+fn legacy_args(args: impl IntoIterator<Item = OsString>) -> Vec<OsString> {
+    let mut options_ended = false;
+    args.into_iter()
+        .map(|arg| {
+            let Some(text) = arg.to_str().filter(|_| !options_ended) else {
+                return arg;
+            };
+            let long = match text {
+                "--" => {
+                    options_ended = true;
+                    return arg;
+                }
+                "-ascii" => "--ascii".to_string(),
+                "-iso" => "--iso".to_string(),
+                "-gb" => "--gb18030".to_string(),
+                _ => match text.strip_prefix('-') {
+                    Some(number) if CodePage::from_number(number).is_some() => {
+                        format!("--code-page={}", number)
+                    }
+                    Some(flags) if flags.len() > 1 && flags.starts_with('i') => {
+                        format!("--info={}", &flags[1..])
+                    }
+                    _ => return arg,
+                },
+            };
+            OsString::from(long)
+        })
+        .collect()
+}
+
+/// Prints the version of `tool`, with what the build can tell about itself:
+/// the commit it was built from, its target, and the optional features
+/// compiled in.
+fn print_version(tool: Tool) {
+    let commit = env!("BUILD_COMMIT");
+    if commit.is_empty() {
+        println!(
+            "{}",
+            tr!(
+                "{} version {}",
+                tool.version_name(),
+                env!("CARGO_PKG_VERSION")
+            )
+        );
+    } else {
+        println!(
+            "{}",
+            tr!(
+                "{} version {} ({})",
+                tool.version_name(),
+                env!("CARGO_PKG_VERSION"),
+                commit
+            )
+        );
+    }
+    println!("{}", tr!("Target: {}", env!("BUILD_TARGET")));
+    let features: Vec<&str> = [
+        ("encoding", cfg!(feature = "encoding")),
+        ("bytes", cfg!(feature = "bytes")),
+        ("tracing", cfg!(feature = "tracing")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect();
+    if features.is_empty() {
+        println!("{}", gettext("Features: none"));
+    } else {
+        println!("{}", tr!("Features: {}", features.join(", ")));
+    }
+    if !cfg!(feature = "encoding") {
+        println!(
+            "{}",
+            gettext("Without legacy character sets such as GB18030 and Shift_JIS.")
+        );
+    }
+}
+
+/// Columns selected with `--info=FLAGS`.
+#[derive(Copy, Clone)]
+struct InfoFlags {
+    dos: bool,
+    unix: bool,
+    mac: bool,
+    bom: bool,
+    text: bool,
+    convert_only: bool,
+}
+
+impl InfoFlags {
+    /// Parses the flag characters following `-i` or `--info=`. No flags
+    /// selects every column, like GNU dos2unix.
+    fn parse(flags: &str) -> Result<InfoFlags, char> {
+        let mut info = InfoFlags {
+            dos: false,
+            unix: false,
+            mac: false,
+            bom: false,
+            text: false,
+            convert_only: false,
+        };
+        for flag in flags.chars() {
+            match flag {
+                'd' => info.dos = true,
+                'u' => info.unix = true,
+                'm' => info.mac = true,
+                'b' => info.bom = true,
+                'e' | 't' => info.text = true,
+                'c' => info.convert_only = true,
+                other => return Err(other),
+            }
+        }
+        if !(info.dos || info.unix || info.mac || info.bom || info.text || info.convert_only) {
+            info.dos = true;
+            info.unix = true;
+            info.mac = true;
+            info.bom = true;
+            info.text = true;
+        }
+        Ok(info)
+    }
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    /// One JSON object per file on stdout.
+    Json,
+    /// Comma-separated `--info` records with a header row.
+    Csv,
+    /// Tab-separated `--info` records with a header row.
+    Tsv,
+    /// One tab-separated record per file on stdout, for `--porcelain`.
+    Porcelain,
+}
+
+impl OutputFormat {
+    fn parse(name: &str) -> Option<OutputFormat> {
+        match name {
+            "text" => Some(OutputFormat::Text),
+            "json" => Some(OutputFormat::Json),
+            "csv" => Some(OutputFormat::Csv),
+            "tsv" => Some(OutputFormat::Tsv),
+            _ => None,
+        }
+    }
+
+    /// Whether results are printed as one record per file on stdout, in
+    /// place of messages.
+    fn prints_records(self) -> bool {
+        matches!(self, OutputFormat::Json | OutputFormat::Porcelain)
+    }
+}
+
+/// Settings collected from the command line.
+struct Options {
+    progname: String,
+    keep_bom: bool,
+    remove_bom: bool,
+    add_bom: bool,
+    force: bool,
+    skip_binary: bool,
+    fail_fast: bool,
+    sniff_file_type: bool,
+    skip_extensions: Vec<String>,
+    binary_policy: BinaryPolicy,
+    backup: bool,
+    backup_naming: BackupNaming,
+    keep_date: bool,
+    allow_chown: bool,
+    no_clobber: bool,
+    /// Convert the targets of symbolic links instead of skipping them.
+    follow_symlinks: bool,
+    sync: bool,
+    in_place: bool,
+    force_readonly: bool,
+    force_special: bool,
+    lock: bool,
+    /// How many more times to try a locked file.
+    retries: usize,
+    retry_delay: Duration,
+    stream: bool,
+    mmap: bool,
+    buffer_size: usize,
+    recursive: bool,
+    max_depth: Option<usize>,
+    output_dir: Option<PathBuf>,
+    to_stdout: bool,
+    /// How many files to convert at the same time.
+    jobs: usize,
+    gitattributes: bool,
+    files_from: Option<PathBuf>,
+    null_separated: bool,
+    path_filter: PathFilter,
+    mac_mode: bool,
+    /// Line break of `--newline`, which overrides that of the tool.
+    newline: Option<TargetNewline>,
+    add_eol: bool,
+    to_utf8: bool,
+    gb18030: bool,
+    from_encoding: Option<String>,
+    to_encoding: Option<String>,
+    bom: Option<BomKind>,
+    char_conversion: CharConversion,
+    info: Option<InfoFlags>,
+    check: bool,
+    watch: bool,
+    check_utf8: bool,
+    strict: bool,
+    normalize: bool,
+    auto: bool,
+    unicode_breaks: bool,
+    strip_cr: bool,
+    dry_run: bool,
+    diff: bool,
+    format: OutputFormat,
+    verbose: usize,
+    quiet: bool,
+    /// Whether to ask before converting each file, for `--interactive`.
+    prompt: Cell<Prompt>,
+    /// Whether the CSV/TSV header row has been printed yet.
+    header_printed: Cell<bool>,
+    /// Exit status of the run so far; the last failure wins.
+    status: Cell<ExitStatus>,
+    /// Number of failures so far, of files and of the paths given.
+    failures: Cell<usize>,
+    /// Whether files were left alone because the run stopped early.
+    stopped: Cell<bool>,
+    /// Binary files passed over because of `--skip-binary`, for the summary.
+    skipped: RefCell<Vec<PathBuf>>,
+    /// Files and paths that failed, with the code of their error, for the
+    /// summary.
+    failed: RefCell<Vec<(PathBuf, ErrorCode)>>,
+    /// Where informational messages go, from the library as well.
+    messages: Arc<Messages>,
+    /// Whether what goes to stdout is colored.
+    color_stdout: bool,
+    /// Whether what goes to stderr is colored.
+    color_stderr: bool,
+    /// Whether to print the paths of the modified files, for `--print0`.
+    print0: bool,
+    /// Where to write the JSON report of the run.
+    report: Option<PathBuf>,
+    /// The JSON records of the files converted so far, for `--report`.
+    report_files: RefCell<Vec<String>>,
+}
+
+/// Where the informational messages of a run go, from the library as well
+/// as the front end: stderr, or stdout with `--messages-to stdout`. Each
+/// message takes the progress bar, if one is shown, off the terminal first.
+struct Messages {
+    to_stdout: bool,
+    progress: Mutex<Option<ProgressBar>>,
+}
+
+impl Messages {
+    fn print(&self, message: &str) {
+        if let Some(progress) = self.progress.lock().unwrap().as_mut() {
+            progress.clear();
+        }
+        if self.to_stdout {
+            println!("{}", message);
+        } else {
+            eprintln!("{}", message);
+        }
+    }
+}
+
+impl Reporter for Messages {
+    fn report(&self, _level: usize, message: &str) {
+        self.print(message);
+    }
+}
+
+/// Where `--interactive` is at in asking about each file.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Prompt {
+    /// Convert without asking, as without `--interactive`.
+    Never,
+    Ask,
+    /// Convert the rest without asking, after an answer of `a`.
+    All,
+    /// Leave the rest alone, after an answer of `q`.
+    Quit,
+}
+
+/// An answer to the question `--interactive` asks about a file.
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Answer {
+    Yes,
+    No,
+    /// Yes, and to the rest too.
+    All,
+    /// No, and to the rest too.
+    Quit,
+}
+
+/// Process exit statuses. The values for missing files and permission errors
+/// are the errno values GNU dos2unix exits with in those cases.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum ExitStatus {
+    Success = 0,
+    /// Invalid usage, `--check` found files to convert, or any other error.
+    Failure = 1,
+    NotFound = 2,
+    /// A binary file was left unconverted.
+    BinarySkipped = 3,
+    PermissionDenied = 13,
+}
+
+impl ExitStatus {
+    fn from_io_error(e: &io::Error) -> ExitStatus {
+        match e.kind() {
+            io::ErrorKind::NotFound => ExitStatus::NotFound,
+            io::ErrorKind::PermissionDenied => ExitStatus::PermissionDenied,
+            _ if ErrorCode::of(e) == ErrorCode::Binary => ExitStatus::BinarySkipped,
+            _ => ExitStatus::Failure,
+        }
+    }
+}
+
+impl Options {
+    /// Records a failed file in the exit status.
+    fn fail(&self, e: &io::Error) {
+        self.status.set(ExitStatus::from_io_error(e));
+        self.failures.set(self.failures.get() + 1);
+    }
+
+    /// Records that `path` failed with `e`, in the exit status and in the
+    /// list of failures after the summary.
+    fn fail_file(&self, path: &Path, e: &io::Error) {
+        self.fail(e);
+        self.failed
+            .borrow_mut()
+            .push((path.to_path_buf(), ErrorCode::of(e)));
+    }
+
+    /// Whether to start no more files: once the run is interrupted, or with
+    /// `--fail-fast` after a failure.
+    fn stopping(&self) -> bool {
+        signal::interrupted() || (self.fail_fast && self.failures.get() > 0)
+    }
+
+    /// [`stopping`](Options::stopping), called before a file, which is then
+    /// noted as left alone.
+    fn stop(&self) -> bool {
+        let stop = self.stopping();
+        if stop {
+            self.stopped.set(true);
+        }
+        stop
+    }
+
+    /// The status to exit with: that of the last failure, or once the run is
+    /// interrupted, 128 plus the number of the signal, as a shell reports a
+    /// process the signal ended.
+    fn exit_code(&self) -> i32 {
+        signal::exit_status().unwrap_or(self.status.get() as i32)
+    }
+
+    /// `text` in `style` if stdout is colored.
+    fn paint_stdout<'a>(&self, text: &'a str, style: Style) -> Cow<'a, str> {
+        paint(text, style, self.color_stdout)
+    }
+
+    /// `text` in `style` if stderr is colored.
+    fn paint_stderr<'a>(&self, text: &'a str, style: Style) -> Cow<'a, str> {
+        paint(text, style, self.color_stderr)
+    }
+
+    /// Takes the progress bar off the terminal, if one is shown, so that a
+    /// message can be printed.
+    fn clear_progress(&self) {
+        if let Some(progress) = self.messages.progress.lock().unwrap().as_mut() {
+            progress.clear();
+        }
+    }
+
+    /// Adds the `result` of converting `input_path` to `output_path`, which
+    /// took `elapsed`, to the `--report`, if one is written.
+    fn record_report(
+        &self,
+        input_path: &Path,
+        output_path: Option<&Path>,
+        result: &io::Result<ProcessedFile>,
+        elapsed: Duration,
+    ) {
+        if self.report.is_none() {
+            return;
+        }
+        let action = match file_outcome(result) {
+            FileOutcome::Converted => "converted",
+            FileOutcome::AlreadyClean => "unchanged",
+            FileOutcome::SkippedBinary | FileOutcome::SkippedLocked => "skipped",
+            FileOutcome::Error => "error",
+        };
+        let stats = result.as_ref().ok().map(|file| file.stats);
+        let output = output_path.map(|path| path.to_string_lossy());
+        let error = result.as_ref().err().map(|e| e.to_string());
+        let code = result.as_ref().err().map(ErrorCode::of);
+        let record = JsonObject::new()
+            .string("path", &input_path.to_string_lossy())
+            .opt_string("output", output.as_deref())
+            .string("action", action)
+            .opt_number("crlf", stats.map(|s| s.crlf as u64))
+            .opt_number("lf", stats.map(|s| s.lf as u64))
+            .opt_number("cr", stats.map(|s| s.cr as u64))
+            .opt_string("bom", stats.and_then(|s| s.bom).map(|bom| bom.name()))
+            .opt_string("error", error.as_deref())
+            .opt_string("code", code.map(ErrorCode::name))
+            .number("duration_us", elapsed.as_micros() as u64)
+            .finish();
+        self.report_files.borrow_mut().push(record);
+    }
+
+    /// Asks whether to convert `input_path` for `--interactive`, showing its
+    /// line breaks, BOM and whether it is text. Files that converting would
+    /// not change or would fail on, such as binary ones, are left to the
+    /// conversion without asking, as is stdin. The file is judged by a scan
+    /// a chunk at a time, so that a large one is not read into memory only
+    /// to ask about it.
+    fn confirm(&self, tool: Tool, input_path: &Path) -> bool {
+        match self.prompt.get() {
+            Prompt::Never | Prompt::All => return true,
+            Prompt::Quit => return false,
+            Prompt::Ask => {}
+        }
+        if is_stdio(input_path) {
+            return true;
+        }
+        let scanned = self
+            .open_input(input_path)
+            .and_then(|(mut file, metadata)| {
+                // Only a regular file can be read again for the conversion
+                if !metadata.is_file() {
+                    return Ok(None);
+                }
+                if let Some(scanned) = scan_file(&file, self.binary_policy)? {
+                    return Ok(Some(scanned));
+                }
+                // Converted in memory anyway
+                file.rewind()?;
+                let content = read_file(&file, self.mmap)?;
+                let binary = is_binary_with(&content, &self.binary_policy);
+                Ok(Some((detect_line_endings(&content), binary)))
+            });
+        let Ok(Some((stats, binary))) = scanned else {
+            return true;
+        };
+        if !self.may_change(tool, &stats, binary) {
+            return true;
+        }
+        let question = tr!(
+            "{}: convert '{}' ({} CRLF, {} LF, {} CR, {}, {})? [y/n/a/q] ",
+            self.progname,
+            input_path.display(),
+            stats.crlf,
+            stats.lf,
+            stats.cr,
+            match stats.bom {
+                Some(bom) => tr!("{} BOM", bom.name()),
+                None => gettext("no BOM").to_string(),
+            },
+            if binary {
+                gettext("binary")
+            } else {
+                gettext("text")
+            }
+        );
+        let answer = loop {
+            eprint!("{}", question);
+            let _ = io::stderr().flush();
+            match read_answer() {
+                Ok(Some(answer)) => match answer.trim().to_ascii_lowercase().as_str() {
+                    "y" | "yes" => break Answer::Yes,
+                    "" | "n" | "no" => break Answer::No,
+                    "a" | "all" => break Answer::All,
+                    "q" | "quit" => break Answer::Quit,
+                    _ => {}
+                },
+                // The end of input leaves the rest alone, as q does, and so
+                // does Ctrl-C
+                Ok(None) => {
+                    eprintln!();
+                    break Answer::Quit;
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {
+                    eprintln!();
+                    break Answer::Quit;
+                }
+                Err(e) => {
+                    eprintln!(
+                        "{}",
+                        tr!(
+                            "{}: cannot read an answer from the terminal: {}",
+                            self.progname,
+                            e
+                        )
+                    );
+                    self.status.set(ExitStatus::Failure);
+                    break Answer::Quit;
+                }
+            }
+        };
+        match answer {
+            Answer::All => self.prompt.set(Prompt::All),
+            Answer::Quit => self.prompt.set(Prompt::Quit),
+            Answer::Yes | Answer::No => {}
+        }
+        let convert = matches!(answer, Answer::Yes | Answer::All);
+        if !convert && self.format.prints_records() {
+            print_record(
+                self,
+                Some(input_path),
+                Some(&stats),
+                Some(binary),
+                "skipped",
+                None,
+            );
+        }
+        convert
+    }
+
+    /// Whether converting a file with the line breaks `stats` with `tool`
+    /// may change it, where `binary` tells whether it looks binary. A
+    /// binary file is only converted with `--force`. Options that do more
+    /// than convert line breaks, or add a line break at the end, may change
+    /// any file, which is then asked about too.
+    fn may_change(&self, tool: Tool, stats: &LineEndingStats, binary: bool) -> bool {
+        if binary && !self.force {
+            return false;
+        }
+        let options = self.conversion(tool);
+        would_convert(tool, self, stats, binary)
+            || !is_plain(&options)
+            || options.add_eol
+            || (options.strip_cr && stats.cr > 0)
+            || options.writes_utf8_bom(stats.bom.is_some()) != stats.bom.is_some()
+    }
+
+    /// Opens `path` for reading, failing for a FIFO, device or socket
+    /// unless `--force-special` is given.
+    fn open_input(&self, path: &Path) -> io::Result<(File, Metadata)> {
+        open_input(path, path, self.force_special, false, false)
+    }
+
+    /// The paths to convert `input_path` to `output_path` with, where the
+    /// file the conversion replaces, `output_path` or else `input_path`
+    /// itself, may be a symbolic link. Replacing a link would turn it into a
+    /// regular file, so with `-F` the conversion goes to the file the link
+    /// points to, and otherwise the link is skipped with a notice and `None`
+    /// is returned.
+    fn resolve_symlink(
+        &self,
+        input_path: &Path,
+        output_path: Option<&Path>,
+    ) -> Option<(PathBuf, Option<PathBuf>)> {
+        let replaced = output_path.unwrap_or(input_path);
+        let is_symlink =
+            fs::symlink_metadata(replaced).is_ok_and(|metadata| metadata.file_type().is_symlink());
+        if is_stdio(input_path) || is_stdio(replaced) || !is_symlink {
+            return Some((input_path.to_path_buf(), output_path.map(Path::to_path_buf)));
+        }
+        if self.follow_symlinks {
+            let target = link_target(replaced);
+            return Some(match output_path {
+                Some(_) => (input_path.to_path_buf(), Some(target)),
+                None => (target, None),
+            });
+        }
+        if self.format.prints_records() {
+            print_record(self, Some(replaced), None, None, "skipped", None);
+        } else if !self.quiet {
+            let message = tr!(
+                "{}: Skipping symbolic link '{}'",
+                self.progname,
+                replaced.display()
+            );
+            self.inform(&self.paint_messages(&message, Style::Yellow));
+        }
+        None
+    }
+
+    /// Shows `input_path` on the progress bar, if one is shown, as the file
+    /// being converted.
+    fn start_progress(&self, input_path: &Path) {
+        if let Some(progress) = self.messages.progress.lock().unwrap().as_mut() {
+            progress.start_file(input_path);
+        }
+    }
+
+    /// Counts the file at `index` of the targets as done on the progress
+    /// bar, if one is shown.
+    fn finish_progress(&self, index: usize) {
+        if let Some(progress) = self.messages.progress.lock().unwrap().as_mut() {
+            progress.finish_file(index);
+        }
+    }
+
+    /// Library settings for converting with `tool`.
+    fn conversion(&self, tool: Tool) -> ConversionOptions {
+        ConversionOptions {
+            keep_bom: self.keep_bom,
+            remove_bom: self.remove_bom,
+            add_bom: self.add_bom,
+            force: self.force,
+            binary_policy: self.binary_policy,
+            sniff_file_type: self.sniff_file_type,
+            skip_extensions: self.skip_extensions.clone(),
+            backup: self.backup,
+            backup_naming: self.backup_naming.clone(),
+            keep_date: self.keep_date,
+            allow_chown: self.allow_chown,
+            no_clobber: self.no_clobber,
+            sync: self.sync,
+            in_place: self.in_place,
+            force_readonly: self.force_readonly,
+            force_special: self.force_special,
+            lock: self.lock,
+            retries: self.retries,
+            retry_delay: self.retry_delay,
+            stream: self.stream,
+            mmap: self.mmap,
+            buffer_size: self.buffer_size,
+            threads: self.jobs,
+            add_eol: self.add_eol,
+            to_utf8: self.to_utf8,
+            gb18030: self.gb18030,
+            from_encoding: self.from_encoding.clone(),
+            to_encoding: self.to_encoding.clone(),
+            check_utf8: self.check_utf8,
+            strict: self.strict,
+            normalize: self.normalize,
+            auto: self.auto,
+            unicode_breaks: self.unicode_breaks,
+            strip_cr: self.strip_cr,
+            bom: self.bom,
+            char_conversion: self.char_conversion,
+            verbose: self.verbosity(),
+            progname: self.progname.clone(),
+            reporter: self.messages.clone(),
+            ..ConversionOptions::new(self.conversion_mode(tool))
+        }
+    }
+
+    /// Library settings for converting with `tool` to what may be stdout,
+    /// which messages then keep out of.
+    fn pipe_conversion(&self, tool: Tool) -> ConversionOptions {
+        ConversionOptions {
+            reporter: Arc::new(StderrReporter),
+            ..self.conversion(tool)
+        }
+    }
+
+    /// Number of line breaks in a file with `stats` that converting with
+    /// `tool` rewrites.
+    fn conversions(&self, tool: Tool, stats: &LineEndingStats) -> usize {
+        let unicode = if self.unicode_breaks {
+            stats.unicode
+        } else {
+            0
+        };
+        if self.auto {
+            return unicode
+                + stats
+                    .dominant_mode()
+                    .map_or(0, |conversion_mode| stats.normalizations(conversion_mode));
+        }
+        let conversion_mode = self.conversion_mode(tool);
+        unicode
+            + if self.normalize {
+                stats.normalizations(conversion_mode)
+            } else {
+                stats.conversions(conversion_mode)
+            }
+    }
+
+    /// The conversion `tool` does: to the line break of `--newline`, or to its
+    /// own.
+    fn conversion_mode(&self, tool: Tool) -> ConversionMode {
+        match self.newline {
+            Some(newline) => newline.conversion_mode(),
+            None => tool.conversion_mode(self.mac_mode),
+        }
+    }
+
+    /// Verbosity passed to the library: 1 by default, for a message for each
+    /// file, and one more for each `-v`. `--quiet` overrides any `-v`.
+    fn verbosity(&self) -> usize {
+        if self.quiet {
+            0
+        } else {
+            self.verbose + 1
+        }
+    }
+
+    /// Prints the informational `message` where `--messages-to` sends it.
+    fn inform(&self, message: &str) {
+        self.messages.print(message);
+    }
+
+    /// `text` in `style` if where `--messages-to` sends messages is colored.
+    fn paint_messages<'a>(&self, text: &'a str, style: Style) -> Cow<'a, str> {
+        let color = if self.messages.to_stdout {
+            self.color_stdout
+        } else {
+            self.color_stderr
+        };
+        paint(text, style, color)
+    }
+}
+
+/// Prints one line of `--info` output in the same column layout as GNU
+/// dos2unix: DOS, Unix and Mac line break counts, BOM, text/binary and name.
+fn print_info(
+    opts: &Options,
+    flags: InfoFlags,
+    stats: &LineEndingStats,
+    binary: bool,
+    would_convert: bool,
+    name: Option<&Path>,
+) {
+    let mut line = String::new();
+    for (selected, count) in [
+        (flags.dos, stats.crlf),
+        (flags.unix, stats.lf),
+        (flags.mac, stats.cr),
+    ] {
+        if selected {
+            let style = if count > 0 { Style::Bold } else { Style::Dim };
+            line.push_str(&opts.paint_stdout(&format!("{:8}", count), style));
+        }
+    }
+    if flags.bom {
+        let bom = format!("{:<8}", stats.bom.map_or("no_bom", |bom| bom.name()));
+        let bom = match stats.bom {
+            Some(_) => opts.paint_stdout(&bom, Style::Cyan),
+            None => Cow::Owned(bom),
+        };
+        line.push_str(&format!("  {}", bom));
+    }
+    if flags.text {
+        let text = if binary {
+            opts.paint_stdout("binary", Style::Yellow)
+        } else {
+            Cow::Borrowed("text  ")
+        };
+        line.push_str(&format!("  {}", text));
+    }
+    // The files that converting would change stand out
+    let name = name.map(|name| {
+        let name = name.to_string_lossy();
+        if would_convert {
+            opts.paint_stdout(&name, Style::Green).into_owned()
+        } else {
+            name.into_owned()
+        }
+    });
+    match name {
+        Some(name) if line.is_empty() => println!("{}", name),
+        Some(name) => println!("{}  {}", line, name),
+        None => println!("{}", line.trim_end()),
+    }
+}
+
+/// Prints the record of one file for `--format json` or `--porcelain`.
+/// `stats` and `binary` are `None` when the file could not be read.
+fn print_record(
+    opts: &Options,
+    name: Option<&Path>,
+    stats: Option<&LineEndingStats>,
+    binary: Option<bool>,
+    action: &str,
+    error: Option<(ErrorCode, &str)>,
+) {
+    match opts.format {
+        OutputFormat::Porcelain => print_porcelain_record(name, stats, binary, action, error),
+        _ => print_json_record(name, stats, binary, action, error),
+    }
+}
+
+/// Prints a `--porcelain` record: the fields of [`print_json_record`] in the
+/// same order, separated by tabs, with `-` for a missing value. The path is
+/// `-` for stdin too.
+fn print_porcelain_record(
+    name: Option<&Path>,
+    stats: Option<&LineEndingStats>,
+    binary: Option<bool>,
+    action: &str,
+    error: Option<(ErrorCode, &str)>,
+) {
+    let count = |count: Option<usize>| count.map_or(String::from("-"), |n| n.to_string());
+    let path = name.map_or(Cow::Borrowed("-"), |name| name.to_string_lossy());
+    let record = [
+        tsv_field(&path).into_owned(),
+        count(stats.map(|s| s.crlf)),
+        count(stats.map(|s| s.lf)),
+        count(stats.map(|s| s.cr)),
+        stats
+            .map_or("-", |s| s.bom.map_or("no_bom", |bom| bom.name()))
+            .to_string(),
+        match binary {
+            Some(true) => "binary",
+            Some(false) => "text",
+            None => "-",
+        }
+        .to_string(),
+        action.to_string(),
+        error
+            .map_or(Cow::Borrowed("-"), |(_, message)| tsv_field(message))
+            .into_owned(),
+        error.map_or("-", |(code, _)| code.name()).to_string(),
+    ];
+    println!("{}", record.join("\t"));
+}
+
+/// Prints a `--format json` record for one file. `stats` and `binary` are
+/// `None` when the file could not be read.
+fn print_json_record(
+    name: Option<&Path>,
+    stats: Option<&LineEndingStats>,
+    binary: Option<bool>,
+    action: &str,
+    error: Option<(ErrorCode, &str)>,
+) {
+    let path = name.map(|name| name.to_string_lossy());
+    let record = JsonObject::new()
+        .opt_string("path", path.as_deref())
+        .opt_number("crlf", stats.map(|s| s.crlf as u64))
+        .opt_number("lf", stats.map(|s| s.lf as u64))
+        .opt_number("cr", stats.map(|s| s.cr as u64))
+        .opt_string("bom", stats.and_then(|s| s.bom).map(|bom| bom.name()))
+        .opt_bool("binary", binary)
+        .string("action", action)
+        .opt_string("error", error.map(|(_, message)| message))
+        .opt_string("code", error.map(|(code, _)| code.name()))
+        .finish();
+    println!("{}", record);
+}
+
+/// `message` about an error followed by its `code` in brackets, as in
+/// `dos2unix: Error reading 'a.txt': Permission denied (os error 13) [E_PERM]`,
+/// so that the code can be found whatever the language of the message.
+fn with_code(message: String, code: ErrorCode) -> String {
+    format!("{} [{}]", message, code)
+}
+
+/// Quotes a CSV field if it contains a separator, quote or line break.
+fn csv_field(value: &str) -> Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", value.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Escapes the characters TSV fields cannot contain with backslash sequences.
+fn tsv_field(value: &str) -> Cow<'_, str> {
+    if value.contains(['\\', '\t', '\n', '\r']) {
+        Cow::Owned(
+            value
+                .replace('\\', "\\\\")
+                .replace('\t', "\\t")
+                .replace('\n', "\\n")
+                .replace('\r', "\\r"),
+        )
+    } else {
+        Cow::Borrowed(value)
+    }
+}
+
+/// Prints a CSV or TSV `--info` record, preceded by the header row the
+/// first time.
+fn print_delimited_record(
+    opts: &Options,
+    stats: &LineEndingStats,
+    binary: bool,
+    name: Option<&Path>,
+) {
+    let (separator, field): (&str, fn(&str) -> Cow<'_, str>) = match opts.format {
+        OutputFormat::Tsv => ("\t", tsv_field),
+        _ => (",", csv_field),
+    };
+    if !opts.header_printed.replace(true) {
+        println!(
+            "{}",
+            ["path", "crlf", "lf", "cr", "bom", "type"].join(separator)
+        );
+    }
+    let path = name.map_or(Cow::Borrowed(""), |name| name.to_string_lossy());
+    let record = [
+        field(&path).into_owned(),
+        stats.crlf.to_string(),
+        stats.lf.to_string(),
+        stats.cr.to_string(),
+        stats.bom.map_or("no_bom", |bom| bom.name()).to_string(),
+        if binary { "binary" } else { "text" }.to_string(),
+    ];
+    println!("{}", record.join(separator));
+}
+
+/// Whether converting `content` would change it. Binary files are only
+/// converted with `--force`.
+fn would_convert(tool: Tool, opts: &Options, stats: &LineEndingStats, binary: bool) -> bool {
+    opts.conversions(tool, stats) > 0 && (opts.force || !binary)
+}
+
+/// Reports on `content` for `--info`. With the `c` flag, files that would
+/// not be converted are left out.
+fn info_content(tool: Tool, opts: &Options, content: &[u8], name: Option<&Path>) {
+    let flags = opts.info.unwrap();
+    let stats = detect_line_endings(content);
+    let binary = is_binary_with(content, &opts.binary_policy);
+    let would_convert = would_convert(tool, opts, &stats, binary);
+    if flags.convert_only && !would_convert {
+        return;
+    }
+    match opts.format {
+        OutputFormat::Text => print_info(opts, flags, &stats, binary, would_convert, name),
+        OutputFormat::Json | OutputFormat::Porcelain => {
+            print_record(opts, name, Some(&stats), Some(binary), "none", None)
+        }
+        OutputFormat::Csv | OutputFormat::Tsv => print_delimited_record(opts, &stats, binary, name),
+    }
+}
+
+/// Lists `content` for `--check` if its line endings do not match the
+/// target, and records the failure. Content that is not valid UTF-8 under
+/// `--check-utf8`, or has mixed line endings under `--strict`, is reported
+/// and fails the check too.
+fn check_content(tool: Tool, opts: &Options, content: &[u8], name: Option<&Path>) {
+    let stats = detect_line_endings(content);
+    let binary = is_binary_with(content, &opts.binary_policy);
+    let needs_conversion = would_convert(tool, opts, &stats, binary);
+    let mut problems = Vec::new();
+    if opts.check_utf8 {
+        if let Err(invalid) = check_utf8(content) {
+            problems.push((ErrorCode::NotUtf8, invalid.to_string()));
+        }
+    }
+    if let (true, Some(line)) = (opts.strict, stats.first_mixed_line) {
+        problems.push((
+            ErrorCode::MixedLineEndings,
+            mixed_line_endings_message(line),
+        ));
+    }
+    if needs_conversion || !problems.is_empty() {
+        opts.status.set(ExitStatus::Failure);
+    }
+    if !opts.format.prints_records() {
+        for (code, problem) in &problems {
+            eprintln!(
+                "{}: {}: {} [{}]",
+                opts.progname,
+                name.map_or(Cow::Borrowed("-"), |name| name.to_string_lossy()),
+                problem,
+                code
+            );
+        }
+    }
+    match opts.format {
+        OutputFormat::Json | OutputFormat::Porcelain => {
+            let action = if needs_conversion {
+                "needs_conversion"
+            } else {
+                "clean"
+            };
+            // The code is that of the first problem, the message that of all
+            let messages: Vec<&str> = problems.iter().map(|(_, problem)| &problem[..]).collect();
+            let error = messages.join("; ");
+            print_record(
+                opts,
+                name,
+                Some(&stats),
+                Some(binary),
+                action,
+                problems.first().map(|&(code, _)| (code, &error[..])),
+            )
+        }
+        _ if needs_conversion => {
+            let name = name.map_or(Cow::Borrowed("-"), |name| name.to_string_lossy());
+            println!("{}", opts.paint_stdout(&name, Style::Yellow));
+        }
+        _ => {}
+    }
+}
+
+/// The file the symbolic link `link` points to, following any links it
+/// leads to in turn. After as many links as the operating system follows,
+/// the last one is returned, which fails to open as a loop.
+fn link_target(link: &Path) -> PathBuf {
+    let mut path = link.to_path_buf();
+    for _ in 0..40 {
+        let Ok(target) = fs::read_link(&path) else {
+            break;
+        };
+        path = match path.parent() {
+            Some(parent) => parent.join(target),
+            None => target,
+        };
+    }
+    path
+}
+
+/// Where `--output-dir` puts the converted copy of `path`: at the same
+/// relative path below `dir`. A root or drive prefix and `.` components are
+/// dropped, so `/src/a.c` and `./src/a.c` both end up at `dir/src/a.c`, and a
+/// `..` component is refused. If `create`, the directories leading to the
+/// copy are created.
+fn mirror_path(dir: &Path, path: &Path, create: bool) -> io::Result<PathBuf> {
+    let mut mirrored = dir.to_path_buf();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => mirrored.push(name),
+            Component::ParentDir => {
+                return Err(coded(
+                    io::ErrorKind::InvalidInput,
+                    ErrorCode::Usage,
+                    gettext("a path with '..' cannot be mirrored into the output directory").into(),
+                ))
+            }
+            Component::Prefix(_) | Component::RootDir | Component::CurDir => {}
+        }
+    }
+    if create {
+        if let Some(parent) = mirrored.parent() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    Ok(mirrored)
+}
+
+/// Reads the paths listed in the file `list`, or on stdin if it is `-`, for
+/// `--files-from`. Paths are separated by newlines, or by NULs if
+/// `null_separated`, as written by `find -print0` and `git ls-files -z`.
+/// Each path is taken literally, without expanding wildcards.
+fn read_file_list(list: &Path, null_separated: bool) -> io::Result<Vec<PathBuf>> {
+    let content = if is_stdio(list) {
+        let mut content = Vec::new();
+        io::stdin().read_to_end(&mut content)?;
+        content
+    } else {
+        fs::read(list)?
+    };
+    let separator = if null_separated { b'\0' } else { b'\n' };
+    Ok(content
+        .split(|&b| b == separator)
+        .map(|entry| {
+            if null_separated {
+                entry
+            } else {
+                entry.strip_suffix(b"\r").unwrap_or(entry)
+            }
+        })
+        .filter(|entry| !entry.is_empty())
+        .map(path_from_bytes)
+        .collect())
+}
+
+#[cfg(unix)]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    use std::os::unix::ffi::OsStrExt;
+    PathBuf::from(std::ffi::OsStr::from_bytes(bytes))
+}
+
+#[cfg(not(unix))]
+fn path_from_bytes(bytes: &[u8]) -> PathBuf {
+    PathBuf::from(String::from_utf8_lossy(bytes).into_owned())
+}
+
+#[cfg(unix)]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    use std::os::unix::ffi::OsStrExt;
+    Cow::Borrowed(path.as_os_str().as_bytes())
+}
+
+#[cfg(not(unix))]
+fn path_bytes(path: &Path) -> Cow<'_, [u8]> {
+    match path.to_string_lossy() {
+        Cow::Borrowed(path) => Cow::Borrowed(path.as_bytes()),
+        Cow::Owned(path) => Cow::Owned(path.into_bytes()),
+    }
+}
+
+/// Replaces each directory in `paths` with the files in it, for `--ext`, or
+/// below it, for `--recursive`. Directories that cannot be read are reported
+/// and count as failures, without stopping the others.
+fn expand_directories(opts: &Options, paths: Vec<PathBuf>, summary: &mut Summary) -> Vec<PathBuf> {
+    let mut files = Vec::new();
+    for path in paths {
+        if !path.is_dir() {
+            files.push(path);
+            continue;
+        }
+        walk_files(
+            &path,
+            &opts.path_filter,
+            if opts.recursive {
+                opts.max_depth
+            } else {
+                Some(1)
+            },
+            &mut |file| files.push(file.to_path_buf()),
+            &mut |failed, e| {
+                opts.fail_file(failed, &e);
+                summary.record(FileOutcome::Error);
+                match opts.format {
+                    OutputFormat::Json | OutputFormat::Porcelain => print_record(
+                        opts,
+                        Some(failed),
+                        None,
+                        None,
+                        "error",
+                        Some((ErrorCode::of(&e), &e.to_string())),
+                    ),
+                    _ => eprintln!(
+                        "{}",
+                        opts.paint_stderr(
+                            &with_code(
+                                tr!(
+                                    "{}: Error reading '{}': {}",
+                                    opts.progname,
+                                    failed.display(),
+                                    e
+                                ),
+                                ErrorCode::of(&e)
+                            ),
+                            Style::Red
+                        )
+                    ),
+                }
+                opts.record_report(failed, None, &Err(e), Duration::ZERO);
+            },
+        );
+    }
+    files
+}
+
+/// Keeps the files of `paths` whose line endings git would normalize to those
+/// `tool` writes, for `--gitattributes`.
+fn filter_by_gitattributes(tool: Tool, opts: &Options, paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    let eol = match opts.conversion_mode(tool) {
+        ConversionMode::ToUnix => "lf",
+        ConversionMode::ToDos => "crlf",
+        ConversionMode::ToMac => "cr",
+    };
+    let mut attributes = GitAttributes::default();
+    paths
+        .into_iter()
+        .filter(|path| {
+            let wanted = is_stdio(path) || attributes.lookup(path).wants(eol);
+            if !wanted && opts.verbose > 0 && !opts.quiet {
+                opts.inform(&tr!(
+                    "{}: skipping '{}', not {} text according to .gitattributes",
+                    opts.progname,
+                    path.display(),
+                    eol.to_uppercase()
+                ));
+            }
+            wanted
+        })
+        .collect()
+}
+
+/// Reads a line typed on the terminal, which stdin may not be, or `None` at
+/// the end of input. Fails once the run is interrupted, rather than waiting
+/// for the line.
+fn read_answer() -> io::Result<Option<String>> {
+    let terminal = if cfg!(windows) { "CONIN$" } else { "/dev/tty" };
+    let mut terminal = File::open(terminal)?;
+    let mut answer = Vec::new();
+    let mut byte = [0];
+    loop {
+        match terminal.read(&mut byte) {
+            Ok(0) if answer.is_empty() => return Ok(None),
+            Ok(0) => break,
+            Ok(_) => {
+                answer.push(byte[0]);
+                if byte[0] == b'\n' {
+                    break;
+                }
+            }
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => signal::check()?,
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some(String::from_utf8_lossy(&answer).into_owned()))
+}
+
+/// Whether `path` is `-`, which names stdin as an input and stdout as an
+/// output.
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == "-"
+}
+
+/// Reads `path` whole, or all of stdin for `-`. A FIFO, device or socket is
+/// refused unless `--force-special` is given.
+fn read_input(opts: &Options, path: &Path) -> io::Result<FileContent> {
+    if !is_stdio(path) {
+        let (file, _) = opts.open_input(path)?;
+        return read_file(&file, opts.mmap);
+    }
+    let mut content = Vec::new();
+    io::stdin().read_to_end(&mut content)?;
+    Ok(FileContent::Read(content))
+}
+
+/// Reads `path` for one of the modes that only inspect files.
+fn inspect_file(tool: Tool, opts: &Options, path: &Path) {
+    match read_input(opts, path) {
+        Ok(content) if opts.check => check_content(tool, opts, &content, Some(path)),
+        Ok(content) => info_content(tool, opts, &content, Some(path)),
+        Err(e) => {
+            opts.fail(&e);
+            match opts.format {
+                OutputFormat::Json | OutputFormat::Porcelain => print_record(
+                    opts,
+                    Some(path),
+                    None,
+                    None,
+                    "error",
+                    Some((ErrorCode::of(&e), &e.to_string())),
+                ),
+                _ => eprintln!(
+                    "{}",
+                    opts.paint_stderr(
+                        &with_code(
+                            tr!(
+                                "{}: Error reading '{}': {}",
+                                opts.progname,
+                                path.display(),
+                                e
+                            ),
+                            ErrorCode::of(&e)
+                        ),
+                        Style::Red
+                    )
+                ),
+            }
+        }
+    }
+}
+
+fn convert_file(
+    tool: Tool,
+    opts: &Options,
+    input_path: &Path,
+    output_path: Option<&Path>,
+) -> FileOutcome {
+    if is_stdio(input_path) || output_path.is_some_and(is_stdio) {
+        return convert_stdio(tool, opts, input_path, output_path);
+    }
+    let started = Instant::now();
+    let result = process_file_with_options(input_path, output_path, &opts.conversion(tool));
+    let elapsed = started.elapsed();
+    report_conversion(tool, opts, input_path, output_path, result, elapsed)
+}
+
+/// Converts `input_path` to `output_path` as a filter would, where either
+/// may be `-` for stdin or stdout. Without an output path the conversion
+/// goes to stdout, as a file cannot be replaced by reading from stdin.
+fn convert_stdio(
+    tool: Tool,
+    opts: &Options,
+    input_path: &Path,
+    output_path: Option<&Path>,
+) -> FileOutcome {
+    let started = Instant::now();
+    let result = (|| {
+        let input: Box<dyn Read + Send> = if is_stdio(input_path) {
+            Box::new(io::stdin())
+        } else {
+            Box::new(opts.open_input(input_path)?.0)
+        };
+        let output: Box<dyn Write + Send> = match output_path {
+            Some(path) if !is_stdio(path) => Box::new(
+                File::options()
+                    .write(true)
+                    .create(true)
+                    .truncate(true)
+                    .create_new(opts.no_clobber)
+                    .open(path)?,
+            ),
+            _ => Box::new(io::stdout()),
+        };
+        convert_pipe(input, output, &opts.pipe_conversion(tool))
+    })();
+    let elapsed = started.elapsed();
+    report_conversion(tool, opts, input_path, output_path, result, elapsed)
+}
+
+/// A file to convert, in the order of the command line.
+enum Target {
+    /// A file and the path its conversion goes to, if not the file itself.
+    File(PathBuf, Option<PathBuf>),
+    /// A file given in new-file mode without an output file, which fails
+    /// when its turn comes.
+    Unpaired(PathBuf),
+}
+
+impl Target {
+    /// The file converted.
+    fn input_path(&self) -> &Path {
+        match self {
+            Target::File(input_path, _) | Target::Unpaired(input_path) => input_path,
+        }
+    }
+}
+
+/// Reports that `infile` was given in new-file mode without an output file.
+fn unpaired_file(opts: &Options, infile: &Path) -> FileOutcome {
+    eprintln!(
+        "{}",
+        with_code(
+            tr!(
+                "{}: No output file given for '{}' in new-file mode",
+                opts.progname,
+                infile.display()
+            ),
+            ErrorCode::Usage
+        )
+    );
+    let e = coded(
+        io::ErrorKind::InvalidInput,
+        ErrorCode::Usage,
+        gettext("no output file given").into(),
+    );
+    opts.fail_file(infile, &e);
+    opts.record_report(infile, None, &Err(e), Duration::ZERO);
+    FileOutcome::Error
+}
+
+/// The targets for `files` given in old-file mode: with `-R` or
+/// `--extension`, the files in the directories among them, less those
+/// `.gitattributes` leaves out, each converted in place or to its copy
+/// below `--output-dir`.
+fn in_place_targets(
+    tool: Tool,
+    opts: &Options,
+    files: Vec<PathBuf>,
+    summary: &mut Summary,
+) -> Vec<Target> {
+    let mut files = files;
+    if opts.recursive || !opts.path_filter.extensions.is_empty() {
+        files = expand_directories(opts, files, summary);
+    }
+    if opts.gitattributes {
+        files = filter_by_gitattributes(tool, opts, files);
+    }
+    // Output directories are only created when something is written
+    let writes = !opts.diff && !opts.dry_run && !opts.check && opts.info.is_none();
+    files
+        .into_iter()
+        .filter_map(|path| match &opts.output_dir {
+            _ if opts.check || opts.info.is_some() => Some(Target::File(path, None)),
+            None if opts.to_stdout => Some(Target::File(path, Some(PathBuf::from("-")))),
+            None => Some(Target::File(path, None)),
+            // Stdin has no place in the tree, so it still goes to stdout
+            Some(_) if is_stdio(&path) => Some(Target::File(path, None)),
+            Some(dir) => match mirror_path(dir, &path, writes) {
+                Ok(output_path) => Some(Target::File(path, Some(output_path))),
+                Err(e) => {
+                    eprintln!(
+                        "{}",
+                        with_code(
+                            tr!(
+                                "{}: Error converting '{}': {}",
+                                opts.progname,
+                                path.display(),
+                                e
+                            ),
+                            ErrorCode::of(&e)
+                        )
+                    );
+                    opts.fail_file(&path, &e);
+                    opts.record_report(&path, None, &Err(e), Duration::ZERO);
+                    summary.record(FileOutcome::Error);
+                    None
+                }
+            },
+        })
+        .collect()
+}
+
+/// Converts `targets` on up to `opts.jobs` threads. Each file is still reported and recorded in
+/// `summary` in the order given, as soon as it and every file before it are
+/// done. Stdin and stdout are left to this thread, to keep the output of
+/// each file in one piece.
+///
+/// A long run on a terminal shows a progress bar, unless the output goes to
+/// stdout, messages are asked for or against, or `--interactive` asks about
+/// each file.
+fn convert_files(tool: Tool, opts: &Options, targets: &[Target], summary: &mut Summary) {
+    let stdio = targets.iter().any(|target| match target {
+        Target::File(input_path, output_path) => {
+            is_stdio(input_path) || output_path.as_deref().is_some_and(is_stdio)
+        }
+        Target::Unpaired(_) => false,
+    });
+    let asks = opts.prompt.get() != Prompt::Never;
+    if !stdio && !asks && !opts.quiet && opts.format == OutputFormat::Text {
+        *opts.messages.progress.lock().unwrap() =
+            ProgressBar::for_files(targets.iter().map(Target::input_path));
+    }
+    convert_targets(tool, opts, targets, summary);
+    opts.messages.progress.lock().unwrap().take();
+}
+
+fn convert_targets(tool: Tool, opts: &Options, targets: &[Target], summary: &mut Summary) {
+    // Questions are asked about one file at a time
+    let jobs = match opts.prompt.get() {
+        Prompt::Never => opts.jobs.min(targets.len()),
+        _ => 1,
+    };
+    if jobs <= 1 {
+        for (index, target) in targets.iter().enumerate() {
+            if opts.stop() {
+                break;
+            }
+            let (input_path, output_path) = match target {
+                Target::File(input_path, output_path) => (input_path, output_path),
+                Target::Unpaired(infile) => {
+                    summary.record(unpaired_file(opts, infile));
+                    opts.finish_progress(index);
+                    continue;
+                }
+            };
+            if !opts.confirm(tool, input_path) {
+                continue;
+            }
+            opts.start_progress(input_path);
+            summary.record(convert_file(tool, opts, input_path, output_path.as_deref()));
+            opts.finish_progress(index);
+        }
+        return;
+    }
+    // The threads left over go to splitting up large files
+    let conversion = ConversionOptions {
+        threads: opts.jobs / jobs,
+        ..opts.conversion(tool)
+    };
+    let next = AtomicUsize::new(0);
+    // Set once a file failed with --fail-fast. A file taken is always
+    // converted and reported, so that none is left out of the order.
+    let stop = AtomicBool::new(false);
+    let (sender, receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        for _ in 0..jobs {
+            let sender = sender.clone();
+            let (conversion, next, stop) = (&conversion, &next, &stop);
+            scope.spawn(move || loop {
+                if signal::interrupted() || stop.load(Ordering::Relaxed) {
+                    break;
+                }
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(target) = targets.get(index) else {
+                    break;
+                };
+                // Unpaired files are reported by this thread too
+                let Target::File(input_path, output_path) = target else {
+                    if sender.send((index, None)).is_err() {
+                        break;
+                    }
+                    continue;
+                };
+                let stdio = is_stdio(input_path) || output_path.as_deref().is_some_and(is_stdio);
+                let result = (!stdio).then(|| {
+                    let started = Instant::now();
+                    let result =
+                        process_file_with_options(input_path, output_path.as_deref(), conversion);
+                    (result, started.elapsed())
+                });
+                if sender.send((index, result)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(sender);
+        if let Some(target) = targets.first() {
+            opts.start_progress(target.input_path());
+        }
+        // Results that arrived ahead of an earlier file still being converted
+        let mut pending = BTreeMap::new();
+        let mut reported = 0;
+        for (index, result) in receiver {
+            pending.insert(index, result);
+            while let Some(result) = pending.remove(&reported) {
+                summary.record(match (&targets[reported], result) {
+                    (Target::File(input_path, output_path), Some((result, elapsed))) => {
+                        report_conversion(
+                            tool,
+                            opts,
+                            input_path,
+                            output_path.as_deref(),
+                            result,
+                            elapsed,
+                        )
+                    }
+                    (Target::File(input_path, output_path), None) => {
+                        convert_stdio(tool, opts, input_path, output_path.as_deref())
+                    }
+                    (Target::Unpaired(infile), _) => unpaired_file(opts, infile),
+                });
+                opts.finish_progress(reported);
+                reported += 1;
+                if opts.stopping() {
+                    stop.store(true, Ordering::Relaxed);
+                }
+                if let Some(target) = targets.get(reported) {
+                    opts.start_progress(target.input_path());
+                }
+            }
+        }
+    });
+    if next.load(Ordering::Relaxed) < targets.len() {
+        opts.stopped.set(true);
+    }
+}
+
+/// Converts each file of `targets` again whenever its modification time or
+/// size changes, for `--watch`, checking every [`WATCH_INTERVAL`]. Does not
+/// return while there is a file to watch, until the run is interrupted or,
+/// with `--fail-fast`, a conversion fails.
+fn watch_files(tool: Tool, opts: &Options, targets: &[Target]) {
+    let stamp = |path: &Path| {
+        fs::metadata(path)
+            .and_then(|metadata| Ok((metadata.modified()?, metadata.len())))
+            .ok()
+    };
+    let mut watched: Vec<_> = targets
+        .iter()
+        .filter_map(|target| match target {
+            Target::File(input_path, output_path) if !is_stdio(input_path) => {
+                Some((input_path, output_path, stamp(input_path)))
+            }
+            _ => None,
+        })
+        .collect();
+    if watched.is_empty() {
+        return;
+    }
+    while !opts.stopping() {
+        thread::sleep(WATCH_INTERVAL);
+        for (input_path, output_path, seen) in &mut watched {
+            if opts.stopping() {
+                break;
+            }
+            let current = stamp(input_path);
+            // A file being replaced may be missing for a moment
+            if current.is_none() || current == *seen {
+                continue;
+            }
+            convert_file(tool, opts, input_path, output_path.as_deref());
+            // Converting in place changes the stamp, which is no change to
+            // convert again
+            *seen = stamp(input_path);
+        }
+    }
+}
+
+/// Prints `path` followed by a NUL, for `--print0`. The path is written as
+/// it is, even if it is not valid Unicode.
+fn print_path0(path: &Path) {
+    let mut stdout = io::stdout().lock();
+    let _ = stdout.write_all(path_bytes(path).as_ref());
+    let _ = stdout.write_all(b"\0");
+}
+
+/// What the `result` of converting a file counts as in the summary.
+fn file_outcome(result: &io::Result<ProcessedFile>) -> FileOutcome {
+    match result {
+        Ok(file) if file.changed => FileOutcome::Converted,
+        Ok(_) => FileOutcome::AlreadyClean,
+        Err(e) => match ErrorCode::of(e) {
+            ErrorCode::Binary => FileOutcome::SkippedBinary,
+            ErrorCode::Locked => FileOutcome::SkippedLocked,
+            _ => FileOutcome::Error,
+        },
+    }
+}
+
+/// Writes the `--report` of a run that began at `started`, with the totals
+/// of `summary` and the files recorded in `opts`, to `path`.
+fn write_report(
+    tool: Tool,
+    opts: &Options,
+    path: &Path,
+    summary: &Summary,
+    started: SystemTime,
+) -> io::Result<()> {
+    let totals = JsonObject::new()
+        .number("converted", summary.converted as u64)
+        .number("already_clean", summary.already_clean as u64)
+        .number("skipped_binary", summary.skipped_binary as u64)
+        .number("skipped_locked", summary.skipped_locked as u64)
+        .number("errors", summary.errors as u64)
+        .finish();
+    let files = format!("[{}]", opts.report_files.borrow().join(","));
+    let newline = match opts.conversion_mode(tool) {
+        ConversionMode::ToUnix => "lf",
+        ConversionMode::ToDos => "crlf",
+        ConversionMode::ToMac => "cr",
+    };
+    let start = started
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default();
+    let report = JsonObject::new()
+        .string("tool", tool.name())
+        .string("version", env!("CARGO_PKG_VERSION"))
+        .number("started", start.as_secs())
+        .number(
+            "duration_ms",
+            started.elapsed().unwrap_or_default().as_millis() as u64,
+        )
+        .string("newline", newline)
+        .number("exit_status", opts.exit_code() as u64)
+        .number("failures", opts.failures.get() as u64)
+        .raw("summary", &totals)
+        .raw("files", &files)
+        .finish();
+    fs::write(path, report + "\n")
+}
+
+/// Reports the `result` of converting `input_path` to `output_path`, which
+/// took `elapsed`, and records a failure in the exit status.
+fn report_conversion(
+    tool: Tool,
+    opts: &Options,
+    input_path: &Path,
+    output_path: Option<&Path>,
+    result: io::Result<ProcessedFile>,
+    elapsed: Duration,
+) -> FileOutcome {
+    let conversion_mode = opts.conversion_mode(tool);
+    let outcome = file_outcome(&result);
+    opts.record_report(input_path, output_path, &result, elapsed);
+    // Stdout, when written to, carries the conversion itself
+    let written = output_path.unwrap_or(input_path);
+    if opts.print0 && outcome == FileOutcome::Converted && !is_stdio(written) {
+        print_path0(written);
+    }
+    // With --skip-binary a binary file is not a failure, just noted.
+    let skipped = opts.skip_binary && outcome == FileOutcome::SkippedBinary;
+    if skipped {
+        opts.skipped.borrow_mut().push(input_path.to_path_buf());
+    } else if let Err(e) = &result {
+        opts.fail_file(input_path, e);
+    }
+    match (opts.format, result.map(|file| file.stats)) {
+        (OutputFormat::Json | OutputFormat::Porcelain, Ok(stats)) => {
+            // Without --force a successful conversion implies a text file.
+            let binary = if opts.force { None } else { Some(false) };
+            let action = match outcome {
+                FileOutcome::AlreadyClean => "unchanged",
+                _ => "converted",
+            };
+            print_record(opts, Some(input_path), Some(&stats), binary, action, None)
+        }
+        (OutputFormat::Json | OutputFormat::Porcelain, Err(_)) if skipped => {
+            print_record(opts, Some(input_path), None, Some(true), "skipped", None)
+        }
+        (_, Err(_)) if skipped => {
+            if !opts.quiet {
+                let message = tr!(
+                    "{}: Skipping binary file '{}'",
+                    opts.progname,
+                    input_path.display()
+                );
+                opts.inform(&opts.paint_messages(&message, Style::Yellow));
+            }
+        }
+        (OutputFormat::Json | OutputFormat::Porcelain, Err(e)) => print_record(
+            opts,
+            Some(input_path),
+            None,
+            None,
+            match outcome {
+                FileOutcome::SkippedLocked => "skipped",
+                _ => "error",
+            },
+            Some((ErrorCode::of(&e), &e.to_string())),
+        ),
+        (_, Err(e)) if outcome == FileOutcome::SkippedLocked => {
+            opts.clear_progress();
+            eprintln!(
+                "{}",
+                opts.paint_stderr(
+                    &with_code(
+                        tr!(
+                            "{}: Skipping locked file '{}': {}",
+                            opts.progname,
+                            input_path.display(),
+                            e
+                        ),
+                        ErrorCode::Locked
+                    ),
+                    Style::Yellow
+                )
+            );
+        }
+        (_, Ok(stats)) => {
+            // A lone CR is a line break only for -m; otherwise it is kept
+            // as text, which is usually a copy-and-paste accident.
+            let keeps_lone_cr = conversion_mode == ConversionMode::ToUnix
+                && !opts.mac_mode
+                && !opts.strip_cr
+                && !opts.normalize
+                && !opts.auto;
+            if let (true, false, Some(position)) = (keeps_lone_cr, opts.quiet, stats.first_lone_cr)
+            {
+                opts.clear_progress();
+                eprintln!(
+                    "{}",
+                    opts.paint_stderr(
+                        &tr!(
+                            "{}: '{}': lone CR kept at {} (use --strip-cr to remove)",
+                            opts.progname,
+                            input_path.display(),
+                            position
+                        ),
+                        Style::Yellow
+                    )
+                );
+            }
+        }
+        (_, Err(e)) => {
+            opts.clear_progress();
+            eprintln!(
+                "{}",
+                opts.paint_stderr(
+                    &with_code(
+                        tr!(
+                            "{}: Error converting '{}': {}",
+                            opts.progname,
+                            input_path.display(),
+                            e
+                        ),
+                        ErrorCode::of(&e)
+                    ),
+                    Style::Red
+                )
+            );
+            if outcome == FileOutcome::SkippedBinary && !opts.force && !opts.quiet {
+                eprintln!(
+                    "{}",
+                    tr!("{}: Use --force to convert binary files.", opts.progname)
+                );
+            }
+        }
+    }
+    outcome
+}
+
+/// Runs the conversion of `input_path` in memory for `--dry-run` and reports
+/// what [`process_file`] would do, without touching the filesystem.
+fn dry_run_file(tool: Tool, opts: &Options, input_path: &Path, output_path: Option<&Path>) {
+    let result = read_input(opts, input_path).and_then(|content| {
+        if let (true, Some(output_path)) = (opts.no_clobber, output_path.filter(|p| !is_stdio(p))) {
+            refuse_clobber(output_path, output_path, &opts.progname)?;
+        }
+        let converted = convert_with_options(&content, &opts.conversion(tool))?;
+        let backup = match opts.backup {
+            true => Some(opts.backup_naming.path(input_path)?),
+            false => None,
+        };
+        Ok((
+            detect_line_endings(&content),
+            converted[..] != content[..],
+            backup,
+        ))
+    });
+    let (stats, changed, backup) = match result {
+        Ok(result) => result,
+        Err(e) => {
+            opts.fail(&e);
+            match opts.format {
+                OutputFormat::Json | OutputFormat::Porcelain => print_record(
+                    opts,
+                    Some(input_path),
+                    None,
+                    None,
+                    "error",
+                    Some((ErrorCode::of(&e), &e.to_string())),
+                ),
+                _ => println!(
+                    "{}",
+                    opts.paint_stdout(
+                        &with_code(
+                            tr!(
+                                "{}: would fail to convert '{}': {}",
+                                opts.progname,
+                                input_path.display(),
+                                e
+                            ),
+                            ErrorCode::of(&e)
+                        ),
+                        Style::Red
+                    )
+                ),
+            }
+            return;
+        }
+    };
+
+    if opts.format.prints_records() {
+        let action = if changed {
+            "would_convert"
+        } else {
+            "unchanged"
+        };
+        let binary = if opts.force { None } else { Some(false) };
+        print_record(opts, Some(input_path), Some(&stats), binary, action, None);
+        return;
+    }
+    if let Some(backup) = backup {
+        println!(
+            "{}",
+            tr!(
+                "{}: would create backup file '{}'",
+                opts.progname,
+                backup.display()
+            )
+        );
+    }
+    let (conversions, total) = (opts.conversions(tool, &stats), stats.total());
+    if changed {
+        let message = match output_path {
+            Some(output_path) => tr!(
+                "{}: would convert '{}' to '{}' ({} of {} line breaks)",
+                opts.progname,
+                input_path.display(),
+                output_path.display(),
+                conversions,
+                total
+            ),
+            None => tr!(
+                "{}: would convert '{}' ({} of {} line breaks)",
+                opts.progname,
+                input_path.display(),
+                conversions,
+                total
+            ),
+        };
+        println!("{}", opts.paint_stdout(&message, Style::Green));
+    } else {
+        let message = match output_path {
+            Some(output_path) => tr!(
+                "{}: would write '{}' to '{}' unchanged",
+                opts.progname,
+                input_path.display(),
+                output_path.display()
+            ),
+            None => tr!(
+                "{}: would write '{}' unchanged",
+                opts.progname,
+                input_path.display()
+            ),
+        };
+        println!("{}", message);
+    }
+}
+
+/// Prints a unified diff of the changes converting `input_path` would make,
+/// for `--diff`, without writing anything.
+fn diff_file(tool: Tool, opts: &Options, input_path: &Path, output_path: Option<&Path>) {
+    let result = read_input(opts, input_path).and_then(|content| {
+        let converted = convert_with_options(&content, &opts.conversion(tool))?;
+        let new_name = output_path.unwrap_or(input_path);
+        write_diff(
+            &mut io::stdout().lock(),
+            &content,
+            &converted,
+            &input_path.to_string_lossy(),
+            &new_name.to_string_lossy(),
+        )
+    });
+    if let Err(e) = result {
+        opts.fail(&e);
+        eprintln!(
+            "{}",
+            opts.paint_stderr(
+                &tr!(
+                    "{}: Error converting '{}': {}",
+                    opts.progname,
+                    input_path.display(),
+                    e
+                ),
+                Style::Red
+            )
+        );
+    }
+}
+
+/// Parses a size in bytes, optionally followed by `k` or `M` for KiB or MiB.
+fn parse_size(value: &str) -> Option<usize> {
+    let (number, unit) = match value.strip_suffix(['k', 'K']) {
+        Some(number) => (number, 1024),
+        None => match value.strip_suffix('M') {
+            Some(number) => (number, 1024 * 1024),
+            None => (value, 1),
+        },
+    };
+    number.parse::<usize>().ok()?.checked_mul(unit)
+}
+
+/// Parses a comma-separated list of control character codes, in decimal or
+/// with a `0x` prefix in hexadecimal, into a [`BinaryPolicy`] bit set.
+fn parse_controls(value: &str) -> Option<u32> {
+    let mut allowed = 0;
+    for code in value.split(',').filter(|code| !code.is_empty()) {
+        let code = match code.strip_prefix("0x") {
+            Some(hex) => u8::from_str_radix(hex, 16).ok()?,
+            None => code.parse().ok()?,
+        };
+        if code >= 32 {
+            return None;
+        }
+        allowed |= 1 << code;
+    }
+    Some(allowed)
+}
+
+/// A file named on the command line, in the mode it was given in.
+#[derive(Debug, PartialEq, Eq)]
+enum FileArgument {
+    /// A file given in old-file mode, converted in place.
+    File(PathBuf),
+    /// An input and output file given in new-file mode.
+    Pair(PathBuf, PathBuf),
+    /// A file given in new-file mode that was not followed by an output.
+    Unpaired(PathBuf),
+}
+
+/// The file arguments in the order they were given, in their modes. `-n`
+/// switches to new-file mode, where the files that follow its own two are
+/// paired up as well, and `-o` back to old-file mode, wherever they are
+/// given.
+fn file_arguments(matches: &ArgMatches) -> Vec<FileArgument> {
+    let mut arguments = Vec::new();
+    let mut switches = Vec::new();
+    if let (Some(occurrences), Some(indices)) = (
+        matches.get_occurrences::<PathBuf>("newfile"),
+        matches.indices_of("newfile"),
+    ) {
+        for (mut pair, index) in occurrences.zip(indices.step_by(2)) {
+            if let (Some(infile), Some(outfile)) = (pair.next(), pair.next()) {
+                arguments.push((index, FileArgument::Pair(infile.clone(), outfile.clone())));
+            }
+            switches.push((index, true));
+        }
+    }
+    switches.extend(
+        matches
+            .indices_of("oldfile")
+            .into_iter()
+            .flatten()
+            .map(|index| (index, false)),
+    );
+    switches.sort_unstable();
+
+    // A file waiting for its output, with the switch it follows
+    let mut waiting: Option<(usize, usize, &PathBuf)> = None;
+    let files = matches.get_many::<PathBuf>("files").into_iter().flatten();
+    let indices = matches.indices_of("files").into_iter().flatten();
+    for (path, index) in files.zip(indices) {
+        let switch = switches.partition_point(|&(at, _)| at < index);
+        if switch == 0 || !switches[switch - 1].1 {
+            arguments.push((index, FileArgument::File(path.clone())));
+            continue;
+        }
+        match waiting.take() {
+            Some((first, same, infile)) if same == switch => {
+                arguments.push((first, FileArgument::Pair(infile.clone(), path.clone())));
+            }
+            previous => {
+                arguments.extend(
+                    previous.map(|(at, _, infile)| (at, FileArgument::Unpaired(infile.clone()))),
+                );
+                waiting = Some((index, switch, path));
+            }
+        }
+    }
+    arguments.extend(waiting.map(|(at, _, infile)| (at, FileArgument::Unpaired(infile.clone()))));
+    arguments.sort_by_key(|&(index, _)| index);
+    arguments
+        .into_iter()
+        .map(|(_, argument)| argument)
+        .collect()
+}
+
+/// The character conversion chosen by the last of `--ascii`, `-7` and a
+/// code page such as `-850`, or their `-c` forms. A later `--iso` switches
+/// the first two to CP437, but keeps a code page.
+fn char_conversion(matches: &ArgMatches) -> CharConversion {
+    // Flags have a default value, with an index of its own
+    let given = |id: &str| {
+        matches
+            .index_of(id)
+            .filter(|_| matches.value_source(id) == Some(ValueSource::CommandLine))
+    };
+    let convmodes = matches
+        .indices_of("convmode")
+        .into_iter()
+        .flatten()
+        .zip(matches.get_many::<String>("convmode").into_iter().flatten());
+    let convmode = |mode: &str| {
+        convmodes
+            .clone()
+            .filter(|(_, given)| *given == mode)
+            .map(|(index, _)| index)
+            .max()
+    };
+    let code_page = matches.get_one::<CodePage>("code-page").copied();
+    let last = [
+        (
+            given("ascii").max(convmode("ascii")),
+            Some(CharConversion::Ascii),
+        ),
+        (
+            given("7").max(convmode("7bit")),
+            Some(CharConversion::SevenBit),
+        ),
+        (given("code-page"), code_page.map(CharConversion::Iso)),
+    ]
+    .into_iter()
+    .filter_map(|(index, conversion)| Some((index?, conversion?)))
+    .max_by_key(|&(index, _)| index);
+    let iso = given("iso").max(convmode("iso"));
+    match last {
+        Some((_, CharConversion::Iso(code_page))) => CharConversion::Iso(code_page),
+        Some((index, conversion)) if iso.is_none_or(|iso| iso < index) => conversion,
+        _ if iso.is_some() => CharConversion::Iso(CodePage::Cp437),
+        _ => CharConversion::Ascii,
+    }
+}
+
+/// Runs the tool the process was started as, going by its program name, or
+/// `default` if that is not the name of a tool. One executable can so be
+/// installed under each name, as links to it.
+pub fn run_as_invoked(default: Tool) -> i32 {
+    let tool = env::args_os()
+        .next()
+        .and_then(|program| Tool::from_program_name(Path::new(&program)));
+    run(tool.unwrap_or(default))
+}
+
+/// Splits `text` into words at whitespace as a shell does, where a word may
+/// be quoted to hold whitespace: `'...'` keeps everything as it is, and
+/// `"..."` everything but `\"` and `\\`, which stand for `"` and `\`.
+/// Outside quotes a backslash only escapes whitespace, a quote or another
+/// backslash, so that Windows paths need no doubled backslashes. Gives
+/// `None` if a quote is not closed.
+fn split_words(text: &str) -> Option<Vec<String>> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\\' => {
+                let word = word.get_or_insert_with(String::new);
+                match chars.next_if(|&next| next.is_whitespace() || "'\"\\".contains(next)) {
+                    Some(escaped) => word.push(escaped),
+                    None => word.push('\\'),
+                }
+            }
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => match chars.next_if(|&next| next == '"' || next == '\\') {
+                            Some(escaped) => word.push(escaped),
+                            None => word.push('\\'),
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+    Some(words)
+}
+
+/// Runs the tool with the process's command-line arguments and returns the
+/// process exit status.
+pub fn run(tool: Tool) -> i32 {
+    let started = SystemTime::now();
+    localize_messages();
+    let mut args: Vec<OsString> = env::args_os().collect();
+    let progname = Path::new(&args[0])
+        .file_name()
+        .unwrap()
+        .to_string_lossy()
+        .to_string();
+    // A subcommand only counts as the first argument, so a file of the same
+    // name can still be converted as `./info`
+    let subcommand = args
+        .get(1)
+        .and_then(|arg| SUBCOMMANDS.iter().find(|(name, _)| arg == name));
+    if let Some(&(_, option)) = subcommand {
+        args.splice(1..2, option.map(OsString::from));
+    }
+
+    // Default options go before the command line, which overrides them:
+    // those of configuration files, then those of the environment
+    let mut defaults: Vec<OsString> = Vec::new();
+    match env::var(tool.options_variable()) {
+        Ok(options) => match split_words(&options) {
+            Some(words) => defaults.extend(words.into_iter().map(OsString::from)),
+            None => {
+                eprintln!(
+                    "{}",
+                    tr!(
+                        "{}: Error reading {}: a quote is not closed",
+                        progname,
+                        tool.options_variable()
+                    )
+                );
+                return ExitStatus::Failure as i32;
+            }
+        },
+        Err(env::VarError::NotUnicode(_)) => eprintln!(
+            "{}",
+            tr!(
+                "{}: Ignoring {}, which is not valid Unicode",
+                progname,
+                tool.options_variable()
+            )
+        ),
+        Err(env::VarError::NotPresent) => {}
+    }
+    let no_config = defaults
+        .iter()
+        .chain(args[1..].iter().take_while(|arg| *arg != "--"))
+        .any(|arg| arg == "--no-config");
+    if !no_config {
+        let mut configured = Vec::new();
+        for path in config_files() {
+            match config::read_options(&path, &command(tool, &progname), tool.name()) {
+                Ok(options) => configured.extend(options),
+                Err(e) => {
+                    eprintln!(
+                        "{}",
+                        tr!("{}: Error reading '{}': {}", progname, path.display(), e)
+                    );
+                    return ExitStatus::Failure as i32;
+                }
+            }
+        }
+        defaults.splice(0..0, configured);
+    }
+    args.splice(1..1, defaults);
+
+    let matches = match command(tool, &progname).try_get_matches_from(legacy_args(args)) {
+        Ok(matches) => matches,
+        Err(e) => {
+            let _ = e.print();
+            return match e.kind() {
+                ErrorKind::DisplayHelp => ExitStatus::Success,
+                _ => ExitStatus::Failure,
+            } as i32;
+        }
+    };
+    if matches.get_flag("version") {
+        print_version(tool);
+        return ExitStatus::Success as i32;
+    }
+    if let Some(&shell) = matches.get_one::<Shell>("completions") {
+        // Completions are looked up by the command name, without .exe
+        let name = Path::new(&progname).file_stem().unwrap().to_string_lossy();
+        let mut command = command(tool, &name);
+        clap_complete::generate(shell, &mut command, name.as_ref(), &mut io::stdout());
+        return ExitStatus::Success as i32;
+    }
+
+    let strings = |id: &str| -> Vec<String> {
+        matches
+            .get_many::<String>(id)
+            .into_iter()
+            .flatten()
+            .cloned()
+            .collect()
+    };
+    let extension_lists = |id: &str| -> Vec<Vec<String>> {
+        strings(id)
+            .iter()
+            .map(|list| {
+                list.split(',')
+                    .map(|extension| extension.trim_start_matches('.'))
+                    .filter(|extension| !extension.is_empty())
+                    .map(str::to_string)
+                    .collect()
+            })
+            .collect()
+    };
+    let mut skip_extensions: Vec<String> = DEFAULT_SKIP_EXTENSIONS
+        .iter()
+        .map(|extension| extension.to_string())
+        .collect();
+    for list in extension_lists("skip-ext") {
+        // An empty list clears the extensions given so far, including the
+        // built-in ones.
+        if list.is_empty() {
+            skip_extensions.clear();
+        }
+        skip_extensions.extend(list);
+    }
+    let newline = matches.get_one::<TargetNewline>("newline").copied();
+    let color = matches
+        .get_one("color")
+        .copied()
+        .unwrap_or(ColorChoice::Auto);
+    let mac_mode = matches.get_flag("mac")
+        || strings("convmode").iter().any(|mode| mode == "mac")
+        || matches!(tool, Tool::Mac2Unix | Tool::Unix2Mac);
+    let opts = Options {
+        progname,
+        keep_bom: matches.get_flag("keep-bom"),
+        remove_bom: matches.get_flag("remove-bom"),
+        add_bom: matches.get_flag("add-bom"),
+        force: matches.get_flag("force"),
+        skip_binary: matches.get_flag("skip-binary"),
+        fail_fast: matches.get_flag("fail-fast"),
+        sniff_file_type: !matches.get_flag("no-sniff"),
+        skip_extensions,
+        binary_policy: BinaryPolicy {
+            allowed_controls: matches
+                .get_one("allow-control")
+                .copied()
+                .unwrap_or(BinaryPolicy::default().allowed_controls),
+            nul_threshold: matches.get_one("nul-threshold").copied().unwrap_or(0),
+            sample_size: matches
+                .get_one("binary-sample")
+                .copied()
+                .filter(|&size| size > 0),
+            utf8_aware: matches.get_flag("smart-binary"),
+        },
+        backup: matches.get_flag("backup")
+            || matches.contains_id("backup-suffix")
+            || matches.get_flag("backup-numbered")
+            || matches.contains_id("backup-dir"),
+        backup_naming: BackupNaming {
+            suffix: matches
+                .get_one("backup-suffix")
+                .cloned()
+                .unwrap_or(BackupNaming::default().suffix),
+            numbered: matches.get_flag("backup-numbered"),
+            dir: matches.get_one("backup-dir").cloned(),
+        },
+        keep_date: matches.get_flag("keep-date"),
+        allow_chown: matches.get_flag("allow-chown"),
+        no_clobber: matches.get_flag("no-clobber"),
+        follow_symlinks: matches.get_flag("follow-symlink"),
+        sync: matches.get_flag("sync"),
+        in_place: matches.get_flag("in-place"),
+        force_readonly: matches.get_flag("force-readonly"),
+        force_special: matches.get_flag("force-special"),
+        lock: matches.get_flag("lock"),
+        retries: matches.get_one("retry").copied().unwrap_or(0),
+        retry_delay: matches
+            .get_one("retry-delay")
+            .map_or(DEFAULT_RETRY_DELAY, |&ms| Duration::from_millis(ms)),
+        stream: matches.get_flag("stream"),
+        mmap: matches.get_flag("mmap"),
+        buffer_size: matches
+            .get_one("buffer-size")
+            .copied()
+            .unwrap_or(DEFAULT_BUFFER_SIZE),
+        recursive: matches.get_flag("recursive"),
+        max_depth: matches.get_one("max-depth").copied(),
+        output_dir: matches.get_one("output-dir").cloned(),
+        to_stdout: matches.get_flag("to-stdout"),
+        jobs: matches
+            .get_one("jobs")
+            .copied()
+            .unwrap_or_else(|| thread::available_parallelism().map_or(1, NonZeroUsize::get)),
+        gitattributes: matches.get_flag("gitattributes"),
+        files_from: matches.get_one("files-from").cloned(),
+        null_separated: matches.get_flag("null"),
+        path_filter: PathFilter {
+            include: strings("include"),
+            exclude: strings("exclude"),
+            extensions: extension_lists("ext").concat(),
+            ignore_files: !matches.get_flag("no-ignore"),
+            hidden: matches.get_flag("hidden"),
+            symlinks: matches.get_flag("follow-symlink"),
+        },
+        mac_mode,
+        newline,
+        add_eol: matches.get_flag("add-eol"),
+        to_utf8: matches.get_flag("to-utf8"),
+        gb18030: matches.get_flag("gb18030"),
+        from_encoding: matches.get_one("from-encoding").cloned(),
+        to_encoding: matches.get_one("to-encoding").cloned(),
+        bom: matches.get_one("bom").copied(),
+        char_conversion: char_conversion(&matches),
+        info: matches.get_one("info").copied(),
+        check: matches.get_flag("check"),
+        watch: matches.get_flag("watch"),
+        check_utf8: matches.get_flag("check-utf8"),
+        strict: matches.get_flag("strict"),
+        // Mac line breaks are lone CRs, which converting to Unix ones only
+        // touches when normalizing
+        normalize: matches.get_flag("normalize")
+            || (mac_mode
+                && newline.map_or(
+                    tool.conversion_mode(mac_mode),
+                    TargetNewline::conversion_mode,
+                ) == ConversionMode::ToUnix),
+        auto: matches.get_flag("auto"),
+        unicode_breaks: matches.get_flag("unicode-breaks"),
+        strip_cr: matches.get_flag("strip-cr"),
+        dry_run: matches.get_flag("dry-run"),
+        diff: matches.get_flag("diff"),
+        format: if matches.get_flag("porcelain") {
+            OutputFormat::Porcelain
+        } else {
+            matches
+                .get_one("format")
+                .copied()
+                .unwrap_or(OutputFormat::Text)
+        },
+        verbose: matches.get_count("verbose") as usize,
+        quiet: matches.get_flag("quiet"),
+        prompt: Cell::new(if matches.get_flag("interactive") {
+            Prompt::Ask
+        } else {
+            Prompt::Never
+        }),
+        header_printed: Cell::new(false),
+        status: Cell::new(ExitStatus::Success),
+        failures: Cell::new(0),
+        stopped: Cell::new(false),
+        skipped: RefCell::new(Vec::new()),
+        failed: RefCell::new(Vec::new()),
+        messages: Arc::new(Messages {
+            to_stdout: matches
+                .get_one::<String>("messages-to")
+                .is_some_and(|stream| stream == "stdout"),
+            progress: Mutex::new(None),
+        }),
+        color_stdout: color.stdout(),
+        color_stderr: color.stderr(),
+        print0: matches.get_flag("print0"),
+        report: matches.get_one("report").cloned(),
+        report_files: RefCell::new(Vec::new()),
+    };
+    // Records are read by programs, and so are the errors in them
+    if opts.format.prints_records() {
+        nls::disable();
+    }
+    let progname = opts.progname.clone();
+    let mut summary = Summary::default();
+
+    // Input comes from stdin only if no files were named, not when a
+    // pattern, directory or filter ends up selecting none.
+    let arguments = file_arguments(&matches);
+    let files_named = !arguments.is_empty() || opts.files_from.is_some();
+    // Converting stdin alone leaves nothing to clean up, and the default
+    // action is what ends a read that is waiting for input
+    if files_named {
+        signal::install();
+    }
+
+    // Files converted in place are expanded a run at a time, so that they
+    // keep their place among the pairs of new-file mode
+    let mut targets = Vec::new();
+    let mut files: Vec<PathBuf> = Vec::new();
+    for argument in arguments {
+        let target = match argument {
+            FileArgument::File(path) => {
+                let pattern = path.to_string_lossy();
+                // The shell may not have expanded wildcards, as on Windows
+                if glob::has_wildcards(&pattern) && !path.exists() {
+                    let matches = glob::expand(&path);
+                    if matches.is_empty() {
+                        eprintln!("{}", tr!("{}: No files match '{}'", progname, pattern));
+                        opts.fail(&io::ErrorKind::NotFound.into());
+                    }
+                    files.extend(matches);
+                } else {
+                    files.push(path);
+                }
+                continue;
+            }
+            FileArgument::Pair(infile, outfile) => Target::File(infile, Some(outfile)),
+            FileArgument::Unpaired(infile) => Target::Unpaired(infile),
+        };
+        targets.extend(in_place_targets(
+            tool,
+            &opts,
+            mem::take(&mut files),
+            &mut summary,
+        ));
+        targets.push(target);
+    }
+
+    if let Some(list) = &opts.files_from {
+        match read_file_list(list, opts.null_separated) {
+            Ok(listed) => files.extend(listed),
+            Err(e) => {
+                eprintln!(
+                    "{}",
+                    with_code(
+                        tr!("{}: Error reading '{}': {}", progname, list.display(), e),
+                        ErrorCode::of(&e)
+                    )
+                );
+                return ExitStatus::from_io_error(&e) as i32;
+            }
+        }
+    }
+    targets.extend(in_place_targets(tool, &opts, files, &mut summary));
+
+    if !files_named {
+        // Check if stdin is connected to a terminal
+        if is_stdin_tty() {
+            eprintln!(
+                "{}",
+                tr!("{}: No files specified and no input provided.", progname)
+            );
+            eprintln!("{}", tr!("Try '{} --help' for more information.", progname));
+            return ExitStatus::Failure as i32;
+        } else if opts.check || opts.info.is_some() {
+            // Read from stdin
+            let mut input = Vec::new();
+            match io::stdin().read_to_end(&mut input) {
+                Ok(_) if opts.check => check_content(tool, &opts, &input, None),
+                Ok(_) => info_content(tool, &opts, &input, None),
+                Err(e) => {
+                    eprintln!(
+                        "{}",
+                        with_code(
+                            tr!("{}: Error converting input: {}", progname, e),
+                            ErrorCode::of(&e)
+                        )
+                    );
+                    opts.fail(&e);
+                }
+            }
+        } else {
+            let started = Instant::now();
+            let result = convert_pipe(io::stdin(), io::stdout(), &opts.pipe_conversion(tool));
+            opts.record_report(Path::new("-"), None, &result, started.elapsed());
+            summary.record(file_outcome(&result));
+            if let Err(e) = result {
+                eprintln!(
+                    "{}",
+                    with_code(
+                        tr!("{}: Error converting input: {}", progname, e),
+                        ErrorCode::of(&e)
+                    )
+                );
+                opts.fail(&e);
+            }
+        }
+    } else if opts.check || opts.info.is_some() {
+        for target in &targets {
+            if opts.stop() {
+                break;
+            }
+            match target {
+                Target::File(input_path, _) => inspect_file(tool, &opts, input_path),
+                Target::Unpaired(infile) => summary.record(unpaired_file(&opts, infile)),
+            }
+        }
+    } else {
+        let targets: Vec<Target> = targets
+            .into_iter()
+            .filter_map(|target| match target {
+                Target::File(input_path, output_path) => opts
+                    .resolve_symlink(&input_path, output_path.as_deref())
+                    .map(|(input_path, output_path)| Target::File(input_path, output_path)),
+                unpaired => Some(unpaired),
+            })
+            .collect();
+        if opts.diff || opts.dry_run {
+            for target in &targets {
+                if opts.stop() {
+                    break;
+                }
+                match target {
+                    Target::File(input_path, output_path) if opts.diff => {
+                        diff_file(tool, &opts, input_path, output_path.as_deref())
+                    }
+                    Target::File(input_path, output_path) => {
+                        dry_run_file(tool, &opts, input_path, output_path.as_deref())
+                    }
+                    Target::Unpaired(infile) => summary.record(unpaired_file(&opts, infile)),
+                }
+            }
+        } else {
+            convert_files(tool, &opts, &targets, &mut summary);
+            if opts.watch && !opts.stopping() {
+                watch_files(tool, &opts, &targets);
+            }
+        }
+    }
+
+    if opts.stopped.get() {
+        let message = if signal::interrupted() {
+            tr!(
+                "{}: Interrupted, the remaining files were left alone",
+                progname
+            )
+        } else {
+            tr!(
+                "{}: Stopped at the first failure, the remaining files were left alone",
+                progname
+            )
+        };
+        eprintln!("{}", message);
+    }
+
+    if summary.total() > 1 && opts.format == OutputFormat::Text && !opts.quiet {
+        let style = if summary.errors > 0 {
+            Style::Red
+        } else if summary.skipped_binary > 0 || summary.skipped_locked > 0 {
+            Style::Yellow
+        } else {
+            Style::Green
+        };
+        let line = format!("{}: {}", progname, summary);
+        opts.inform(&opts.paint_messages(&line, style));
+        for path in opts.skipped.borrow().iter() {
+            let line = tr!("{}: skipped binary file '{}'", progname, path.display());
+            opts.inform(&opts.paint_messages(&line, Style::Yellow));
+        }
+        for (path, code) in opts.failed.borrow().iter() {
+            let line = with_code(tr!("{}: failed file '{}'", progname, path.display()), *code);
+            opts.inform(&opts.paint_messages(&line, Style::Red));
+        }
+    }
+
+    if let Some(report) = &opts.report {
+        if let Err(e) = write_report(tool, &opts, report, &summary, started) {
+            eprintln!(
+                "{}",
+                tr!(
+                    "{}: Error writing report '{}': {}",
+                    progname,
+                    report.display(),
+                    e
+                )
+            );
+            opts.fail(&e);
+        }
+    }
+
+    opts.exit_code()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn words_are_split_at_whitespace() {
+        assert_eq!(
+            split_words("  --keep-bom\t--keep-date\n -v ").unwrap(),
+            ["--keep-bom", "--keep-date", "-v"]
+        );
+        assert_eq!(split_words("").unwrap(), Vec::<String>::new());
+    }
+
+    #[test]
+    fn quotes_hold_whitespace() {
+        assert_eq!(
+            split_words(r#"--exclude 'my dir/*' --backup-dir="old \"copies\"" x''"#).unwrap(),
+            ["--exclude", "my dir/*", r#"--backup-dir=old "copies""#, "x"]
+        );
+        assert_eq!(split_words(r"'\' '' a\ b").unwrap(), [r"\", "", "a b"]);
+    }
+
+    #[test]
+    fn backslashes_of_windows_paths_are_kept() {
+        assert_eq!(
+            split_words(r#"--backup-dir C:\Backups\new "D:\old files""#).unwrap(),
+            ["--backup-dir", r"C:\Backups\new", r"D:\old files"]
+        );
+    }
+
+    #[test]
+    fn unclosed_quotes_are_refused() {
+        assert!(split_words("--exclude 'my dir").is_none());
+        assert!(split_words(r#"--exclude "a\""#).is_none());
+    }
+
+    fn files(args: &[&str]) -> Vec<FileArgument> {
+        let args = std::iter::once("dos2unix").chain(args.iter().copied());
+        let matches = command(Tool::Dos2Unix, "dos2unix")
+            .try_get_matches_from(legacy_args(args.map(OsString::from)))
+            .unwrap();
+        file_arguments(&matches)
+    }
+
+    fn file(path: &str) -> FileArgument {
+        FileArgument::File(path.into())
+    }
+
+    fn pair(infile: &str, outfile: &str) -> FileArgument {
+        FileArgument::Pair(infile.into(), outfile.into())
+    }
+
+    fn unpaired(path: &str) -> FileArgument {
+        FileArgument::Unpaired(path.into())
+    }
+
+    #[test]
+    fn files_are_converted_in_place_by_default() {
+        assert_eq!(files(&["a", "b"]), [file("a"), file("b")]);
+        assert_eq!(files(&["-o", "a"]), [file("a")]);
+    }
+
+    #[test]
+    fn files_after_n_are_paired() {
+        assert_eq!(files(&["-n", "a", "b"]), [pair("a", "b")]);
+        assert_eq!(
+            files(&["-n", "a", "b", "c", "d"]),
+            [pair("a", "b"), pair("c", "d")]
+        );
+        assert_eq!(
+            files(&["x", "-n", "a", "b", "c", "d"]),
+            [file("x"), pair("a", "b"), pair("c", "d")]
+        );
+    }
+
+    #[test]
+    fn o_switches_back_to_in_place() {
+        assert_eq!(
+            files(&["-n", "a", "b", "-o", "x", "y"]),
+            [pair("a", "b"), file("x"), file("y")]
+        );
+        assert_eq!(
+            files(&["-o", "x", "-n", "a", "b", "c", "d", "-o", "y"]),
+            [file("x"), pair("a", "b"), pair("c", "d"), file("y")]
+        );
+    }
+
+    #[test]
+    fn a_file_left_without_an_output_is_unpaired() {
+        assert_eq!(
+            files(&["-n", "a", "b", "c"]),
+            [pair("a", "b"), unpaired("c")]
+        );
+        assert_eq!(
+            files(&["-n", "a", "b", "c", "-o", "x"]),
+            [pair("a", "b"), unpaired("c"), file("x")]
+        );
+        // A file is not paired with one after the next -n
+        assert_eq!(
+            files(&["-n", "a", "b", "c", "-n", "d", "e", "f", "g"]),
+            [
+                pair("a", "b"),
+                unpaired("c"),
+                pair("d", "e"),
+                pair("f", "g")
+            ]
+        );
+    }
+}
@@ -0,0 +1,123 @@
+//! ANSI colors for the messages and `--info` columns printed to a terminal,
+//! for `--color`.
+
+use std::borrow::Cow;
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// When to color output, as chosen with `--color`.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub(crate) enum ColorChoice {
+    /// When writing to a terminal and `NO_COLOR` is not set.
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    pub(crate) fn parse(name: &str) -> Option<ColorChoice> {
+        match name {
+            "auto" => Some(ColorChoice::Auto),
+            "always" => Some(ColorChoice::Always),
+            "never" => Some(ColorChoice::Never),
+            _ => None,
+        }
+    }
+
+    /// Whether output to stdout is colored.
+    pub(crate) fn stdout(self) -> bool {
+        self.enabled(io::stdout().is_terminal(), Stream::Stdout)
+    }
+
+    /// Whether output to stderr is colored.
+    pub(crate) fn stderr(self) -> bool {
+        self.enabled(io::stderr().is_terminal(), Stream::Stderr)
+    }
+
+    fn enabled(self, terminal: bool, stream: Stream) -> bool {
+        let wanted = match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            // https://no-color.org/
+            ColorChoice::Auto => {
+                terminal
+                    && env::var_os("NO_COLOR").is_none_or(|value| value.is_empty())
+                    && env::var_os("TERM").is_none_or(|term| term != "dumb")
+            }
+        };
+        // A console that cannot be switched to escape sequences would print
+        // them as they are
+        wanted && (enable_escapes(stream) || self == ColorChoice::Always)
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Stream {
+    Stdout,
+    Stderr,
+}
+
+/// Colors and other attributes of text.
+#[derive(Copy, Clone)]
+pub(crate) enum Style {
+    Green,
+    Yellow,
+    Red,
+    Cyan,
+    Bold,
+    Dim,
+}
+
+impl Style {
+    fn code(self) -> &'static str {
+        match self {
+            Style::Green => "32",
+            Style::Yellow => "33",
+            Style::Red => "31",
+            Style::Cyan => "36",
+            Style::Bold => "1",
+            Style::Dim => "2",
+        }
+    }
+}
+
+/// `text` in `style` if `enabled`, or as it is.
+pub(crate) fn paint(text: &str, style: Style, enabled: bool) -> Cow<'_, str> {
+    if enabled {
+        Cow::Owned(format!("\x1b[{}m{}\x1b[0m", style.code(), text))
+    } else {
+        Cow::Borrowed(text)
+    }
+}
+
+/// Switches the console of `stream` to interpreting escape sequences, which
+/// Windows 10 and later support but leave off, and tells whether it does.
+#[cfg(windows)]
+fn enable_escapes(stream: Stream) -> bool {
+    use winapi::shared::minwindef::DWORD;
+    use winapi::um::consoleapi::{GetConsoleMode, SetConsoleMode};
+    use winapi::um::handleapi::INVALID_HANDLE_VALUE;
+    use winapi::um::processenv::GetStdHandle;
+    use winapi::um::winbase::{STD_ERROR_HANDLE, STD_OUTPUT_HANDLE};
+
+    const ENABLE_VIRTUAL_TERMINAL_PROCESSING: DWORD = 0x0004;
+    let which = match stream {
+        Stream::Stdout => STD_OUTPUT_HANDLE,
+        Stream::Stderr => STD_ERROR_HANDLE,
+    };
+    unsafe {
+        let handle = GetStdHandle(which);
+        let mut mode: DWORD = 0;
+        if handle == INVALID_HANDLE_VALUE || GetConsoleMode(handle, &mut mode) == 0 {
+            return false;
+        }
+        mode & ENABLE_VIRTUAL_TERMINAL_PROCESSING != 0
+            || SetConsoleMode(handle, mode | ENABLE_VIRTUAL_TERMINAL_PROCESSING) != 0
+    }
+}
+
+/// Terminals elsewhere interpret escape sequences anyway.
+#[cfg(not(windows))]
+fn enable_escapes(_stream: Stream) -> bool {
+    true
+}
@@ -0,0 +1,134 @@
+//! Configuration files with default options: `dos2unix/config.toml` in the
+//! user's configuration directory, and the nearest `.dos2unix.toml` in the
+//! current directory or above it, for settings that go with a project.
+
+use std::env;
+use std::ffi::OsString;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+use clap::{ArgAction, Command};
+use toml::{Table, Value};
+
+use crate::nls::tr;
+
+/// Name of the per-project configuration file.
+const PROJECT_FILE: &str = ".dos2unix.toml";
+
+/// The configuration files that exist, in the order they are read: the
+/// user's, then the project's, whose settings come later and so win.
+pub(crate) fn config_files() -> Vec<PathBuf> {
+    let user = user_config_dir().map(|dir| dir.join("dos2unix").join("config.toml"));
+    let project = env::current_dir().ok().and_then(|dir| {
+        dir.ancestors()
+            .map(|dir| dir.join(PROJECT_FILE))
+            .find(|file| file.is_file())
+    });
+    user.filter(|file| file.is_file())
+        .into_iter()
+        .chain(project)
+        .collect()
+}
+
+/// The directory configuration files of the user go in, as given by the XDG
+/// Base Directory specification.
+#[cfg(not(windows))]
+fn user_config_dir() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| Path::new(&home).join(".config")))
+}
+
+/// The directory configuration files of the user go in, their roaming
+/// application data folder.
+#[cfg(windows)]
+fn user_config_dir() -> Option<PathBuf> {
+    env::var_os("APPDATA").map(PathBuf::from)
+}
+
+/// Reads the configuration file at `path` and returns the options its
+/// settings stand for. Each key is the long name of an option of `command`,
+/// and its value one of:
+///
+/// - `true` to give the option, or `false` to leave it out;
+/// - an integer or string as the value of the option, or, for `verbose`,
+///   the number of times to give it;
+/// - an array to give the option once for each of its values.
+///
+/// Settings in a table named after the tool, such as `[unix2dos]` for a
+/// `tool` of `unix2dos`, apply to that tool only, after the others. Tables
+/// for other tools are skipped.
+pub(crate) fn read_options(
+    path: &Path,
+    command: &Command,
+    tool: &str,
+) -> io::Result<Vec<OsString>> {
+    let table: Table = fs::read_to_string(path)?
+        .parse()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let mut options = Vec::new();
+    push_settings(&mut options, &table, command)?;
+    if let Some(Value::Table(section)) = table.get(tool) {
+        push_settings(&mut options, section, command)?;
+    }
+    Ok(options)
+}
+
+/// Appends the options for the settings of `table` to `options`, leaving out
+/// the tables in it.
+fn push_settings(options: &mut Vec<OsString>, table: &Table, command: &Command) -> io::Result<()> {
+    for (key, value) in table {
+        if value.is_table() {
+            continue;
+        }
+        let Some(arg) = command
+            .get_arguments()
+            .find(|arg| arg.get_long() == Some(key))
+        else {
+            return Err(invalid(tr!("unknown option '{}'", key)));
+        };
+        let count = matches!(arg.get_action(), ArgAction::Count);
+        push_value(options, key, value, count)?;
+    }
+    Ok(())
+}
+
+/// Appends the options `value` of the setting `key` stands for to `options`.
+/// With `count`, a number is how many times to give the option.
+fn push_value(
+    options: &mut Vec<OsString>,
+    key: &str,
+    value: &Value,
+    count: bool,
+) -> io::Result<()> {
+    match value {
+        Value::Boolean(true) => options.push(format!("--{}", key).into()),
+        Value::Boolean(false) => {}
+        Value::Integer(times) if count => {
+            for _ in 0..*times {
+                options.push(format!("--{}", key).into());
+            }
+        }
+        Value::Integer(number) => options.push(format!("--{}={}", key, number).into()),
+        Value::String(string) => options.push(format!("--{}={}", key, string).into()),
+        Value::Array(values) => {
+            for value in values {
+                push_value(options, key, value, count)?;
+            }
+        }
+        _ => {
+            return Err(invalid(tr!(
+                "'{}' must be a boolean, integer, string or array",
+                key
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// An error for a setting that cannot be turned into options.
+fn invalid(message: String) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
@@ -0,0 +1,460 @@
+use std::fmt;
+
+use crate::bom::BomKind;
+use crate::nls::tr;
+use crate::units::{decode_units, CodeUnit};
+use crate::ConversionMode;
+
+/// Line break counts for a piece of text, as reported by
+/// [`detect_line_endings`].
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct LineEndingStats {
+    /// DOS line breaks (CRLF).
+    pub crlf: usize,
+    /// Unix line breaks (LF).
+    pub lf: usize,
+    /// Mac line breaks (a CR not followed by LF).
+    pub cr: usize,
+    /// More than one kind of line break is present.
+    pub mixed: bool,
+    /// Number of the first line whose line break differs in kind from the
+    /// first line's, if `mixed` is set.
+    pub first_mixed_line: Option<usize>,
+    /// Position of the first CR that is not part of a CRLF, which conversion
+    /// to Unix line endings keeps unless told to strip it. Lines are counted
+    /// by LF here, as they are after that conversion.
+    pub first_lone_cr: Option<TextPosition>,
+    /// Unicode line breaks: NEL (U+0085), LINE SEPARATOR (U+2028) and
+    /// PARAGRAPH SEPARATOR (U+2029). These are not included in `total`.
+    pub unicode: usize,
+    /// Byte order mark at the start of the input, if any.
+    pub bom: Option<BomKind>,
+}
+
+impl LineEndingStats {
+    /// Total number of line breaks of any kind.
+    pub fn total(&self) -> usize {
+        self.crlf + self.lf + self.cr
+    }
+
+    /// Number of line breaks converting with `conversion_mode` would rewrite.
+    pub fn conversions(&self, conversion_mode: ConversionMode) -> usize {
+        match conversion_mode {
+            ConversionMode::ToUnix => self.crlf,
+            ConversionMode::ToDos | ConversionMode::ToMac => self.lf,
+        }
+    }
+
+    /// Number of line breaks normalizing to the line break of
+    /// `conversion_mode` would rewrite, which includes every line break of
+    /// another kind.
+    pub fn normalizations(&self, conversion_mode: ConversionMode) -> usize {
+        match conversion_mode {
+            ConversionMode::ToUnix => self.crlf + self.cr,
+            ConversionMode::ToDos => self.lf + self.cr,
+            ConversionMode::ToMac => self.crlf + self.lf,
+        }
+    }
+
+    /// The conversion mode producing the most common kind of line break, or
+    /// `None` if there are no line breaks. Ties go to LF, then CRLF.
+    pub fn dominant_mode(&self) -> Option<ConversionMode> {
+        let most = self.lf.max(self.crlf).max(self.cr);
+        if most == 0 {
+            None
+        } else if self.lf == most {
+            Some(ConversionMode::ToUnix)
+        } else if self.crlf == most {
+            Some(ConversionMode::ToDos)
+        } else {
+            Some(ConversionMode::ToMac)
+        }
+    }
+
+    /// Whether converting with `conversion_mode` would change any line break.
+    pub fn needs_conversion(&self, conversion_mode: ConversionMode) -> bool {
+        self.conversions(conversion_mode) > 0
+    }
+}
+
+/// Identifies the BOM of `content` and returns it with the values of the
+/// code units that follow, which are bytes unless the BOM announces UTF-16 or
+/// UTF-32.
+fn code_units(content: &[u8]) -> (Option<BomKind>, impl Iterator<Item = u32> + '_) {
+    let bom = BomKind::detect(content);
+    let (unit_size, little_endian) = match bom {
+        Some(bom) => (bom.unit_size(), bom.is_little_endian()),
+        None => (1, false),
+    };
+    let body = &content[bom.map_or(0, |b| b.bytes().len())..];
+    let units = body.chunks_exact(unit_size).map(move |unit| {
+        if little_endian {
+            unit.iter()
+                .rev()
+                .fold(0u32, |acc, &b| (acc << 8) | b as u32)
+        } else {
+            unit.iter().fold(0u32, |acc, &b| (acc << 8) | b as u32)
+        }
+    });
+    (bom, units)
+}
+
+/// Counts the NEL, LINE SEPARATOR and PARAGRAPH SEPARATOR characters in
+/// `units`.
+pub(crate) fn count_unicode_breaks<T: CodeUnit>(units: &[T]) -> usize {
+    let mut idx = 0;
+    let mut count = 0;
+    while idx < units.len() {
+        match T::unicode_break_len(&units[idx..]) {
+            0 => idx += 1,
+            len => {
+                count += 1;
+                idx += len;
+            }
+        }
+    }
+    count
+}
+
+#[derive(Copy, Clone, PartialEq, Eq)]
+enum Break {
+    Crlf,
+    Lf,
+    Cr,
+}
+
+/// Counts line breaks in input fed to it in pieces, which may split a CRLF.
+/// Unicode line breaks are left to the caller.
+pub(crate) struct LineEndingScanner {
+    stats: LineEndingStats,
+    first_kind: Option<Break>,
+    /// The number of the first line break of each kind, by `Break` order,
+    /// which [`append`](LineEndingScanner::append) needs.
+    firsts: [Option<usize>; 3],
+    prev_cr: bool,
+    position: TextPosition,
+}
+
+impl LineEndingScanner {
+    pub(crate) fn new(bom: Option<BomKind>) -> Self {
+        LineEndingScanner {
+            stats: LineEndingStats {
+                bom,
+                ..LineEndingStats::default()
+            },
+            first_kind: None,
+            firsts: [None; 3],
+            prev_cr: false,
+            position: TextPosition { line: 1, column: 1 },
+        }
+    }
+
+    /// Scans the next code units of the input.
+    pub(crate) fn feed(&mut self, units: impl IntoIterator<Item = u32>) {
+        for value in units {
+            if value == b'\n' as u32 {
+                self.record(if self.prev_cr { Break::Crlf } else { Break::Lf });
+            } else if self.prev_cr {
+                self.record_lone_cr();
+            }
+            self.prev_cr = value == b'\r' as u32;
+            if value == b'\n' as u32 {
+                self.position = TextPosition {
+                    line: self.position.line + 1,
+                    column: 1,
+                };
+            } else {
+                self.position.column += 1;
+            }
+        }
+    }
+
+    /// The number of the line the next unit fed is on, counting lines by LF.
+    pub(crate) fn line(&self) -> usize {
+        self.position.line
+    }
+
+    /// Adds the counts of `piece`, a scanner fed the input that follows what
+    /// this one was fed. If that ended with a CR, the input of `piece` must
+    /// not start with an LF.
+    pub(crate) fn append(&mut self, piece: LineEndingScanner) {
+        if self.prev_cr {
+            self.record_lone_cr();
+        }
+        let before = self.stats.total();
+        let stats = &mut self.stats;
+        if stats.first_mixed_line.is_none() {
+            let first_mixed = match self.first_kind {
+                None => piece.stats.first_mixed_line,
+                Some(kind) => (0..3)
+                    .filter(|&other| other != kind as usize)
+                    .filter_map(|other| piece.firsts[other])
+                    .min(),
+            };
+            stats.first_mixed_line = first_mixed.map(|line| before + line);
+        }
+        for (first, piece_first) in self.firsts.iter_mut().zip(piece.firsts) {
+            *first = first.or(piece_first.map(|line| before + line));
+        }
+        self.first_kind = self.first_kind.or(piece.first_kind);
+        stats.crlf += piece.stats.crlf;
+        stats.lf += piece.stats.lf;
+        stats.cr += piece.stats.cr;
+        let position = self.position;
+        let shift = |piece_position: TextPosition| TextPosition {
+            line: position.line + piece_position.line - 1,
+            column: match piece_position.line {
+                1 => position.column + piece_position.column - 1,
+                _ => piece_position.column,
+            },
+        };
+        if stats.first_lone_cr.is_none() {
+            stats.first_lone_cr = piece.stats.first_lone_cr.map(shift);
+        }
+        self.position = shift(piece.position);
+        self.prev_cr = piece.prev_cr;
+    }
+
+    /// The counts for the whole input.
+    pub(crate) fn finish(mut self) -> LineEndingStats {
+        if self.prev_cr {
+            self.record_lone_cr();
+        }
+        let stats = &mut self.stats;
+        stats.mixed = [stats.crlf, stats.lf, stats.cr]
+            .iter()
+            .filter(|&&count| count > 0)
+            .count()
+            > 1;
+        self.stats
+    }
+
+    fn record(&mut self, kind: Break) {
+        let stats = &mut self.stats;
+        match kind {
+            Break::Crlf => stats.crlf += 1,
+            Break::Lf => stats.lf += 1,
+            Break::Cr => stats.cr += 1,
+        }
+        self.firsts[kind as usize].get_or_insert(stats.total());
+        if *self.first_kind.get_or_insert(kind) != kind && stats.first_mixed_line.is_none() {
+            stats.first_mixed_line = Some(stats.total());
+        }
+    }
+
+    fn record_lone_cr(&mut self) {
+        self.record(Break::Cr);
+        self.stats.first_lone_cr.get_or_insert(TextPosition {
+            column: self.position.column - 1,
+            ..self.position
+        });
+    }
+}
+
+/// Counts the line breaks in `content` by kind.
+///
+/// A leading BOM is recognized and reported but not counted as content. For
+/// UTF-16 and UTF-32 input the scan works on whole code units, so the zero
+/// bytes of `0D 00 0A 00` do not split a CRLF pair.
+pub fn detect_line_endings(content: &[u8]) -> LineEndingStats {
+    let (bom, units) = code_units(content);
+    let mut scanner = LineEndingScanner::new(bom);
+    scanner.feed(units);
+    let mut stats = scanner.finish();
+    let body = &content[bom.map_or(0, |b| b.bytes().len())..];
+    let little_endian = bom.is_some_and(|b| b.is_little_endian());
+    stats.unicode = match bom.map_or(1, |b| b.unit_size()) {
+        1 => count_unicode_breaks(body),
+        2 => count_unicode_breaks(&decode_units::<u16>(body, little_endian).0),
+        _ => count_unicode_breaks(&decode_units::<u32>(body, little_endian).0),
+    };
+    stats
+}
+
+/// Location of the first invalid UTF-8 sequence found by [`check_utf8`].
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct InvalidUtf8 {
+    /// Byte offset from the start of the input, BOM included.
+    pub offset: usize,
+    /// Line number, counting from 1.
+    pub line: usize,
+}
+
+impl fmt::Display for InvalidUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&tr!(
+            "invalid UTF-8 at byte {} (line {})",
+            self.offset,
+            self.line
+        ))
+    }
+}
+
+/// Checks that `content`, after an optional UTF-8 BOM, is well-formed UTF-8.
+///
+/// Input with a UTF-16 or UTF-32 BOM is not UTF-8, and fails at offset 0.
+pub fn check_utf8(content: &[u8]) -> Result<(), InvalidUtf8> {
+    let start = match BomKind::detect(content) {
+        None => 0,
+        Some(BomKind::Utf8) => BomKind::Utf8.bytes().len(),
+        Some(_) => return Err(InvalidUtf8 { offset: 0, line: 1 }),
+    };
+    match std::str::from_utf8(&content[start..]) {
+        Ok(_) => Ok(()),
+        Err(e) => {
+            let offset = start + e.valid_up_to();
+            Err(InvalidUtf8 {
+                offset,
+                line: detect_line_endings(&content[..offset]).total() + 1,
+            })
+        }
+    }
+}
+
+/// A location in a text, counting lines and columns from 1.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct TextPosition {
+    pub line: usize,
+    /// Code units into the line.
+    pub column: usize,
+}
+
+impl fmt::Display for TextPosition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&tr!("line {}, column {}", self.line, self.column))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INPUTS: &[&[u8]] = &[
+        b"a\r\nb\nc\rd",
+        b"one\rtwo\r",
+        b"\r\r\n\n\r",
+        b"x\ry\r\nz\n",
+        b"a\nb\r\nc\r\n\rd",
+        b"\xff\xfea\0\r\0\n\0b\0\r\0c\0\n\0\r\0",
+    ];
+
+    /// Scans `units` in the pieces that start at `splits`, one scanner each,
+    /// and appends them as [`crate::stream`] does.
+    fn scan_pieces(bom: Option<BomKind>, units: &[u32], splits: &[usize]) -> LineEndingStats {
+        let mut bounds = vec![0];
+        bounds.extend_from_slice(splits);
+        bounds.push(units.len());
+        let mut scanners = bounds.windows(2).enumerate().map(|(idx, range)| {
+            let mut scanner = LineEndingScanner::new(if idx == 0 { bom } else { None });
+            scanner.feed(units[range[0]..range[1]].iter().copied());
+            scanner
+        });
+        let mut whole = scanners.next().unwrap();
+        for piece in scanners {
+            whole.append(piece);
+        }
+        whole.finish()
+    }
+
+    fn scan_whole(content: &[u8]) -> (Option<BomKind>, Vec<u32>, LineEndingStats) {
+        let (bom, units) = code_units(content);
+        let units: Vec<u32> = units.collect();
+        let stats = scan_pieces(bom, &units, &[]);
+        (bom, units, stats)
+    }
+
+    /// Whether a piece may start at `at`, which must not split a CRLF.
+    fn splittable(units: &[u32], at: usize) -> bool {
+        !(units[at - 1] == b'\r' as u32 && units[at] == b'\n' as u32)
+    }
+
+    #[test]
+    fn one_scan_counts_as_detect_line_endings() {
+        for input in INPUTS {
+            let (_, _, stats) = scan_whole(input);
+            let detected = detect_line_endings(input);
+            assert_eq!(
+                stats,
+                LineEndingStats {
+                    unicode: 0,
+                    ..detected
+                }
+            );
+        }
+    }
+
+    #[test]
+    fn two_pieces_append_as_one_scan() {
+        for input in INPUTS {
+            let (bom, units, whole) = scan_whole(input);
+            for at in (1..units.len()).filter(|&at| splittable(&units, at)) {
+                assert_eq!(
+                    scan_pieces(bom, &units, &[at]),
+                    whole,
+                    "{:?} split at {}",
+                    input,
+                    at
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn three_pieces_append_as_one_scan() {
+        for input in INPUTS {
+            let (bom, units, whole) = scan_whole(input);
+            for first in (1..units.len()).filter(|&at| splittable(&units, at)) {
+                for second in (first + 1..units.len()).filter(|&at| splittable(&units, at)) {
+                    assert_eq!(
+                        scan_pieces(bom, &units, &[first, second]),
+                        whole,
+                        "{:?} split at {} and {}",
+                        input,
+                        first,
+                        second
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn a_split_right_after_a_cr_keeps_its_position() {
+        let units: Vec<u32> = b"ab\ncd\ref".iter().map(|&b| b as u32).collect();
+        let stats = scan_pieces(None, &units, &[6]);
+        assert_eq!(stats.cr, 1);
+        assert_eq!(stats.lf, 1);
+        assert_eq!(stats.first_mixed_line, Some(2));
+        assert_eq!(
+            stats.first_lone_cr,
+            Some(TextPosition { line: 2, column: 3 })
+        );
+    }
+
+    #[test]
+    fn a_lone_cr_at_the_end_is_counted_once() {
+        let units: Vec<u32> = b"a\r\nb\r".iter().map(|&b| b as u32).collect();
+        for splits in [&[][..], &[3], &[4], &[3, 4]] {
+            let stats = scan_pieces(None, &units, splits);
+            assert_eq!((stats.crlf, stats.lf, stats.cr), (1, 0, 1), "{:?}", splits);
+            assert_eq!(
+                stats.first_lone_cr,
+                Some(TextPosition { line: 2, column: 2 })
+            );
+            assert!(stats.mixed);
+        }
+    }
+
+    #[test]
+    fn utf16le_pieces_split_on_code_units() {
+        let input = b"\xff\xfea\0\r\0\n\0b\0\r\0c\0";
+        let (bom, units, whole) = scan_whole(input);
+        assert_eq!(bom, Some(BomKind::Utf16Le));
+        assert_eq!((whole.crlf, whole.cr), (1, 1));
+        for at in [1, 3, 5] {
+            let stats = scan_pieces(bom, &units, &[at]);
+            assert_eq!(stats, whole, "split at {}", at);
+            assert_eq!(stats.bom, Some(BomKind::Utf16Le));
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//! Unified-diff preview of a conversion for `--diff`.
+
+use std::io::{self, Write};
+
+/// Lines of unchanged context shown around each change.
+const CONTEXT: usize = 3;
+
+/// Splits `content` into lines, each keeping its CRLF, LF or CR terminator.
+fn split_lines(content: &[u8]) -> Vec<&[u8]> {
+    let mut lines = Vec::new();
+    let mut start = 0;
+    let mut idx = 0;
+    while idx < content.len() {
+        match content[idx] {
+            b'\r' if content.get(idx + 1) == Some(&b'\n') => {
+                lines.push(&content[start..idx + 2]);
+                idx += 2;
+                start = idx;
+            }
+            b'\r' | b'\n' => {
+                lines.push(&content[start..idx + 1]);
+                idx += 1;
+                start = idx;
+            }
+            _ => idx += 1,
+        }
+    }
+    if start < content.len() {
+        lines.push(&content[start..]);
+    }
+    lines
+}
+
+/// Writes one diff line. The terminator is shown the way `cat -A` does, with
+/// a CR rendered as `^M`, so that lines differing only in their line ending
+/// remain distinguishable.
+fn write_line(out: &mut impl Write, prefix: u8, line: &[u8]) -> io::Result<()> {
+    let text_len = line
+        .iter()
+        .position(|&b| b == b'\r' || b == b'\n')
+        .unwrap_or(line.len());
+    out.write_all(&[prefix])?;
+    out.write_all(&line[..text_len])?;
+    if line[text_len..].contains(&b'\r') {
+        out.write_all(b"^M")?;
+    }
+    out.write_all(b"\n")?;
+    if text_len == line.len() {
+        out.write_all(b"\\ No newline at end of file\n")?;
+    }
+    Ok(())
+}
+
+/// Writes a unified diff between `old` and `new` to `out`, with `old_name`
+/// and `new_name` in the file headers. Writes nothing if the contents are
+/// equal.
+///
+/// Line ending conversion never changes how many lines a file has, so lines
+/// are compared pairwise rather than with a general diff algorithm.
+pub(crate) fn write_diff(
+    out: &mut impl Write,
+    old: &[u8],
+    new: &[u8],
+    old_name: &str,
+    new_name: &str,
+) -> io::Result<()> {
+    let old_lines = split_lines(old);
+    let new_lines = split_lines(new);
+    let len = old_lines.len().max(new_lines.len());
+    let changed: Vec<usize> = (0..len)
+        .filter(|&i| old_lines.get(i) != new_lines.get(i))
+        .collect();
+    if changed.is_empty() {
+        return Ok(());
+    }
+
+    writeln!(out, "--- {}", old_name)?;
+    writeln!(out, "+++ {}", new_name)?;
+
+    let mut next = 0;
+    while next < changed.len() {
+        // Extend the hunk while the following change is close enough that
+        // the context around the two would overlap.
+        let start = changed[next].saturating_sub(CONTEXT);
+        let mut last = next;
+        while last + 1 < changed.len() && changed[last + 1] <= changed[last] + 2 * CONTEXT {
+            last += 1;
+        }
+        let end = (changed[last] + 1 + CONTEXT).min(len);
+        next = last + 1;
+
+        let old_count = (start..end).filter(|&i| i < old_lines.len()).count();
+        let new_count = (start..end).filter(|&i| i < new_lines.len()).count();
+        writeln!(
+            out,
+            "@@ -{},{} +{},{} @@",
+            start + 1,
+            old_count,
+            start + 1,
+            new_count
+        )?;
+
+        let mut i = start;
+        while i < end {
+            if old_lines.get(i) == new_lines.get(i) {
+                write_line(out, b' ', old_lines[i])?;
+                i += 1;
+                continue;
+            }
+            let run_end = (i..end)
+                .find(|&j| old_lines.get(j) == new_lines.get(j))
+                .unwrap_or(end);
+            for line in old_lines.iter().take(run_end).skip(i) {
+                write_line(out, b'-', line)?;
+            }
+            for line in new_lines.iter().take(run_end).skip(i) {
+                write_line(out, b'+', line)?;
+            }
+            i = run_end;
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,149 @@
+//! Short codes for the kinds of errors a conversion can fail with, which
+//! stay the same whatever the language of the messages.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+/// The kind of error a file failed with, as a code such as `E_BINARY` that
+/// scripts can branch on, unlike the message, which may be translated.
+///
+/// The errors the library returns are [`io::Error`]s, and [`of`] finds the
+/// code of one: errors the library makes itself carry their code, and those
+/// of the operating system get one from their [`io::ErrorKind`].
+///
+/// [`of`]: ErrorCode::of
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum ErrorCode {
+    /// The file is binary, going by its content, format or extension.
+    Binary,
+    /// The file does not exist.
+    NotFound,
+    /// The operating system denied access to the file.
+    Permission,
+    /// The file is read-only and `force_readonly` is not set (Windows).
+    ReadOnly,
+    /// The file is a FIFO, device, socket or other special file, and
+    /// `force_special` is not set.
+    Special,
+    /// The owner of the file cannot be preserved and `allow_chown` is not set.
+    Owner,
+    /// The input is not valid in its encoding, such as UTF-16 with unpaired
+    /// surrogates.
+    InvalidEncoding,
+    /// The input is not valid UTF-8, with `check_utf8`.
+    NotUtf8,
+    /// The input has mixed line endings, with `strict`.
+    MixedLineEndings,
+    /// An encoding name is not known.
+    UnknownEncoding,
+    /// The output encoding cannot be written, or not by this build, such as
+    /// GB18030 without the `encoding` feature.
+    UnsupportedEncoding,
+    /// The text has characters the output encoding cannot represent.
+    Unmappable,
+    /// The paths given cannot be used, such as an input without an output
+    /// file in new-file mode.
+    Usage,
+    /// A file that was to be created already exists.
+    Exists,
+    /// Another program has the file locked or open in a way that keeps it
+    /// from being read or replaced, as happens on Windows, even after any
+    /// `retries`.
+    Locked,
+    /// The conversion was stopped by Ctrl-C or a signal to end the run.
+    Interrupted,
+    /// Any other error reading or writing a file.
+    Io,
+}
+
+impl ErrorCode {
+    /// The code of `error`.
+    pub fn of(error: &io::Error) -> ErrorCode {
+        if let Some(coded) = error
+            .get_ref()
+            .and_then(|inner| inner.downcast_ref::<Coded>())
+        {
+            return coded.code;
+        }
+        if is_locked(error) {
+            return ErrorCode::Locked;
+        }
+        match error.kind() {
+            io::ErrorKind::NotFound => ErrorCode::NotFound,
+            io::ErrorKind::PermissionDenied => ErrorCode::Permission,
+            io::ErrorKind::AlreadyExists => ErrorCode::Exists,
+            _ => ErrorCode::Io,
+        }
+    }
+
+    /// The code as printed, such as `E_BINARY`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ErrorCode::Binary => "E_BINARY",
+            ErrorCode::NotFound => "E_NOT_FOUND",
+            ErrorCode::Permission => "E_PERM",
+            ErrorCode::ReadOnly => "E_READONLY",
+            ErrorCode::Special => "E_SPECIAL",
+            ErrorCode::Owner => "E_OWNER",
+            ErrorCode::InvalidEncoding => "E_INVALID_ENCODING",
+            ErrorCode::NotUtf8 => "E_NOT_UTF8",
+            ErrorCode::MixedLineEndings => "E_MIXED_EOL",
+            ErrorCode::UnknownEncoding => "E_UNKNOWN_ENCODING",
+            ErrorCode::UnsupportedEncoding => "E_UNSUPPORTED_ENCODING",
+            ErrorCode::Unmappable => "E_UNMAPPABLE",
+            ErrorCode::Usage => "E_USAGE",
+            ErrorCode::Exists => "E_EXISTS",
+            ErrorCode::Locked => "E_LOCKED",
+            ErrorCode::Interrupted => "E_INTERRUPTED",
+            ErrorCode::Io => "E_IO",
+        }
+    }
+}
+
+impl fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The payload of an error made by the library, which displays as its
+/// message and carries its code.
+#[derive(Debug)]
+struct Coded {
+    code: ErrorCode,
+    message: String,
+}
+
+impl fmt::Display for Coded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl Error for Coded {}
+
+/// Whether `error` means another program has the file locked, which may
+/// pass: a sharing or lock violation on Windows, a running executable on
+/// Unix, or a lock that would have to be waited for.
+pub(crate) fn is_locked(error: &io::Error) -> bool {
+    #[cfg(windows)]
+    {
+        // ERROR_SHARING_VIOLATION and ERROR_LOCK_VIOLATION
+        if matches!(error.raw_os_error(), Some(32 | 33)) {
+            return true;
+        }
+    }
+    #[cfg(unix)]
+    {
+        if error.raw_os_error() == Some(libc::ETXTBSY) {
+            return true;
+        }
+    }
+    error.kind() == io::ErrorKind::WouldBlock
+}
+
+/// An error of `kind` with `message` and the code `code`.
+pub(crate) fn coded(kind: io::ErrorKind, code: ErrorCode, message: String) -> io::Error {
+    io::Error::new(kind, Coded { code, message })
+}
@@ -0,0 +1,33 @@
+//! GB18030 output for `--gb18030`.
+//!
+//! GB18030 needs no special care on input: the bytes of its two- and
+//! four-byte sequences are all 0x30 or above, so they never look like line
+//! breaks or binary control characters and pass through the byte-wise
+//! conversion unchanged. Only encoding Unicode text as GB18030 needs the
+//! mapping tables of `encoding_rs`, which the `encoding` feature enables.
+
+use std::io;
+
+/// The GB18030 encoding of U+FEFF.
+pub(crate) const BOM: &[u8] = &[0x84, 0x31, 0x95, 0x33];
+
+/// Appends `text` encoded as GB18030 to `output`.
+#[cfg(feature = "encoding")]
+pub(crate) fn encode(text: &str, output: &mut Vec<u8>, _progname: &str) -> io::Result<()> {
+    // GB18030 covers all of Unicode, so encoding cannot fail.
+    let (bytes, _, _) = encoding_rs::GB18030.encode(text);
+    output.extend_from_slice(&bytes);
+    Ok(())
+}
+
+#[cfg(not(feature = "encoding"))]
+pub(crate) fn encode(_text: &str, _output: &mut Vec<u8>, progname: &str) -> io::Result<()> {
+    Err(crate::error::coded(
+        io::ErrorKind::Unsupported,
+        crate::ErrorCode::UnsupportedEncoding,
+        crate::nls::tr!(
+            "{}: GB18030 output is not supported by this build (enable the `encoding` feature)",
+            progname
+        ),
+    ))
+}
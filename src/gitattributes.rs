@@ -0,0 +1,149 @@
+//! `.gitattributes` lookup for `--gitattributes`, which converts only the
+//! files whose line endings git itself would normalize.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::glob::matches_git_pattern;
+
+/// The state of an attribute for a path, as described in gitattributes(5).
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) enum State {
+    #[default]
+    Unspecified,
+    Set,
+    Unset,
+    Value(String),
+}
+
+/// The attributes that decide how git treats a file's line endings.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(crate) struct Attributes {
+    pub(crate) text: State,
+    pub(crate) eol: State,
+}
+
+impl Attributes {
+    /// Whether git would give the file `eol` (`lf` or `crlf`) line endings.
+    /// That is the case for a file with `eol` set to it, and for a `text`
+    /// or `text=auto` file without any `eol`. Files that are `-text` or
+    /// `binary`, have the other `eol`, or have no attributes are left to
+    /// git's own heuristics and do not count.
+    pub(crate) fn wants(&self, eol: &str) -> bool {
+        match (&self.text, &self.eol) {
+            (State::Unset, _) => false,
+            (_, State::Value(value)) => value == eol,
+            (State::Set | State::Value(_), _) => true,
+            _ => false,
+        }
+    }
+}
+
+/// One line of an attributes file.
+struct Rule {
+    pattern: String,
+    attributes: Vec<(&'static str, State)>,
+}
+
+/// Parses the contents of an attributes file, keeping just the `text` and
+/// `eol` attributes and the `binary` macro, which unsets `text`.
+fn parse(content: &str) -> Vec<Rule> {
+    let mut rules = Vec::new();
+    for line in content.lines() {
+        let mut fields = line.split_whitespace();
+        let Some(pattern) = fields.next() else {
+            continue;
+        };
+        // Comments, macro definitions, and the quoted and negated patterns
+        // this simple reader does not handle
+        if pattern.starts_with(['#', '"', '!']) || pattern.starts_with("[attr]") {
+            continue;
+        }
+        let mut attributes = Vec::new();
+        for field in fields {
+            let (name, state) = if let Some(name) = field.strip_prefix('-') {
+                (name, State::Unset)
+            } else if let Some(name) = field.strip_prefix('!') {
+                (name, State::Unspecified)
+            } else if let Some((name, value)) = field.split_once('=') {
+                (name, State::Value(value.to_string()))
+            } else {
+                (field, State::Set)
+            };
+            match (name, state) {
+                ("text", state) => attributes.push(("text", state)),
+                ("eol", state) => attributes.push(("eol", state)),
+                ("binary", State::Set) => attributes.push(("text", State::Unset)),
+                _ => {}
+            }
+        }
+        if !attributes.is_empty() {
+            rules.push(Rule {
+                pattern: pattern.to_string(),
+                attributes,
+            });
+        }
+    }
+    rules
+}
+
+/// Looks up attributes, reading each attributes file only once.
+#[derive(Default)]
+pub(crate) struct GitAttributes {
+    files: HashMap<PathBuf, Vec<Rule>>,
+}
+
+impl GitAttributes {
+    /// The attributes of the file at `path`.
+    ///
+    /// The `.gitattributes` files of the directories from the repository
+    /// root down to the file are applied in that order, followed by
+    /// `.git/info/attributes`, so that the more specific ones win. Outside a
+    /// repository, every directory up to the file system root is searched.
+    pub(crate) fn lookup(&mut self, path: &Path) -> Attributes {
+        let mut attributes = Attributes::default();
+        let Ok(path) = std::path::absolute(path) else {
+            return attributes;
+        };
+        let mut dirs = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            dirs.push(dir);
+            if dir.join(".git").exists() {
+                break;
+            }
+        }
+        for &dir in dirs.iter().rev() {
+            self.apply(&dir.join(".gitattributes"), dir, &path, &mut attributes);
+        }
+        if let Some(&root) = dirs.last() {
+            let info = root.join(".git").join("info").join("attributes");
+            self.apply(&info, root, &path, &mut attributes);
+        }
+        attributes
+    }
+
+    /// Applies the rules of the attributes file `file`, whose patterns are
+    /// relative to `dir`, that match `path`.
+    fn apply(&mut self, file: &Path, dir: &Path, path: &Path, attributes: &mut Attributes) {
+        let rules = self.files.entry(file.to_path_buf()).or_insert_with(|| {
+            fs::read_to_string(file)
+                .map(|content| parse(&content))
+                .unwrap_or_default()
+        });
+        let Ok(relative) = path.strip_prefix(dir) else {
+            return;
+        };
+        for rule in rules.iter() {
+            if !matches_git_pattern(&rule.pattern, relative) {
+                continue;
+            }
+            for (name, state) in &rule.attributes {
+                match *name {
+                    "text" => attributes.text = state.clone(),
+                    _ => attributes.eol = state.clone(),
+                }
+            }
+        }
+    }
+}
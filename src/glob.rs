@@ -0,0 +1,218 @@
+//! Wildcard patterns for file arguments, which `cmd.exe` and PowerShell pass
+//! through unexpanded, and for the `--include` and `--exclude` filters.
+//!
+//! `*` matches any run of characters within a path component and `?` any one
+//! character, `[abc]`, `[a-z]` and `[!abc]` match one character of a set,
+//! and a component that is exactly `**` matches any number of directories.
+//! As in Unix shells, wildcards do not match a leading `.`. Matching ignores
+//! case on Windows, whose file systems do too.
+
+use std::ffi::{OsStr, OsString};
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+/// Whether `pattern` contains any wildcard characters.
+pub(crate) fn has_wildcards(pattern: &str) -> bool {
+    pattern.contains(['*', '?', '['])
+}
+
+/// The existing paths matching `pattern`, in sorted order. Names that are
+/// not valid Unicode are matched with their invalid parts replaced, but
+/// returned as they are.
+pub(crate) fn expand(pattern: &Path) -> Vec<PathBuf> {
+    let mut base = PathBuf::new();
+    let mut parts = Vec::new();
+    for component in pattern.components() {
+        match component {
+            Component::Normal(part)
+                if parts.is_empty() && !has_wildcards(&part.to_string_lossy()) =>
+            {
+                base.push(part)
+            }
+            Component::Normal(part) => parts.push(part),
+            other if parts.is_empty() => base.push(other),
+            other => parts.push(other.as_os_str()),
+        }
+    }
+    let mut matches = Vec::new();
+    expand_from(&base, &parts, &mut matches);
+    matches.sort();
+    matches.dedup();
+    matches
+}
+
+fn expand_from(base: &Path, parts: &[&OsStr], matches: &mut Vec<PathBuf>) {
+    let Some((&part, rest)) = parts.split_first() else {
+        matches.push(base.to_path_buf());
+        return;
+    };
+    let dir = if base.as_os_str().is_empty() {
+        Path::new(".")
+    } else {
+        base
+    };
+    let pattern = part.to_string_lossy();
+    if !has_wildcards(&pattern) {
+        let path = base.join(part);
+        if (rest.is_empty() && path.exists()) || path.is_dir() {
+            expand_from(&path, rest, matches);
+        }
+        return;
+    }
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let mut names: Vec<OsString> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name())
+        .collect();
+    names.sort();
+    if pattern == "**" {
+        // Zero directories, then one more level with `**` still in place
+        expand_from(base, rest, matches);
+        for name in names
+            .iter()
+            .filter(|name| !name.to_string_lossy().starts_with('.'))
+        {
+            let path = base.join(name);
+            if path.is_dir() {
+                expand_from(&path, parts, matches);
+            }
+        }
+        return;
+    }
+    for name in names
+        .iter()
+        .filter(|name| matches_component(&pattern, &name.to_string_lossy()))
+    {
+        let path = base.join(name);
+        if rest.is_empty() || path.is_dir() {
+            expand_from(&path, rest, matches);
+        }
+    }
+}
+
+/// Whether `path`, relative to the directory being searched, matches
+/// `pattern`. A pattern without a `/` is matched against the last component
+/// of `path` only, and one ending in `/` only matches directories.
+pub(crate) fn matches_path(pattern: &str, path: &Path, is_dir: bool) -> bool {
+    path_matches(pattern, path, is_dir, false)
+}
+
+/// Whether the file `path`, relative to the directory of a `.gitattributes`
+/// file, matches `pattern` from that file. Unlike in [`matches_path`],
+/// wildcards match a leading `.`, as they do for git.
+pub(crate) fn matches_git_pattern(pattern: &str, path: &Path) -> bool {
+    path_matches(pattern, path, false, true)
+}
+
+fn path_matches(pattern: &str, path: &Path, is_dir: bool, dotfiles: bool) -> bool {
+    let pattern = if cfg!(windows) {
+        pattern.replace('\\', "/")
+    } else {
+        pattern.to_string()
+    };
+    let (pattern, dir_only) = match pattern.strip_suffix('/') {
+        Some(pattern) => (pattern, true),
+        None => (pattern.as_str(), false),
+    };
+    if dir_only && !is_dir {
+        return false;
+    }
+    let names: Vec<String> = path
+        .components()
+        .map(|component| component.as_os_str().to_string_lossy().into_owned())
+        .collect();
+    if !pattern.contains('/') {
+        return names
+            .last()
+            .is_some_and(|name| matches_name(pattern, name, dotfiles));
+    }
+    let parts: Vec<&str> = pattern.split('/').filter(|part| !part.is_empty()).collect();
+    matches_parts(&parts, &names, dotfiles)
+}
+
+fn matches_parts(parts: &[&str], names: &[String], dotfiles: bool) -> bool {
+    match parts.split_first() {
+        None => names.is_empty(),
+        Some((&"**", rest)) => {
+            (0..=names.len()).any(|skip| matches_parts(rest, &names[skip..], dotfiles))
+        }
+        Some((part, rest)) => {
+            names
+                .first()
+                .is_some_and(|name| matches_name(part, name, dotfiles))
+                && matches_parts(rest, &names[1..], dotfiles)
+        }
+    }
+}
+
+/// Whether the file name `name` matches the single-component pattern
+/// `pattern`.
+pub(crate) fn matches_component(pattern: &str, name: &str) -> bool {
+    matches_name(pattern, name, false)
+}
+
+fn matches_name(pattern: &str, name: &str, dotfiles: bool) -> bool {
+    if !dotfiles && name.starts_with('.') && !pattern.starts_with('.') {
+        return false;
+    }
+    let pattern: Vec<char> = pattern.chars().map(fold_case).collect();
+    let name: Vec<char> = name.chars().map(fold_case).collect();
+    match_chars(&pattern, &name)
+}
+
+fn fold_case(c: char) -> char {
+    if cfg!(windows) {
+        c.to_ascii_lowercase()
+    } else {
+        c
+    }
+}
+
+fn match_chars(pattern: &[char], name: &[char]) -> bool {
+    match pattern.split_first() {
+        None => name.is_empty(),
+        Some(('*', rest)) => (0..=name.len()).any(|skip| match_chars(rest, &name[skip..])),
+        Some(('?', rest)) => !name.is_empty() && match_chars(rest, &name[1..]),
+        Some(('[', rest)) => match (name.split_first(), match_set(rest)) {
+            (Some((&c, name_rest)), Some((set, negated, after))) => {
+                set_contains(set, c) != negated && match_chars(after, name_rest)
+            }
+            // An unterminated `[` is an ordinary character
+            (Some((&'[', name_rest)), None) => match_chars(rest, name_rest),
+            _ => false,
+        },
+        Some((&p, rest)) => name.first() == Some(&p) && match_chars(rest, &name[1..]),
+    }
+}
+
+/// Splits the part of a pattern after a `[` into the set up to the closing
+/// `]`, whether it starts with `!`, and what follows it. A `]` right after
+/// the `[` or `[!` is part of the set.
+fn match_set(pattern: &[char]) -> Option<(&[char], bool, &[char])> {
+    let (negated, pattern) = match pattern.split_first() {
+        Some(('!', rest)) => (true, rest),
+        _ => (false, pattern),
+    };
+    let end = 1 + pattern.iter().skip(1).position(|&c| c == ']')?;
+    Some((&pattern[..end], negated, &pattern[end + 1..]))
+}
+
+fn set_contains(set: &[char], c: char) -> bool {
+    let mut idx = 0;
+    while idx < set.len() {
+        if idx + 2 < set.len() && set[idx + 1] == '-' {
+            if (set[idx]..=set[idx + 2]).contains(&c) {
+                return true;
+            }
+            idx += 3;
+        } else {
+            if set[idx] == c {
+                return true;
+            }
+            idx += 1;
+        }
+    }
+    false
+}
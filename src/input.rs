@@ -0,0 +1,210 @@
+//! Opening an input file, once it is known to be a file that can be read,
+//! and reading it whole, into memory or, for `--mmap`, by mapping it.
+
+use std::fs::{File, FileType, Metadata};
+use std::io::{self, Read};
+use std::ops::Deref;
+use std::path::Path;
+
+use memmap2::Mmap;
+
+use crate::error::{coded, ErrorCode};
+use crate::nls::{gettext, tr};
+
+/// The content of a file read by [`read_file`].
+pub(crate) enum FileContent {
+    Read(Vec<u8>),
+    Mapped(Mmap),
+}
+
+impl Deref for FileContent {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            FileContent::Read(content) => content,
+            FileContent::Mapped(map) => map,
+        }
+    }
+}
+
+/// Reads all of `file` from where it is at. With `mmap`, the file is
+/// mapped into memory instead, which saves copying it and lets the system
+/// page it in and out as needed. Files that cannot be mapped, such as empty
+/// files and pipes, are read as usual.
+pub(crate) fn read_file(mut file: &File, mmap: bool) -> io::Result<FileContent> {
+    if mmap && file.metadata()?.len() > 0 {
+        // SAFETY: the map is only read from, and dropped before the file
+        // is written. Another process changing the file meanwhile can
+        // change what is read, as it could for a plain read.
+        if let Ok(map) = unsafe { Mmap::map(file) } {
+            return Ok(FileContent::Mapped(map));
+        }
+    }
+    let mut content = Vec::new();
+    file.read_to_end(&mut content)?;
+    Ok(FileContent::Read(content))
+}
+
+/// Opens the file at `path`, shown as `shown`, for reading, and with
+/// `write` for writing too, and returns it with its metadata. Unless
+/// `force_special`, a FIFO, device or socket is refused: opening a FIFO
+/// waits for a writer, possibly forever, and a device such as `/dev/zero`
+/// never ends. A directory is let through, as it fails to read by itself.
+///
+/// The file is judged by the handle opened, which is the one to read from
+/// and write to, so that it cannot be swapped for another in between. On
+/// Unix it is opened with `O_NONBLOCK` unless `force_special`, so that a
+/// FIFO does not wait for a writer before it is refused, and with
+/// `nofollow` with `O_NOFOLLOW`, which refuses a symbolic link, for a file
+/// converted in place whose links were resolved already.
+pub(crate) fn open_input(
+    path: &Path,
+    shown: &Path,
+    force_special: bool,
+    nofollow: bool,
+    write: bool,
+) -> io::Result<(File, Metadata)> {
+    let mut open_options = File::options();
+    open_options.read(true).write(write);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        let nonblock = if force_special { 0 } else { libc::O_NONBLOCK };
+        let nofollow = if nofollow { libc::O_NOFOLLOW } else { 0 };
+        open_options.custom_flags(nonblock | nofollow);
+    }
+    #[cfg(not(unix))]
+    let _ = nofollow;
+    let file = match open_options.open(path) {
+        Ok(file) => file,
+        Err(e) if is_link_refused(&e, path) => {
+            return Err(coded(
+                io::ErrorKind::InvalidInput,
+                ErrorCode::Special,
+                tr!(
+                    "'{}' is a symbolic link, which converting in place would replace",
+                    shown.display()
+                ),
+            ))
+        }
+        Err(e) => return Err(e),
+    };
+    let metadata = file.metadata()?;
+    if force_special {
+        return Ok((file, metadata));
+    }
+    if !metadata.is_file() && !metadata.is_dir() {
+        return Err(coded(
+            io::ErrorKind::InvalidInput,
+            ErrorCode::Special,
+            tr!(
+                "'{}' is not a regular file but a {} (use --force-special to read it anyway)",
+                shown.display(),
+                special_kind(metadata.file_type())
+            ),
+        ));
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::io::AsRawFd;
+
+        // Reads are to wait as usual from here on
+        let fd = file.as_raw_fd();
+        // SAFETY: `fd` is open for as long as `file` is
+        unsafe {
+            let flags = libc::fcntl(fd, libc::F_GETFL);
+            if flags == -1 || libc::fcntl(fd, libc::F_SETFL, flags & !libc::O_NONBLOCK) == -1 {
+                return Err(io::Error::last_os_error());
+            }
+        }
+    }
+    Ok((file, metadata))
+}
+
+/// Whether `error`, from opening `path` with `O_NOFOLLOW`, means that it is
+/// a symbolic link.
+fn is_link_refused(error: &io::Error, path: &Path) -> bool {
+    #[cfg(unix)]
+    {
+        error.raw_os_error() == Some(libc::ELOOP)
+            && std::fs::symlink_metadata(path).is_ok_and(|metadata| metadata.is_symlink())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (error, path);
+        false
+    }
+}
+
+/// What kind of special file `file_type` is, as in "FIFO".
+#[cfg(unix)]
+fn special_kind(file_type: FileType) -> &'static str {
+    use std::os::unix::fs::FileTypeExt;
+
+    if file_type.is_fifo() {
+        gettext("FIFO")
+    } else if file_type.is_char_device() {
+        gettext("character device")
+    } else if file_type.is_block_device() {
+        gettext("block device")
+    } else if file_type.is_socket() {
+        gettext("socket")
+    } else {
+        gettext("special file")
+    }
+}
+
+#[cfg(not(unix))]
+fn special_kind(_file_type: FileType) -> &'static str {
+    gettext("special file")
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::ErrorCode;
+
+    /// A directory of its own for the test `name`.
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dos2unix-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn a_fifo_is_refused_without_waiting_for_a_writer() {
+        let dir = scratch("fifo");
+        let fifo = dir.join("fifo");
+        let path = std::ffi::CString::new(fifo.as_os_str().as_encoded_bytes()).unwrap();
+        assert_eq!(unsafe { libc::mkfifo(path.as_ptr(), 0o600) }, 0);
+        let result = open_input(&fifo, Path::new("fifo"), false, false, false);
+        fs::remove_dir_all(&dir).unwrap();
+        let error = result.unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::Special);
+        assert_eq!(
+            error.to_string(),
+            "'fifo' is not a regular file but a FIFO (use --force-special to read it anyway)"
+        );
+    }
+
+    #[test]
+    fn a_link_is_refused_with_nofollow() {
+        let dir = scratch("link");
+        let (file, link) = (dir.join("file.txt"), dir.join("link.txt"));
+        fs::write(&file, "a\r\n").unwrap();
+        std::os::unix::fs::symlink(&file, &link).unwrap();
+        let followed = open_input(&link, &link, false, false, false).map(|(file, _)| file);
+        let refused = open_input(&link, &link, false, true, false);
+        let forced = open_input(&link, &link, true, true, true);
+        fs::remove_dir_all(&dir).unwrap();
+        let content = read_file(&followed.unwrap(), false).unwrap();
+        assert_eq!(&content[..], b"a\r\n");
+        assert_eq!(ErrorCode::of(&refused.unwrap_err()), ErrorCode::Special);
+        assert_eq!(ErrorCode::of(&forced.unwrap_err()), ErrorCode::Special);
+    }
+}
@@ -1,6 +1,66 @@
-use std::io;
+use std::io::{self, Seek, Write};
 use std::fs;
-use std::path::Path;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+mod backup;
+mod binary;
+mod bom;
+#[cfg(feature = "bytes")]
+mod buffer;
+pub mod cli;
+mod codepage;
+mod color;
+mod config;
+mod detect;
+mod diff;
+mod error;
+mod gb18030;
+mod gitattributes;
+mod glob;
+mod input;
+mod json;
+mod lines;
+mod lock;
+mod magic;
+mod nls;
+mod progress;
+mod report;
+mod signal;
+mod summary;
+mod stream;
+mod temp;
+mod transcode;
+mod units;
+mod walk;
+mod writer;
+
+pub use backup::BackupNaming;
+pub use binary::BinaryPolicy;
+pub use bom::BomKind;
+#[cfg(feature = "bytes")]
+pub use buffer::convert_bytes;
+pub use codepage::{CharConversion, CodePage};
+pub use detect::{check_utf8, detect_line_endings, InvalidUtf8, LineEndingStats, TextPosition};
+pub use error::ErrorCode;
+pub use lines::{Dos2UnixExt, UnixLines};
+pub use magic::{detect_file_type, DEFAULT_SKIP_EXTENSIONS};
+pub use nls::localize_messages;
+pub use report::{Reporter, StderrReporter};
+#[cfg(feature = "tracing")]
+pub use report::TracingReporter;
+pub use stream::{DEFAULT_BUFFER_SIZE, STREAM_THRESHOLD};
+pub use summary::{FileOutcome, Summary};
+pub use writer::ConvertingWriter;
+
+use binary::BinaryScan;
+use error::coded;
+use lock::FileLock;
+use nls::tr;
+use temp::TempFile;
+use units::{decode_units, encode_units, CodeUnit};
 
 #[cfg(windows)]
 use winapi::um::consoleapi::GetConsoleMode;
@@ -13,48 +73,377 @@ use winapi::um::winbase::STD_INPUT_HANDLE;
 #[cfg(windows)]
 use winapi::um::winnt::HANDLE;
 
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum ConversionMode {
     ToUnix,
     ToDos,
     ToMac,
 }
 
+/// A line break to convert to, named by its characters rather than by the
+/// system it comes from.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TargetNewline {
+    /// LF, as on Unix.
+    Lf,
+    /// CRLF, as on DOS and Windows.
+    Crlf,
+    /// A lone CR, as on classic Mac OS.
+    Cr,
+}
+
+impl TargetNewline {
+    /// Parses a line break name, `lf`, `crlf` or `cr`, in any case.
+    pub fn from_name(name: &str) -> Option<TargetNewline> {
+        match name.to_ascii_lowercase().as_str() {
+            "lf" => Some(TargetNewline::Lf),
+            "crlf" => Some(TargetNewline::Crlf),
+            "cr" => Some(TargetNewline::Cr),
+            _ => None,
+        }
+    }
+
+    /// The conversion that writes this line break.
+    pub fn conversion_mode(self) -> ConversionMode {
+        match self {
+            TargetNewline::Lf => ConversionMode::ToUnix,
+            TargetNewline::Crlf => ConversionMode::ToDos,
+            TargetNewline::Cr => ConversionMode::ToMac,
+        }
+    }
+}
+
+/// Returns true if `content` contains control characters that do not occur
+/// in text files. This is the same test [`detect_binary`] applies.
+pub fn is_binary(content: &[u8]) -> bool {
+    is_binary_with(content, &BinaryPolicy::default())
+}
+
+/// Like [`is_binary`], with the characters that count as binary chosen by
+/// `policy`.
+pub fn is_binary_with(content: &[u8], policy: &BinaryPolicy) -> bool {
+    fn has_binary_units<T: CodeUnit>(
+        body: &[u8],
+        little_endian: bool,
+        policy: &BinaryPolicy,
+    ) -> bool {
+        let (units, _) = decode_units::<T>(body, little_endian);
+        policy.find_binary(&units).is_some()
+    }
+
+    match BomKind::detect(content) {
+        Some(BomKind::Utf8) => policy.find_binary(&content[3..]).is_some(),
+        Some(bom) => {
+            let body = &content[bom.bytes().len()..];
+            if bom.unit_size() == 2 {
+                has_binary_units::<u16>(body, bom.is_little_endian(), policy)
+            } else {
+                has_binary_units::<u32>(body, bom.is_little_endian(), policy)
+            }
+        }
+        None => policy.find_binary(content).is_some(),
+    }
+}
+
 pub fn detect_binary(
     content: &[u8],
     force: bool,
     verbose: usize,
     progname: &str,
 ) -> io::Result<()> {
-    let mut line_number = 1;
-    for &byte in content {
-        if byte < 32 && byte != b'\n' && byte != b'\r' && byte != b'\t' && byte != 0x0C {
-            if !force {
-                let error_msg = format!(
-                    "{}: Binary symbol 0x{:02X} found at line {}",
-                    progname, byte, line_number
-                );
-                if verbose > 0 {
-                    eprintln!("{}", error_msg);
-                }
-                return Err(io::Error::new(io::ErrorKind::InvalidData, error_msg));
-            } else {
-                if verbose > 0 {
-                    eprintln!(
-                        "{}: Binary symbol 0x{:02X} found at line {}; continuing due to --force.",
-                        progname, byte, line_number
-                    );
-                }
-                break;
-            }
-        }
-        if byte == b'\n' {
-            line_number += 1;
+    let policy = BinaryPolicy::default();
+    detect_binary_units(content, &policy, force, verbose, &StderrReporter, progname)
+}
+
+fn detect_binary_units<T: CodeUnit>(
+    content: &[T],
+    policy: &BinaryPolicy,
+    force: bool,
+    verbose: usize,
+    reporter: &dyn Reporter,
+    progname: &str,
+) -> io::Result<()> {
+    if let Some(idx) = policy.find_binary(content) {
+        let line_number = content[..idx]
+            .iter()
+            .filter(|&&unit| unit == T::from_ascii(b'\n'))
+            .count()
+            + 1;
+        let value = content[idx].value();
+        return binary_found(value, line_number, force, verbose, reporter, progname);
+    }
+    Ok(())
+}
+
+/// Reports the binary symbol `value` found at `line_number` to `reporter`,
+/// which is an error unless `force` is set.
+fn binary_found(
+    value: u32,
+    line_number: usize,
+    force: bool,
+    verbose: usize,
+    reporter: &dyn Reporter,
+    progname: &str,
+) -> io::Result<()> {
+    if !force {
+        let error_msg = tr!(
+            "{}: Binary symbol 0x{} found at line {}",
+            progname, format!("{:02X}", value), line_number
+        );
+        if verbose > 1 {
+            reporter.report(2, &error_msg);
         }
+        return Err(coded(io::ErrorKind::InvalidData, ErrorCode::Binary, error_msg));
+    } else if verbose > 1 {
+        reporter.report(2, &tr!(
+            "{}: Binary symbol 0x{} found at line {}; continuing due to --force.",
+            progname, format!("{:02X}", value), line_number
+        ));
     }
     Ok(())
 }
 
+/// How long [`process_file_with_options`] waits by default before trying a
+/// locked file again, as set by [`ConversionOptions::retry_delay`].
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+/// Settings for [`convert_with_options`] and [`process_file_with_options`].
+#[derive(Clone, Debug)]
+pub struct ConversionOptions {
+    pub conversion_mode: ConversionMode,
+    /// Keep a UTF-8 BOM instead of dropping it.
+    pub keep_bom: bool,
+    /// Drop the BOM of UTF-16 and UTF-32 input too, even though the output
+    /// then no longer identifies its encoding. Overrides `keep_bom` and
+    /// `add_bom`.
+    pub remove_bom: bool,
+    /// Start UTF-8 output with a BOM even if the input has none.
+    pub add_bom: bool,
+    /// Convert files even if they look binary.
+    pub force: bool,
+    /// What makes a file look binary.
+    pub binary_policy: BinaryPolicy,
+    /// Let [`process_file_with_options`] skip files of well-known binary
+    /// formats, as recognized by [`detect_file_type`], without reading them
+    /// in full. Has no effect with `force`.
+    pub sniff_file_type: bool,
+    /// Let [`process_file_with_options`] skip files with these extensions
+    /// without opening them. Defaults to [`DEFAULT_SKIP_EXTENSIONS`]. Has no
+    /// effect with `force`.
+    pub skip_extensions: Vec<String>,
+    /// Copy the input to a backup file before overwriting it, named as
+    /// `backup_naming` says.
+    pub backup: bool,
+    /// How the backup files of `backup` are named. Defaults to the
+    /// [`backup_path`] of the input.
+    pub backup_naming: BackupNaming,
+    /// Give the output of [`process_file_with_options`] the modification and
+    /// access times of the input instead of the current time.
+    pub keep_date: bool,
+    /// When converting in place on Unix, go ahead even if the owner and group
+    /// of the file cannot be preserved, as when a user converts a file owned
+    /// by someone else. Without it such a file is left alone.
+    pub allow_chown: bool,
+    /// When converting to a separate output file, refuse to overwrite one
+    /// that already exists, even if it appears while the conversion runs.
+    pub no_clobber: bool,
+    /// Flush the output to disk before it replaces the output file, and the
+    /// directory entry after, so a crash leaves either the old or the new
+    /// content in place.
+    pub sync: bool,
+    /// Convert a file in place by rewriting it through its own descriptor
+    /// rather than replacing it with a new file. This keeps its inode, and
+    /// so its hard links, at the cost of atomicity: an interrupted
+    /// conversion can leave the file partly written.
+    pub in_place: bool,
+    /// On Windows, clear the read-only attribute of a file so it can be
+    /// converted, and set it again afterwards. Without it a read-only file
+    /// is reported and left alone. Has no effect elsewhere, where a file's
+    /// permissions do not keep it from being replaced.
+    pub force_readonly: bool,
+    /// Read FIFOs, devices, sockets and other special files, which are
+    /// otherwise reported and left alone: reading a FIFO waits for a writer,
+    /// and reading a device such as `/dev/zero` may never end. A file
+    /// converted in place that is a symbolic link is refused either way, on
+    /// Unix, as replacing it would turn the link into a regular file.
+    pub force_special: bool,
+    /// Hold an exclusive advisory lock on the input file, `flock` on Unix,
+    /// from before it is read until its output has replaced it, so that
+    /// another run with `lock` cannot convert it at the same time and lose
+    /// what one of them wrote. A file locked by another program fails with
+    /// [`ErrorCode::Locked`], or is tried again as set by `retries`.
+    pub lock: bool,
+    /// How many more times to try opening or replacing a file that another
+    /// program has locked, as editors and virus scanners do on Windows,
+    /// before failing with [`ErrorCode::Locked`]. Defaults to 0.
+    pub retries: usize,
+    /// How long to wait before each of the `retries`. Defaults to
+    /// [`DEFAULT_RETRY_DELAY`].
+    pub retry_delay: Duration,
+    /// Let [`process_file_with_options`] convert files of any size a chunk
+    /// at a time as it reads them, as it does for files larger than
+    /// [`STREAM_THRESHOLD`], instead of reading them into memory whole.
+    /// UTF-16 and UTF-32 files, and settings that need the whole input such
+    /// as `auto`, `check_utf8`, `in_place` and transcoding, are still
+    /// converted in memory.
+    pub stream: bool,
+    /// Map input files into memory instead of reading them, where the
+    /// system allows it.
+    pub mmap: bool,
+    /// Size in bytes of the pieces the conversions that stream, in chunks or
+    /// from stdin, read and write at a time. Larger pieces suit network
+    /// file systems, smaller ones systems short of memory. Defaults to
+    /// [`DEFAULT_BUFFER_SIZE`].
+    pub buffer_size: usize,
+    /// Number of threads a file converted a chunk at a time is converted
+    /// on, each taking whole chunks of `buffer_size` or so. Defaults to 1.
+    /// With `verbose` above 1, whose messages follow the input line by line,
+    /// a single thread is used.
+    pub threads: usize,
+    /// Terminate an unterminated last line.
+    pub add_eol: bool,
+    /// Transcode UTF-16 and UTF-32 input to UTF-8 output.
+    pub to_utf8: bool,
+    /// Encoding of the output, announced by its BOM. Input in any other
+    /// encoding is transcoded, and the BOM is written unless `remove_bom` is
+    /// set. Overrides `to_utf8`.
+    pub bom: Option<BomKind>,
+    /// Transcode UTF-16 and UTF-32 input to GB18030 instead of UTF-8. A
+    /// GB18030 BOM is written where a UTF-8 one would be. Overrides
+    /// `to_utf8`, and requires the `encoding` feature.
+    pub gb18030: bool,
+    /// Encoding of the input, as an `encoding_rs` label such as `shift_jis`.
+    /// Without it, the input is UTF-8 unless its BOM says otherwise.
+    pub from_encoding: Option<String>,
+    /// Encoding of the output. Setting either this or `from_encoding`
+    /// overrides `bom`, `gb18030`, `to_utf8` and `char_conversion`; the output
+    /// is then UTF-8 unless this says otherwise. Encodings other than UTF-8,
+    /// UTF-16 and UTF-32 require the `encoding` feature.
+    pub to_encoding: Option<String>,
+    /// Refuse to convert input that is not well-formed UTF-8, as checked by
+    /// [`check_utf8`].
+    pub check_utf8: bool,
+    /// Refuse to convert input with more than one kind of line break.
+    pub strict: bool,
+    /// Rewrite every line break, whether CRLF, LF or a lone CR, to the one
+    /// of `conversion_mode`, instead of only those the mode normally
+    /// converts.
+    pub normalize: bool,
+    /// Normalize each input to its own most common kind of line break, as
+    /// found by [`LineEndingStats::dominant_mode`], instead of to
+    /// `conversion_mode`. Input without line breaks keeps `conversion_mode`.
+    pub auto: bool,
+    /// Convert the Unicode line breaks NEL (U+0085), LINE SEPARATOR (U+2028)
+    /// and PARAGRAPH SEPARATOR (U+2029) to the target line break.
+    pub unicode_breaks: bool,
+    /// When converting to Unix line endings, remove CRs that are not part of
+    /// a CRLF instead of keeping them.
+    pub strip_cr: bool,
+    /// How the characters of files without a BOM are converted.
+    pub char_conversion: CharConversion,
+    /// 0 is silent, 1 reports each file as GNU dos2unix does by default, 2
+    /// adds details such as its BOM and the number of line breaks converted,
+    /// as GNU dos2unix does with `-v`, and 3 and up report each line break.
+    pub verbose: usize,
+    /// Prefix for messages.
+    pub progname: String,
+    /// Receives the messages `verbose` asks for, [`StderrReporter`] unless
+    /// set otherwise.
+    pub reporter: Arc<dyn Reporter>,
+}
+
+impl ConversionOptions {
+    pub fn new(conversion_mode: ConversionMode) -> Self {
+        ConversionOptions {
+            conversion_mode,
+            keep_bom: false,
+            remove_bom: false,
+            add_bom: false,
+            force: false,
+            binary_policy: BinaryPolicy::default(),
+            sniff_file_type: true,
+            skip_extensions: DEFAULT_SKIP_EXTENSIONS
+                .iter()
+                .map(|extension| extension.to_string())
+                .collect(),
+            backup: false,
+            backup_naming: BackupNaming::default(),
+            keep_date: false,
+            allow_chown: false,
+            no_clobber: false,
+            sync: false,
+            in_place: false,
+            force_readonly: false,
+            force_special: false,
+            lock: false,
+            retries: 0,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            stream: false,
+            mmap: false,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            threads: 1,
+            add_eol: false,
+            to_utf8: false,
+            bom: None,
+            gb18030: false,
+            from_encoding: None,
+            to_encoding: None,
+            check_utf8: false,
+            strict: false,
+            normalize: false,
+            auto: false,
+            unicode_breaks: false,
+            strip_cr: false,
+            char_conversion: CharConversion::Ascii,
+            verbose: 0,
+            progname: String::from("dos2unix"),
+            reporter: Arc::new(StderrReporter),
+        }
+    }
+
+    /// Whether UTF-8 output starts with a BOM, given whether the input had
+    /// one.
+    fn writes_utf8_bom(&self, input_has_bom: bool) -> bool {
+        !self.remove_bom
+            && (self.add_bom || self.bom.is_some() || (self.keep_bom && input_has_bom))
+    }
+
+    /// The encoding that input encoded as `input` is transcoded to, if it
+    /// needs transcoding at all.
+    fn target_encoding(&self, input: BomKind) -> Option<BomKind> {
+        let target = match self.bom {
+            Some(bom) => bom,
+            None if self.gb18030 => return None,
+            None if self.to_utf8 => BomKind::Utf8,
+            None => return None,
+        };
+        Some(target).filter(|&target| target != input)
+    }
+}
+
+/// Appends `text` to `result` in `encoding`, after its BOM if `with_bom` is
+/// set.
+fn write_transcoded(text: &str, encoding: BomKind, with_bom: bool, result: &mut Vec<u8>) {
+    if with_bom {
+        result.extend_from_slice(encoding.bytes());
+    }
+    encoding.encode(text, result);
+}
+
+fn invalid_input(progname: &str, encoding: BomKind) -> io::Error {
+    coded(
+        io::ErrorKind::Other,
+        ErrorCode::InvalidEncoding,
+        tr!("{}: {} input is not valid", progname, encoding.name()),
+    )
+}
+
+/// Converts the line endings of `content`.
+///
+/// A UTF-8 BOM is dropped unless `keep_bom` is set. Input starting with a
+/// UTF-16 or UTF-32 BOM is converted code unit by code unit and written back
+/// in its original encoding with its BOM, so `0D 00 0A 00` is treated as one
+/// CRLF.
 pub fn convert_line_endings(
     content: &[u8],
     keep_bom: bool,
@@ -64,48 +453,451 @@ pub fn convert_line_endings(
     verbose: usize,
     progname: &str,
 ) -> io::Result<Vec<u8>> {
+    let options = ConversionOptions {
+        keep_bom,
+        force,
+        add_eol,
+        verbose,
+        progname: progname.to_string(),
+        ..ConversionOptions::new(conversion_mode)
+    };
+    convert_with_options(content, &options)
+}
+
+/// Converts the line endings of `content` as [`convert_line_endings`] does,
+/// with every setting taken from `options`.
+///
+/// With `to_utf8`, UTF-16 and UTF-32 input is decoded and written as UTF-8,
+/// preceded by a UTF-8 BOM if `keep_bom` is set. `add_bom` gives all UTF-8
+/// output a BOM, and `remove_bom` drops the BOM of every encoding. With
+/// `bom`, the output is written in the chosen encoding with its BOM, whatever
+/// the encoding of the input. `gb18030` writes UTF-16 and UTF-32 input as
+/// GB18030 instead of UTF-8. `char_conversion` translates the characters of
+/// input without a BOM as it converts the line endings. `from_encoding` and
+/// `to_encoding` transcode between arbitrary character sets. With
+/// `check_utf8`, input that is not valid UTF-8 is an error, and with
+/// `strict`, input with mixed line endings. `auto` picks the line break for
+/// each input from its content.
+pub fn convert_with_options(content: &[u8], options: &ConversionOptions) -> io::Result<Vec<u8>> {
+    if options.check_utf8 {
+        if let Err(invalid) = check_utf8(content) {
+            return Err(coded(
+                io::ErrorKind::Other,
+                ErrorCode::NotUtf8,
+                format!("{}: {}", options.progname, invalid),
+            ));
+        }
+    }
+    if options.strict {
+        if let Some(line) = detect_line_endings(content).first_mixed_line {
+            return Err(coded(
+                io::ErrorKind::Other,
+                ErrorCode::MixedLineEndings,
+                format!("{}: {}", options.progname, mixed_line_endings_message(line)),
+            ));
+        }
+    }
+    if options.auto {
+        let stats = detect_line_endings(content);
+        let options = ConversionOptions {
+            conversion_mode: stats.dominant_mode().unwrap_or(options.conversion_mode),
+            normalize: true,
+            auto: false,
+            unicode_breaks: false,
+            ..options.clone()
+        };
+        return convert_with_options(content, &options);
+    }
+    if options.from_encoding.is_some() || options.to_encoding.is_some() {
+        return convert_transcoded(content, options);
+    }
+
+    let ConversionOptions {
+        conversion_mode,
+        ref progname,
+        ..
+    } = *options;
     let mut result = Vec::with_capacity(content.len());
-    let mut idx = 0;
-    let mut prev_byte = None;
-    let mut line_number = 1;
-    let mut converted = 0;
 
     // Check for BOM
-    if content.starts_with(&[0xEF, 0xBB, 0xBF]) {
-        if keep_bom {
-            result.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+    match BomKind::detect(content) {
+        Some(bom) if bom.unit_size() == 2 => {
+            convert_wide::<u16>(&content[bom.bytes().len()..], bom, options, &mut result)?
+        }
+        Some(bom) if bom.unit_size() == 4 => {
+            convert_wide::<u32>(&content[bom.bytes().len()..], bom, options, &mut result)?
+        }
+        bom => {
+            let body = &content[bom.map_or(0, |bom| bom.bytes().len())..];
+            match options.target_encoding(BomKind::Utf8) {
+                Some(target) => {
+                    let mut converted = Vec::with_capacity(body.len());
+                    convert_units(body, &mut converted, options)?;
+                    let text = String::from_utf8(converted)
+                        .map_err(|_| invalid_input(progname, BomKind::Utf8))?;
+                    write_transcoded(&text, target, !options.remove_bom, &mut result);
+                }
+                None => {
+                    if options.writes_utf8_bom(bom.is_some()) {
+                        result.extend_from_slice(BomKind::Utf8.bytes());
+                    }
+                    let start = result.len();
+                    convert_units(body, &mut result, options)?;
+                    let table = options.char_conversion.table(conversion_mode);
+                    if let (Some(table), None) = (table, bom) {
+                        codepage::translate(&mut result[start..], &table);
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(result)
+}
+
+/// Whether converting `content` with `options` would give `content` back,
+/// found in a single pass without building the output, so that a clean file
+/// can be copied as it is. Only plain line break conversions of input
+/// without a BOM are judged; anything else gives `false` and is left to the
+/// conversion, as is binary content, for the conversion to report. So is
+/// anything with `verbose` above 1, which reports the line breaks converted.
+fn is_clean(content: &[u8], options: &ConversionOptions) -> bool {
+    let ConversionOptions {
+        conversion_mode,
+        force,
+        normalize,
+        strip_cr,
+        verbose,
+        ..
+    } = *options;
+    let plain = is_plain(options) && !options.add_eol && verbose < 2;
+    if !plain || BomKind::detect(content).is_some() {
+        return false;
+    }
+    let mut scan = if force && verbose < 2 {
+        BinaryScan::finished()
+    } else {
+        BinaryScan::new(options.binary_policy)
+    };
+    let mut prev = None;
+    for (idx, &byte) in content.iter().enumerate() {
+        if byte < 32 {
+            let crlf = byte == b'\r' && content.get(idx + 1) == Some(&b'\n');
+            let kept = match (conversion_mode, byte) {
+                (ConversionMode::ToUnix, b'\r') => !crlf && !normalize && !strip_cr,
+                (ConversionMode::ToDos, b'\r') => crlf || !normalize,
+                (ConversionMode::ToDos, b'\n') => prev == Some(b'\r'),
+                (ConversionMode::ToMac, b'\n') => prev == Some(b'\r') && !normalize,
+                _ => true,
+            };
+            if !kept || scan.check(byte, idx) {
+                return false;
+            }
+        }
+        prev = Some(byte);
+    }
+    true
+}
+
+/// Whether `options` ask for nothing beyond converting the line breaks of
+/// UTF-8 or ASCII content, and adding a line break at the end.
+fn is_plain(options: &ConversionOptions) -> bool {
+    !options.add_bom
+        && !options.auto
+        && !options.check_utf8
+        && !options.strict
+        && !options.unicode_breaks
+        && !options.binary_policy.utf8_aware
+        && options.bom.is_none()
+        && options.from_encoding.is_none()
+        && options.to_encoding.is_none()
+        && options.char_conversion.table(options.conversion_mode).is_none()
+}
+
+/// Describes mixed line endings whose first conflict is at `line`.
+pub(crate) fn mixed_line_endings_message(line: usize) -> String {
+    tr!("mixed line endings, first conflict at line {}", line)
+}
+
+/// Decodes `content` from `from_encoding`, converts its line endings and
+/// encodes it in `to_encoding`.
+fn convert_transcoded(content: &[u8], options: &ConversionOptions) -> io::Result<Vec<u8>> {
+    let progname = &options.progname;
+    let bom = BomKind::detect(content);
+    let from = match &options.from_encoding {
+        Some(label) => label.as_str(),
+        None => bom.unwrap_or(BomKind::Utf8).name(),
+    };
+    let to = options.to_encoding.as_deref().unwrap_or("UTF-8");
+
+    let text = transcode::decode(content, from, progname)?;
+    let mut converted = Vec::with_capacity(text.len());
+    convert_units(text.as_bytes(), &mut converted, options)?;
+    // Only ASCII line breaks were changed, so the text is still valid UTF-8.
+    let text = String::from_utf8(converted).map_err(|_| invalid_input(progname, BomKind::Utf8))?;
+
+    let mut result = Vec::with_capacity(text.len());
+    let with_bom = options.writes_utf8_bom(bom.is_some());
+    transcode::encode(&text, to, with_bom, &mut result, progname)?;
+    Ok(result)
+}
+
+/// Converts the UTF-16 or UTF-32 `body` following `bom`, appending the
+/// result to `result`.
+fn convert_wide<T: CodeUnit>(
+    body: &[u8],
+    bom: BomKind,
+    options: &ConversionOptions,
+    result: &mut Vec<u8>,
+) -> io::Result<()> {
+    let progname = &options.progname;
+    let little_endian = bom.is_little_endian();
+    let (units, rest) = decode_units::<T>(body, little_endian);
+    let mut converted = Vec::with_capacity(units.len());
+    convert_units(&units, &mut converted, options)?;
+    let target = options.target_encoding(bom);
+    if target.is_some() || (options.gb18030 && options.bom.is_none()) {
+        let text = match T::decode(&converted) {
+            Some(text) if rest.is_empty() => text,
+            _ => return Err(invalid_input(progname, bom)),
+        };
+        match target {
+            Some(target) => {
+                let with_bom = if target == BomKind::Utf8 {
+                    options.writes_utf8_bom(true)
+                } else {
+                    !options.remove_bom
+                };
+                write_transcoded(&text, target, with_bom, result);
+            }
+            None => {
+                if options.writes_utf8_bom(true) {
+                    result.extend_from_slice(gb18030::BOM);
+                }
+                gb18030::encode(&text, result, progname)?;
+            }
+        }
+    } else {
+        // The BOM is what identifies the encoding, so it is only dropped on
+        // explicit request.
+        if !options.remove_bom {
+            result.extend_from_slice(bom.bytes());
         }
-        idx = 3;
+        encode_units(&converted, little_endian, result);
+        result.extend_from_slice(rest);
     }
+    Ok(())
+}
 
-    detect_binary(&content[idx..], force, verbose, progname)?;
+/// The conversion loop of [`convert_line_endings`], shared by all
+/// encodings. Binary content is looked for in the same pass, and stops the
+/// conversion unless `force` is set.
+fn convert_units<T: CodeUnit>(
+    content: &[T],
+    result: &mut Vec<T>,
+    options: &ConversionOptions,
+) -> io::Result<()> {
+    let ConversionOptions {
+        force,
+        verbose,
+        ref binary_policy,
+        ref reporter,
+        ref progname,
+        ..
+    } = *options;
+    let mut scan = if binary_policy.utf8_aware {
+        // Judging by the density of control characters takes a pass of its own
+        detect_binary_units(content, binary_policy, force, verbose, &**reporter, progname)?;
+        BinaryScan::finished()
+    } else if force && verbose < 2 {
+        BinaryScan::finished()
+    } else {
+        BinaryScan::new(*binary_policy)
+    };
+    let mut progress = Progress::new();
+    convert_scanning(content, result, options, &mut progress, &mut scan, 1)?;
+    finish_conversion(result, options, progress);
+    Ok(())
+}
+
+/// Converts `content`, one piece of the input starting at line
+/// `first_line`, with [`convert_chunk`], reporting what `scan` finds.
+pub(crate) fn convert_scanning<T: CodeUnit>(
+    content: &[T],
+    result: &mut Vec<T>,
+    options: &ConversionOptions,
+    progress: &mut Progress<T>,
+    scan: &mut BinaryScan,
+    first_line: usize,
+) -> io::Result<()> {
+    let mut start = 0;
+    while let Some(idx) = convert_chunk(&content[start..], result, options, progress, scan) {
+        let idx = start + idx;
+        let line_number = first_line
+            + content[..idx]
+                .iter()
+                .filter(|&&unit| unit == T::from_ascii(b'\n'))
+                .count();
+        let ConversionOptions {
+            force,
+            verbose,
+            ref reporter,
+            ref progname,
+            ..
+        } = *options;
+        let value = content[idx].value();
+        binary_found(value, line_number, force, verbose, &**reporter, progname)?;
+        // With --force the conversion goes on from the binary unit
+        start = idx;
+    }
+    Ok(())
+}
+
+/// How far a conversion fed in pieces has got, carried from one piece to
+/// the next.
+pub(crate) struct Progress<T> {
+    /// The last unit converted, or the last unit of the line break it was
+    /// replaced with, which decides how an LF starting the next piece is
+    /// treated.
+    prev: Option<T>,
+    line_number: usize,
+    converted: usize,
+}
+
+impl<T> Progress<T> {
+    pub(crate) fn new() -> Self {
+        Progress {
+            prev: None,
+            line_number: 1,
+            converted: 0,
+        }
+    }
+}
+
+/// Converts `content`, one piece of the input, appending the result to
+/// `result`. A piece must not end with a CR, or in the middle of a Unicode
+/// line break, as what follows decides how those are converted.
+///
+/// Stops at the first unit `scan` finds to be binary and returns its index;
+/// the conversion can be picked up from there.
+fn convert_chunk<T: CodeUnit>(
+    content: &[T],
+    result: &mut Vec<T>,
+    options: &ConversionOptions,
+    progress: &mut Progress<T>,
+    scan: &mut BinaryScan,
+) -> Option<usize> {
+    let ConversionOptions {
+        conversion_mode,
+        normalize,
+        unicode_breaks,
+        strip_cr,
+        verbose,
+        ref reporter,
+        ref progname,
+        ..
+    } = *options;
+    let cr = T::from_ascii(b'\r');
+    let lf = T::from_ascii(b'\n');
+    let target_break: &[T] = match conversion_mode {
+        ConversionMode::ToUnix => &[lf],
+        ConversionMode::ToDos => &[cr, lf],
+        ConversionMode::ToMac => &[cr],
+    };
+    let mut idx = 0;
+    let mut prev_byte = progress.prev;
+    let mut line_number = progress.line_number;
+    let mut converted = progress.converted;
 
     while idx < content.len() {
+        if scan.check(content[idx], idx) {
+            scan.advance(idx);
+            *progress = Progress {
+                prev: prev_byte,
+                line_number,
+                converted,
+            };
+            return Some(idx);
+        }
+
+        let unicode_break_len = if unicode_breaks {
+            T::unicode_break_len(&content[idx..])
+        } else {
+            0
+        };
+        if unicode_break_len > 0 {
+            idx += unicode_break_len;
+            result.extend_from_slice(target_break);
+            converted += 1;
+            if verbose > 2 {
+                reporter.report(3, &tr!(
+                    "{}: Converted Unicode line break at line {}.",
+                    progname, line_number
+                ));
+            }
+            line_number += 1;
+            prev_byte = target_break.last().copied();
+            continue;
+        }
+
         let byte = content[idx];
         idx += 1;
 
+        if normalize && (byte == cr || byte == lf) {
+            let crlf = byte == cr && content.get(idx) == Some(&lf);
+            if crlf {
+                idx += 1;
+            }
+            let unchanged = match conversion_mode {
+                ConversionMode::ToUnix => byte == lf,
+                ConversionMode::ToDos => crlf,
+                ConversionMode::ToMac => byte == cr && !crlf,
+            };
+            result.extend_from_slice(target_break);
+            if !unchanged {
+                converted += 1;
+                if verbose > 2 {
+                    reporter.report(3, &tr!(
+                        "{}: Normalized line break at line {}.",
+                        progname, line_number
+                    ));
+                }
+            }
+            line_number += 1;
+            prev_byte = target_break.last().copied();
+            continue;
+        }
+
         match conversion_mode {
             ConversionMode::ToUnix => {
                 // DOS to UNIX conversion
-                if byte == b'\r' {
-                    if idx < content.len() && content[idx] == b'\n' {
+                if byte == cr {
+                    if idx < content.len() && content[idx] == lf {
                         // CRLF sequence, convert to LF
-                        result.push(b'\n');
+                        result.push(lf);
                         idx += 1;
                         converted += 1;
                         line_number += 1;
-                        if verbose > 1 {
-                            eprintln!(
+                        if verbose > 2 {
+                            reporter.report(3, &tr!(
                                 "{}: Converted CRLF to LF at line {}.",
                                 progname, line_number - 1
-                            );
+                            ));
+                        }
+                    } else if strip_cr {
+                        // Single CR, not part of any line ending here
+                        if verbose > 2 {
+                            reporter.report(3, &tr!(
+                                "{}: Removed lone CR at line {}.",
+                                progname, line_number
+                            ));
                         }
                     } else {
                         // Single CR, leave as is (could be Mac line ending)
-                        result.push(b'\r');
+                        result.push(cr);
                     }
                 } else {
-                    if byte == b'\n' {
+                    if byte == lf {
                         line_number += 1;
                     }
                     result.push(byte);
@@ -113,19 +905,19 @@ pub fn convert_line_endings(
             }
             ConversionMode::ToDos => {
                 // UNIX to DOS conversion
-                if byte == b'\n' {
-                    if prev_byte != Some(b'\r') {
+                if byte == lf {
+                    if prev_byte != Some(cr) {
                         // LF not preceded by CR, insert CR
-                        result.push(b'\r');
+                        result.push(cr);
                         converted += 1;
-                        if verbose > 1 {
-                            eprintln!(
+                        if verbose > 2 {
+                            reporter.report(3, &tr!(
                                 "{}: Converted LF to CRLF at line {}.",
                                 progname, line_number
-                            );
+                            ));
                         }
                     }
-                    result.push(b'\n');
+                    result.push(lf);
                     line_number += 1;
                 } else {
                     result.push(byte);
@@ -133,20 +925,20 @@ pub fn convert_line_endings(
             }
             ConversionMode::ToMac => {
                 // UNIX/Mac conversion
-                if byte == b'\n' {
-                    if prev_byte != Some(b'\r') {
+                if byte == lf {
+                    if prev_byte != Some(cr) {
                         // LF not part of CRLF, convert LF to CR
-                        result.push(b'\r');
+                        result.push(cr);
                         converted += 1;
-                        if verbose > 1 {
-                            eprintln!(
+                        if verbose > 2 {
+                            reporter.report(3, &tr!(
                                 "{}: Converted LF to CR at line {}.",
                                 progname, line_number
-                            );
+                            ));
                         }
                     } else {
                         // Part of CRLF, keep as is
-                        result.push(b'\n');
+                        result.push(lf);
                     }
                     line_number += 1;
                 } else {
@@ -157,19 +949,52 @@ pub fn convert_line_endings(
         prev_byte = Some(byte);
     }
 
+    scan.advance(content.len());
+    *progress = Progress {
+        prev: prev_byte,
+        line_number,
+        converted,
+    };
+    None
+}
+
+/// Ends a conversion fed to [`convert_chunk`], terminating the last line for
+/// `add_eol`.
+pub(crate) fn finish_conversion<T: CodeUnit>(
+    result: &mut Vec<T>,
+    options: &ConversionOptions,
+    progress: Progress<T>,
+) {
+    let ConversionOptions {
+        conversion_mode,
+        add_eol,
+        verbose,
+        ref reporter,
+        ref progname,
+        ..
+    } = *options;
+    let cr = T::from_ascii(b'\r');
+    let lf = T::from_ascii(b'\n');
+    let Progress {
+        prev: prev_byte,
+        mut line_number,
+        converted,
+    } = progress;
+
     if add_eol {
         if let Some(last_byte) = prev_byte {
-            if last_byte != b'\n' && last_byte != b'\r' {
-                if verbose > 1 {
-                    eprintln!("{}: Added line break to last line.", progname);
+            if last_byte != lf && last_byte != cr {
+                if verbose > 2 {
+                    let message = tr!("{}: Added line break to last line.", progname);
+                    reporter.report(3, &message);
                 }
                 match conversion_mode {
-                    ConversionMode::ToUnix => result.push(b'\n'),
+                    ConversionMode::ToUnix => result.push(lf),
                     ConversionMode::ToDos => {
-                        result.push(b'\r');
-                        result.push(b'\n');
+                        result.push(cr);
+                        result.push(lf);
                     }
-                    ConversionMode::ToMac => result.push(b'\r'),
+                    ConversionMode::ToMac => result.push(cr),
                 }
                 line_number += 1;
             }
@@ -177,17 +1002,127 @@ pub fn convert_line_endings(
     }
 
     if verbose > 1 {
-        eprintln!(
+        reporter.report(2, &tr!(
             "{}: Converted {} out of {} line breaks.",
             progname,
             converted,
             line_number - 1
-        );
+        ));
     }
+}
 
-    Ok(result)
+/// The name of the backup file `-b` creates for `input_path` by default.
+pub fn backup_path(input_path: &Path) -> PathBuf {
+    let mut name = input_path.as_os_str().to_os_string();
+    name.push("~");
+    PathBuf::from(name)
 }
 
+/// The modification and access times recorded in `metadata`, for
+/// `keep_date`.
+fn file_times(metadata: &fs::Metadata) -> io::Result<fs::FileTimes> {
+    let times = fs::FileTimes::new().set_modified(metadata.modified()?);
+    Ok(match metadata.accessed() {
+        Ok(accessed) => times.set_accessed(accessed),
+        Err(_) => times,
+    })
+}
+
+/// The form of `path` to hand to the file system. On Windows this is the
+/// absolute, extended-length `\\?\` form, which lifts the limit of 260
+/// characters on paths, so deep trees such as `node_modules` can be
+/// converted.
+#[cfg(windows)]
+fn fs_path(path: &Path) -> io::Result<PathBuf> {
+    use std::ffi::OsString;
+    use std::path::{Component, Prefix};
+
+    let absolute = std::path::absolute(path)?;
+    let mut components = absolute.components();
+    match components.next() {
+        Some(Component::Prefix(prefix)) => match prefix.kind() {
+            Prefix::Disk(_) => {
+                let mut extended = OsString::from(r"\\?\");
+                extended.push(absolute.as_os_str());
+                Ok(PathBuf::from(extended))
+            }
+            // \\server\share\dir becomes \\?\UNC\server\share\dir
+            Prefix::UNC(server, share) => {
+                let mut extended = PathBuf::from(r"\\?\UNC\");
+                extended.push(server);
+                extended.push(share);
+                extended.extend(components.filter(|c| *c != Component::RootDir));
+                Ok(extended)
+            }
+            // Already extended-length, or a device path
+            _ => Ok(absolute),
+        },
+        _ => Ok(absolute),
+    }
+}
+
+#[cfg(not(windows))]
+fn fs_path(path: &Path) -> io::Result<PathBuf> {
+    Ok(path.to_path_buf())
+}
+
+/// Makes the existing file at `path` writable if it is read-only on Windows,
+/// where such a file can be neither written nor replaced. Returns the
+/// permissions to restore afterwards, if they were changed.
+#[cfg(windows)]
+fn make_writable(
+    path: &Path,
+    options: &ConversionOptions,
+) -> io::Result<Option<fs::Permissions>> {
+    let permissions = match fs::metadata(path) {
+        Ok(metadata) => metadata.permissions(),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    if !permissions.readonly() {
+        return Ok(None);
+    }
+    if !options.force_readonly {
+        return Err(coded(
+            io::ErrorKind::PermissionDenied,
+            ErrorCode::ReadOnly,
+            tr!(
+                "{}: file is read-only (use --force-readonly to convert it anyway)",
+                options.progname
+            ),
+        ));
+    }
+    let mut writable = permissions.clone();
+    #[allow(clippy::permissions_set_readonly_false)]
+    writable.set_readonly(false);
+    fs::set_permissions(path, writable)?;
+    Ok(Some(permissions))
+}
+
+#[cfg(not(windows))]
+fn make_writable(
+    _path: &Path,
+    _options: &ConversionOptions,
+) -> io::Result<Option<fs::Permissions>> {
+    Ok(None)
+}
+
+/// Gives the file at `path` the owner and group recorded in `metadata`.
+#[cfg(unix)]
+fn copy_ownership(path: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+    use std::os::unix::fs::MetadataExt;
+    std::os::unix::fs::chown(path, Some(metadata.uid()), Some(metadata.gid()))
+}
+
+#[cfg(not(unix))]
+fn copy_ownership(_path: &Path, _metadata: &fs::Metadata) -> io::Result<()> {
+    Ok(())
+}
+
+/// Converts `input_path` in place, or into `output_path` if given.
+///
+/// Returns the line break counts of the original file.
+#[allow(clippy::too_many_arguments)]
 pub fn process_file(
     input_path: &Path,
     output_path: Option<&Path>,
@@ -198,54 +1133,365 @@ pub fn process_file(
     add_eol: bool,
     verbose: usize,
     progname: &str,
-) -> io::Result<()> {
-    let content = fs::read(input_path)?;
-
-    match convert_line_endings(
-        &content,
+) -> io::Result<LineEndingStats> {
+    let options = ConversionOptions {
         keep_bom,
         force,
-        conversion_mode,
+        backup,
         add_eol,
         verbose,
-        progname,
-    ) {
-        Ok(converted_content) => {
-            if backup {
-                let backup_filename = format!("{}~", input_path.display());
-                if verbose > 0 {
-                    eprintln!(
-                        "{}: creating backup file '{}'",
-                        progname, backup_filename
-                    );
+        progname: progname.to_string(),
+        ..ConversionOptions::new(conversion_mode)
+    };
+    process_file_with_options(input_path, output_path, &options).map(|file| file.stats)
+}
+
+/// What [`process_file_with_options`] did with a file.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ProcessedFile {
+    /// Line break counts of the original file.
+    pub stats: LineEndingStats,
+    /// Whether the conversion changed the content.
+    pub changed: bool,
+}
+
+/// Converts `input_path` as [`process_file`] does, with every setting taken
+/// from `options`.
+///
+/// A file converted in place that the conversion leaves unchanged is not
+/// written at all, so its modification time and inode stay the same, and
+/// no backup is made of it.
+pub fn process_file_with_options(
+    input_path: &Path,
+    output_path: Option<&Path>,
+    options: &ConversionOptions,
+) -> io::Result<ProcessedFile> {
+    #[cfg(feature = "tracing")]
+    let _span = tracing::info_span!("convert", path = %input_path.display()).entered();
+    let verbose = options.verbose;
+    let reporter = &options.reporter;
+    let progname = &options.progname;
+    // The paths as given are kept for messages
+    let input_fs = fs_path(input_path)?;
+    let output_fs = output_path.map(fs_path).transpose()?;
+    if let (true, Some(shown), Some(output_fs)) = (options.no_clobber, output_path, &output_fs) {
+        refuse_clobber(output_fs, shown, progname)?;
+    }
+    // The file is judged, locked, read and written by the one handle, and
+    // its metadata taken before any reading, which may update the access
+    // time. A file converted in place is not to be a link, which was
+    // resolved, and with `in_place` it is rewritten through the handle.
+    let nofollow = output_path.is_none();
+    let rewrite = options.in_place && output_path.is_none();
+    let force_special = options.force_special;
+    let open = |write| input::open_input(&input_fs, input_path, force_special, nofollow, write);
+    let (input, metadata, writable) = retry(input_path, options, || match open(rewrite) {
+        // A read-only file needs no writing if it is left unchanged
+        Err(e) if rewrite && e.kind() == io::ErrorKind::PermissionDenied => {
+            open(false).map(|(input, metadata)| (input, metadata, false))
+        }
+        opened => opened.map(|(input, metadata)| (input, metadata, rewrite)),
+    })?;
+    // Taken again once the file is locked, as it may have changed before
+    let (_lock, metadata) = if options.lock {
+        let lock = retry(input_path, options, || {
+            FileLock::acquire(input.try_clone()?, &input_fs, input_path)
+        })?;
+        let metadata = lock.metadata()?;
+        (Some(lock), metadata)
+    } else {
+        (None, metadata)
+    };
+    if !options.force {
+        let skip_reason = match magic::skipped_extension(input_path, &options.skip_extensions) {
+            Some(extension) => Some(tr!("'.{}' file skipped", extension)),
+            // Only the start of a regular file can be read again
+            None if options.sniff_file_type && metadata.is_file() => {
+                retry(input_path, options, || magic::sniff_file(&input))?
+                    .map(|file_type| tr!("{} detected", file_type))
+            }
+            None => None,
+        };
+        if let Some(reason) = skip_reason {
+            let error_msg = format!("{}: {}", progname, reason);
+            if verbose > 1 {
+                reporter.report(2, &error_msg);
+            }
+            return Err(coded(io::ErrorKind::InvalidData, ErrorCode::Binary, error_msg));
+        }
+    }
+    if verbose > 0 {
+        reporter.report(1, &converting_message(input_path, output_path, options));
+    }
+    let output_fs = output_fs.as_deref();
+    let target = output_fs.unwrap_or(&input_fs);
+    let streams = stream::streams(options, metadata.len());
+    let streamed = if streams {
+        stream::convert_file(&input, target, options)?
+    } else {
+        None
+    };
+    let (stats, changed, output) = match streamed {
+        Some(streamed) => (streamed.stats, streamed.changed, Output::Temp(streamed.temp)),
+        None => {
+            // From the start again, if UTF-16 or UTF-32 stopped the stream
+            if streams {
+                (&input).rewind()?;
+            }
+            // Dropped at the end of this block, before the output is
+            // written, which Windows does not allow for a mapped file
+            let content = input::read_file(&input, options.mmap)?;
+            if output_fs.is_some() && is_clean(&content, options) {
+                (detect_line_endings(&content), false, Output::Copy)
+            } else {
+                let converted_content = convert_with_options(&content, options)?;
+                let changed = converted_content[..] != content[..];
+                (detect_line_endings(&content), changed, Output::Content(converted_content))
+            }
+        }
+    };
+
+    if let (true, Some(bom)) = (verbose > 1, stats.bom) {
+        let input = input_path.display();
+        let message = tr!("{}: input file {} has {} BOM.", progname, input, bom.name());
+        reporter.report(2, &message);
+    }
+
+    if !changed && output_path.is_none() {
+        if verbose > 1 {
+            reporter.report(2, &tr!(
+                "{}: '{}' needs no conversion, left untouched",
+                progname, input_path.display()
+            ));
+        }
+        return Ok(ProcessedFile { stats, changed });
+    }
+
+    if options.backup {
+        let naming = &options.backup_naming;
+        let backup_filename = naming.path(input_path)?;
+        if verbose > 1 {
+            reporter.report(2, &tr!(
+                "{}: creating backup file '{}'",
+                progname, backup_filename.display()
+            ));
+        }
+        retry(input_path, options, || {
+            backup::create(naming, input_path, &input, &backup_filename)
+        }).map_err(|e| {
+            let message = tr!("cannot create backup file '{}': {}", backup_filename.display(), e);
+            coded(e.kind(), ErrorCode::of(&e), message)
+        })?;
+    }
+
+    let cleared = make_writable(target, options)?;
+    // Opened for writing only now that the read-only attribute is cleared
+    let input = match (rewrite, writable) {
+        (true, false) => retry(input_path, options, || open(true))?.0,
+        _ => input,
+    };
+    let written = write_output(&input, &input_fs, output_fs, output, &metadata, options);
+    // A replaced file has the permissions of the temporary file,
+    // but otherwise the read-only attribute has to be put back
+    if let Some(permissions) = cleared {
+        if written.is_err() || (options.in_place && output_fs.is_none()) {
+            let _ = fs::set_permissions(target, permissions);
+        }
+    }
+    written?;
+
+    Ok(ProcessedFile { stats, changed })
+}
+
+/// Runs `operation` on `path`, and while it fails because another program
+/// has the file locked, runs it again up to `options.retries` more times,
+/// `options.retry_delay` apart.
+pub(crate) fn retry<T>(
+    path: &Path,
+    options: &ConversionOptions,
+    mut operation: impl FnMut() -> io::Result<T>,
+) -> io::Result<T> {
+    let mut attempt = 0;
+    loop {
+        match operation() {
+            Err(e) if attempt < options.retries && error::is_locked(&e) => {
+                attempt += 1;
+                if options.verbose > 1 {
+                    options.reporter.report(2, &tr!(
+                        "{}: '{}' is locked, trying again ({} of {})",
+                        options.progname, path.display(), attempt, options.retries
+                    ));
                 }
-                fs::copy(input_path, &backup_filename)?;
+                thread::sleep(options.retry_delay);
+                signal::check()?;
             }
+            result => return result,
+        }
+    }
+}
 
-            let output_path = output_path.unwrap_or(input_path);
+/// Fails if there is a file at `path`, shown as `shown`, for `no_clobber`.
+/// A symbolic link counts, even if it leads nowhere.
+pub(crate) fn refuse_clobber(path: &Path, shown: &Path, progname: &str) -> io::Result<()> {
+    if fs::symlink_metadata(path).is_err() {
+        return Ok(());
+    }
+    Err(coded(
+        io::ErrorKind::AlreadyExists,
+        ErrorCode::Exists,
+        tr!(
+            "{}: output file '{}' already exists (not overwritten with --no-clobber)",
+            progname,
+            shown.display()
+        ),
+    ))
+}
 
-            // Preserve file permissions
-            let metadata = fs::metadata(input_path)?;
-            let permissions = metadata.permissions();
+/// The message GNU dos2unix prints for each file it converts, such as
+/// `dos2unix: converting file notes.txt to Unix format...`. With `auto`,
+/// the line break is not known yet and is left out.
+fn converting_message(
+    input_path: &Path,
+    output_path: Option<&Path>,
+    options: &ConversionOptions,
+) -> String {
+    let format = match options.conversion_mode {
+        _ if options.auto => None,
+        ConversionMode::ToUnix => Some("Unix"),
+        ConversionMode::ToDos => Some("DOS"),
+        ConversionMode::ToMac => Some("Mac"),
+    };
+    let progname = &options.progname;
+    let input = input_path.display();
+    match (output_path, format) {
+        (None, Some(format)) => {
+            tr!("{}: converting file {} to {} format...", progname, input, format)
+        }
+        (None, None) => tr!("{}: converting file {}...", progname, input),
+        (Some(output), Some(format)) => tr!(
+            "{}: converting file {} to file {} in {} format...",
+            progname, input, output.display(), format
+        ),
+        (Some(output), None) => {
+            tr!("{}: converting file {} to file {}...", progname, input, output.display())
+        }
+    }
+}
 
-            // Write the converted content to a temporary file first
-            let temp_path = output_path.with_extension("tmp");
-            fs::write(&temp_path, converted_content)?;
+/// Converted content on its way to the output file.
+enum Output {
+    Content(Vec<u8>),
+    /// Already written to a temporary file beside the output file.
+    Temp(TempFile),
+    /// The input, which needs no conversion, as it is.
+    Copy,
+}
 
-            // Set the permissions of the temp file to match the original
-            fs::set_permissions(&temp_path, permissions)?;
+/// Writes `output` over `input_path`, or into `output_path` if given, as
+/// set up by `options`. `input` is the file at `input_path` as opened,
+/// which a copy is read from, and which `in_place` rewrites.
+fn write_output(
+    input: &fs::File,
+    input_path: &Path,
+    output_path: Option<&Path>,
+    output: Output,
+    metadata: &fs::Metadata,
+    options: &ConversionOptions,
+) -> io::Result<()> {
+    let verbose = options.verbose;
+    let reporter = &options.reporter;
+    let progname = &options.progname;
+    signal::check()?;
+    if let (Output::Content(converted_content), true, None) =
+        (&output, options.in_place, output_path)
+    {
+        // Writing before truncating lets the file grow or shrink
+        let mut file = input;
+        file.rewind()?;
+        file.write_all(converted_content)?;
+        file.set_len(converted_content.len() as u64)?;
+        if options.keep_date {
+            file.set_times(file_times(metadata)?)?;
+        }
+        if options.sync {
+            file.sync_all()?;
+        }
+        return Ok(());
+    }
 
-            // Replace the original file with the temp file
-            fs::rename(&temp_path, output_path)?;
+    let new_file = output_path.is_some();
+    let output_path = output_path.unwrap_or(input_path);
 
+    let mut temp = match output {
+        Output::Content(converted_content) => {
+            // Write the converted content to a temporary file first
+            let mut temp = TempFile::create(output_path)?;
+            temp.file().write_all(&converted_content)?;
+            temp
+        }
+        Output::Temp(temp) => temp,
+        Output::Copy => {
+            // On Linux this copies within the kernel, using copy_file_range
+            let mut temp = TempFile::create(output_path)?;
+            let mut input = input;
+            input.rewind()?;
+            io::copy(&mut input, temp.file())?;
+            temp
+        }
+    };
+
+    if options.keep_date {
+        temp.file().set_times(file_times(metadata)?)?;
+    }
+
+    // The temp file belongs to whoever runs the conversion, such as
+    // root, so give it the owner of the file it replaces. This comes
+    // before setting the permissions, which chown may clear bits of.
+    if output_path == input_path {
+        if let Err(e) = copy_ownership(temp.path(), metadata) {
+            if !options.allow_chown {
+                return Err(coded(
+                    e.kind(),
+                    ErrorCode::Owner,
+                    tr!(
+                        "{}: cannot preserve the owner of '{}': {} \
+                         (use --allow-chown to convert anyway)",
+                        progname,
+                        input_path.display(),
+                        e
+                    ),
+                ));
+            }
             if verbose > 0 {
-                eprintln!("{}: converted '{}'", progname, input_path.display());
+                reporter.report(1, &tr!(
+                    "{}: owner of '{}' changed: {}",
+                    progname, input_path.display(), e
+                ));
             }
-
-            Ok(())
         }
-        Err(e) => Err(e),
     }
+
+    // Set the permissions of the temp file to match the original
+    temp.file().set_permissions(metadata.permissions())?;
+
+    if options.sync {
+        temp.file().sync_all()?;
+    }
+
+    // Replace the original file with the temp file, or with no_clobber
+    // move it to the output file only if there is none by now. Once
+    // interrupted, the temp file is removed instead.
+    signal::check()?;
+    if options.no_clobber && new_file {
+        retry(output_path, options, || temp.persist_new(output_path))?;
+    } else {
+        retry(output_path, options, || temp.persist(output_path))?;
+    }
+    if options.sync {
+        temp::sync_parent(output_path)?;
+    }
+
+    Ok(())
 }
 
 pub fn is_stdin_tty() -> bool {
@@ -273,4 +1519,332 @@ pub fn is_stdin_tty() -> bool {
         // For other platforms, assume stdin is not a TTY
         false
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CRLF, LF and lone CR line breaks, and a last line without one.
+    const MIXED: &[u8] = b"a\r\nb\nc\rd";
+
+    fn options(conversion_mode: ConversionMode) -> ConversionOptions {
+        ConversionOptions::new(conversion_mode)
+    }
+
+    fn converted(content: &[u8], options: &ConversionOptions) -> Vec<u8> {
+        convert_with_options(content, options).unwrap()
+    }
+
+    /// `text` as UTF-16LE with a BOM.
+    fn utf16le(text: &str) -> Vec<u8> {
+        let mut encoded = BomKind::Utf16Le.bytes().to_vec();
+        encoded.extend(text.encode_utf16().flat_map(u16::to_le_bytes));
+        encoded
+    }
+
+    #[test]
+    fn each_mode_converts_its_line_breaks() {
+        let cases: [(ConversionMode, &[u8]); 3] = [
+            // A lone CR is not a DOS line break
+            (ConversionMode::ToUnix, b"a\nb\nc\rd"),
+            (ConversionMode::ToDos, b"a\r\nb\r\nc\rd"),
+            // A CRLF is left alone
+            (ConversionMode::ToMac, b"a\r\nb\rc\rd"),
+        ];
+        for (conversion_mode, expected) in cases {
+            assert_eq!(converted(MIXED, &options(conversion_mode)), expected);
+        }
+    }
+
+    #[test]
+    fn converted_input_converts_to_itself() {
+        for conversion_mode in [
+            ConversionMode::ToUnix,
+            ConversionMode::ToDos,
+            ConversionMode::ToMac,
+        ] {
+            let options = options(conversion_mode);
+            let once = converted(MIXED, &options);
+            assert_eq!(converted(&once, &options), once);
+        }
+    }
+
+    #[test]
+    fn normalize_converts_every_line_break() {
+        let cases: [(ConversionMode, &[u8]); 3] = [
+            (ConversionMode::ToUnix, b"a\nb\nc\nd"),
+            (ConversionMode::ToDos, b"a\r\nb\r\nc\r\nd"),
+            (ConversionMode::ToMac, b"a\rb\rc\rd"),
+        ];
+        for (conversion_mode, expected) in cases {
+            let options = ConversionOptions {
+                normalize: true,
+                ..options(conversion_mode)
+            };
+            assert_eq!(converted(MIXED, &options), expected);
+        }
+    }
+
+    #[test]
+    fn strip_cr_removes_lone_crs() {
+        let options = ConversionOptions {
+            strip_cr: true,
+            ..options(ConversionMode::ToUnix)
+        };
+        assert_eq!(converted(MIXED, &options), b"a\nb\ncd");
+        assert_eq!(converted(b"a\r\r\n\r", &options), b"a\n");
+    }
+
+    #[test]
+    fn auto_picks_the_most_common_line_break() {
+        let options = ConversionOptions {
+            auto: true,
+            ..options(ConversionMode::ToUnix)
+        };
+        assert_eq!(converted(b"a\r\nb\r\nc\n", &options), b"a\r\nb\r\nc\r\n");
+        assert_eq!(converted(b"a\rb\rc\r\n", &options), b"a\rb\rc\r");
+        // Ties go to LF
+        assert_eq!(converted(b"a\r\nb\n", &options), b"a\nb\n");
+        // Without line breaks, the mode given
+        assert_eq!(converted(b"a", &options), b"a");
+    }
+
+    #[test]
+    fn unicode_breaks_are_converted_when_asked() {
+        let text = "a\u{2028}b\u{85}c\u{2029}".as_bytes();
+        assert_eq!(converted(text, &options(ConversionMode::ToUnix)), text);
+        let cases: [(ConversionMode, &[u8]); 2] = [
+            (ConversionMode::ToUnix, b"a\nb\nc\n"),
+            (ConversionMode::ToDos, b"a\r\nb\r\nc\r\n"),
+        ];
+        for (conversion_mode, expected) in cases {
+            let options = ConversionOptions {
+                unicode_breaks: true,
+                ..options(conversion_mode)
+            };
+            assert_eq!(converted(text, &options), expected);
+        }
+    }
+
+    #[test]
+    fn add_eol_terminates_the_last_line() {
+        let options = ConversionOptions {
+            add_eol: true,
+            ..options(ConversionMode::ToDos)
+        };
+        assert_eq!(converted(b"a\nb", &options), b"a\r\nb\r\n");
+        assert_eq!(converted(b"a\n", &options), b"a\r\n");
+        assert_eq!(converted(b"", &options), b"");
+    }
+
+    #[test]
+    fn utf8_boms_are_kept_or_dropped_as_asked() {
+        let input = b"\xef\xbb\xbfa\r\n";
+        let unix = options(ConversionMode::ToUnix);
+        assert_eq!(converted(input, &unix), b"a\n");
+        let keep = ConversionOptions {
+            keep_bom: true,
+            ..unix.clone()
+        };
+        assert_eq!(converted(input, &keep), b"\xef\xbb\xbfa\n");
+        let add = ConversionOptions {
+            add_bom: true,
+            ..unix
+        };
+        assert_eq!(converted(b"a\r\n", &add), b"\xef\xbb\xbfa\n");
+    }
+
+    #[test]
+    fn utf16_converts_whole_code_units() {
+        // U+0D0A and U+0A0D hold the bytes of a CRLF and an LFCR
+        let input = utf16le("a\r\nb\nc\u{d0a}\u{a0d}\r");
+        let cases = [
+            (ConversionMode::ToUnix, "a\nb\nc\u{d0a}\u{a0d}\r"),
+            (ConversionMode::ToDos, "a\r\nb\r\nc\u{d0a}\u{a0d}\r"),
+            (ConversionMode::ToMac, "a\r\nb\rc\u{d0a}\u{a0d}\r"),
+        ];
+        for (conversion_mode, expected) in cases {
+            assert_eq!(
+                converted(&input, &options(conversion_mode)),
+                utf16le(expected)
+            );
+        }
+        let normalized = ConversionOptions {
+            normalize: true,
+            ..options(ConversionMode::ToUnix)
+        };
+        assert_eq!(
+            converted(&input, &normalized),
+            utf16le("a\nb\nc\u{d0a}\u{a0d}\n")
+        );
+    }
+
+    #[test]
+    fn utf16_can_be_written_as_utf8() {
+        let options = ConversionOptions {
+            to_utf8: true,
+            ..options(ConversionMode::ToUnix)
+        };
+        assert_eq!(converted(&utf16le("caf\u{e9}\r\n"), &options), "caf\u{e9}\n".as_bytes());
+    }
+
+    #[test]
+    fn binary_input_is_refused_unless_forced() {
+        let input = b"a\r\n\x00b\r\n";
+        let unix = options(ConversionMode::ToUnix);
+        let error = convert_with_options(input, &unix).unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::Binary);
+        assert!(error.to_string().contains("0x00 found at line 2"));
+        let forced = ConversionOptions {
+            force: true,
+            ..unix
+        };
+        assert_eq!(converted(input, &forced), b"a\n\x00b\n");
+    }
+
+    #[test]
+    fn strict_refuses_mixed_line_endings() {
+        let options = ConversionOptions {
+            strict: true,
+            ..options(ConversionMode::ToUnix)
+        };
+        let error = convert_with_options(MIXED, &options).unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::MixedLineEndings);
+        assert_eq!(converted(b"a\r\nb\r\n", &options), b"a\nb\n");
+    }
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dos2unix-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn no_clobber_leaves_an_existing_output_alone() {
+        let dir = scratch("no-clobber");
+        let input = dir.join("input.txt");
+        let output = dir.join("output.txt");
+        fs::write(&input, b"new\r\n").unwrap();
+        fs::write(&output, b"old\r\n").unwrap();
+        let options = ConversionOptions {
+            no_clobber: true,
+            ..options(ConversionMode::ToUnix)
+        };
+        let refused = process_file_with_options(&input, Some(&output), &options);
+        let kept = fs::read(&output).unwrap();
+        let clobbered = ConversionOptions {
+            no_clobber: false,
+            ..options
+        };
+        process_file_with_options(&input, Some(&output), &clobbered).unwrap();
+        let replaced = fs::read(&output).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let error = refused.unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::Exists);
+        assert!(error.to_string().contains("output.txt' already exists"));
+        assert_eq!(kept, b"old\r\n");
+        assert_eq!(replaced, b"new\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn no_clobber_counts_a_link_that_leads_nowhere() {
+        let dir = scratch("no-clobber-link");
+        let input = dir.join("input.txt");
+        let output = dir.join("output.txt");
+        fs::write(&input, b"new\r\n").unwrap();
+        std::os::unix::fs::symlink(dir.join("missing"), &output).unwrap();
+        let options = ConversionOptions {
+            no_clobber: true,
+            ..options(ConversionMode::ToUnix)
+        };
+        let refused = process_file_with_options(&input, Some(&output), &options);
+        let missing = dir.join("missing").exists();
+        fs::remove_dir_all(&dir).unwrap();
+        let error = refused.unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::Exists);
+        assert!(error.to_string().contains("not overwritten with --no-clobber"));
+        assert!(!missing);
+    }
+
+    /// The error another program holding the file open gives: a sharing
+    /// violation on Windows, and elsewhere a lock that would be waited for.
+    fn sharing_violation() -> io::Error {
+        if cfg!(windows) {
+            io::Error::from_raw_os_error(32)
+        } else {
+            io::ErrorKind::WouldBlock.into()
+        }
+    }
+
+    /// Keeps the messages reported to it.
+    #[derive(Default)]
+    struct Reported(std::sync::Mutex<Vec<String>>);
+
+    impl Reporter for Reported {
+        fn report(&self, _level: usize, message: &str) {
+            self.0.lock().unwrap().push(message.to_string());
+        }
+    }
+
+    fn retrying(retries: usize, reported: &Arc<Reported>) -> ConversionOptions {
+        ConversionOptions {
+            retries,
+            retry_delay: Duration::ZERO,
+            verbose: 2,
+            progname: "dos2unix".to_string(),
+            reporter: reported.clone(),
+            ..options(ConversionMode::ToUnix)
+        }
+    }
+
+    #[test]
+    fn retry_tries_again_after_a_sharing_violation() {
+        let reported = Arc::new(Reported::default());
+        let options = retrying(3, &reported);
+        let mut attempts = 0;
+        let result = retry(Path::new("busy.txt"), &options, || {
+            attempts += 1;
+            match attempts {
+                1 | 2 => Err(sharing_violation()),
+                _ => Ok(attempts),
+            }
+        });
+        assert_eq!(result.unwrap(), 3);
+        assert_eq!(
+            *reported.0.lock().unwrap(),
+            [
+                "dos2unix: 'busy.txt' is locked, trying again (1 of 3)",
+                "dos2unix: 'busy.txt' is locked, trying again (2 of 3)",
+            ]
+        );
+    }
+
+    #[test]
+    fn retry_gives_up_after_its_retries() {
+        let reported = Arc::new(Reported::default());
+        let options = retrying(2, &reported);
+        let mut attempts = 0;
+        let result: io::Result<()> = retry(Path::new("busy.txt"), &options, || {
+            attempts += 1;
+            Err(sharing_violation())
+        });
+        assert_eq!(ErrorCode::of(&result.unwrap_err()), ErrorCode::Locked);
+        assert_eq!(attempts, 3);
+        assert_eq!(reported.0.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn retry_does_not_repeat_other_errors() {
+        let reported = Arc::new(Reported::default());
+        let options = retrying(5, &reported);
+        let mut attempts = 0;
+        let result: io::Result<()> = retry(Path::new("busy.txt"), &options, || {
+            attempts += 1;
+            Err(io::ErrorKind::PermissionDenied.into())
+        });
+        assert_eq!(result.unwrap_err().kind(), io::ErrorKind::PermissionDenied);
+        assert_eq!(attempts, 1);
+        assert!(reported.0.lock().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,135 @@
+use std::io::{self, BufRead};
+
+/// Line iteration that understands every line ending style.
+pub trait Dos2UnixExt: BufRead + Sized {
+    /// Returns an iterator over the lines of this reader with their line
+    /// endings removed.
+    ///
+    /// Unlike [`BufRead::lines`], a line may end in LF, CRLF or a lone CR,
+    /// and none of these bytes are left in the yielded string. The input is
+    /// read incrementally, so the stream does not need to fit in memory.
+    /// Lines that are not valid UTF-8 produce an
+    /// [`InvalidData`](io::ErrorKind::InvalidData) error.
+    fn unix_lines(self) -> UnixLines<Self> {
+        UnixLines {
+            reader: self,
+            skip_lf: false,
+        }
+    }
+}
+
+impl<B: BufRead> Dos2UnixExt for B {}
+
+/// Iterator returned by [`Dos2UnixExt::unix_lines`].
+pub struct UnixLines<B> {
+    reader: B,
+    // The previous line ended in CR; a LF at the start of the next read
+    // belongs to that line ending.
+    skip_lf: bool,
+}
+
+impl<B: BufRead> UnixLines<B> {
+    fn read_line(&mut self) -> io::Result<Option<Vec<u8>>> {
+        let mut line = Vec::new();
+        let mut read_any = false;
+        loop {
+            let available = match self.reader.fill_buf() {
+                Ok(buf) => buf,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            };
+            if available.is_empty() {
+                return Ok(if read_any { Some(line) } else { None });
+            }
+            if self.skip_lf {
+                self.skip_lf = false;
+                if available[0] == b'\n' {
+                    self.reader.consume(1);
+                    continue;
+                }
+            }
+            read_any = true;
+            match available.iter().position(|&b| b == b'\n' || b == b'\r') {
+                Some(pos) => {
+                    line.extend_from_slice(&available[..pos]);
+                    self.skip_lf = available[pos] == b'\r';
+                    self.reader.consume(pos + 1);
+                    return Ok(Some(line));
+                }
+                None => {
+                    let len = available.len();
+                    line.extend_from_slice(available);
+                    self.reader.consume(len);
+                }
+            }
+        }
+    }
+}
+
+impl<B: BufRead> Iterator for UnixLines<B> {
+    type Item = io::Result<String>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.read_line() {
+            Ok(Some(line)) => Some(
+                String::from_utf8(line).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e)),
+            ),
+            Ok(None) => None,
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    fn lines(reader: impl BufRead) -> Vec<String> {
+        reader.unix_lines().collect::<io::Result<_>>().unwrap()
+    }
+
+    #[test]
+    fn every_line_ending_is_removed() {
+        assert_eq!(lines(&b"a\nb\n"[..]), ["a", "b"]);
+        assert_eq!(lines(&b"a\r\nb\r\n"[..]), ["a", "b"]);
+        assert_eq!(lines(&b"a\rb\r"[..]), ["a", "b"]);
+        assert_eq!(lines(&b"a\r\nb\rc\nd"[..]), ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn empty_lines_are_kept() {
+        assert_eq!(lines(&b"\n\r\n\r"[..]), ["", "", ""]);
+        assert_eq!(lines(&b"a\r\r\nb"[..]), ["a", "", "b"]);
+        assert_eq!(lines(&b"a\n\nb"[..]), ["a", "", "b"]);
+        assert!(lines(&b""[..]).is_empty());
+    }
+
+    #[test]
+    fn a_crlf_split_across_reads_is_one_line_ending() {
+        let split = (&b"a\r"[..]).chain(&b"\nb"[..]);
+        assert_eq!(lines(split), ["a", "b"]);
+        let split = (&b"a\r"[..]).chain(&b"\n"[..]);
+        assert_eq!(lines(split), ["a"]);
+        let split = (&b"a\r"[..]).chain(&b"\rb"[..]);
+        assert_eq!(lines(split), ["a", "", "b"]);
+    }
+
+    #[test]
+    fn one_byte_reads_split_lines_the_same() {
+        let input = b"one\r\ntwo\rthree\n\r\nfour\r";
+        let whole = lines(&input[..]);
+        assert_eq!(whole, ["one", "two", "three", "", "four"]);
+        assert_eq!(lines(io::BufReader::with_capacity(1, &input[..])), whole);
+    }
+
+    #[test]
+    fn invalid_utf8_is_an_error() {
+        let mut lines = (&b"ok\n\xff\nnext"[..]).unix_lines();
+        assert_eq!(lines.next().unwrap().unwrap(), "ok");
+        let e = lines.next().unwrap().unwrap_err();
+        assert_eq!(e.kind(), io::ErrorKind::InvalidData);
+        assert_eq!(lines.next().unwrap().unwrap(), "next");
+        assert!(lines.next().is_none());
+    }
+}
@@ -0,0 +1,167 @@
+//! The advisory lock taken on an input file while it is converted, with
+//! `lock`, so that two runs do not convert the same file at once.
+
+use std::fs::{self, File, Metadata};
+use std::io;
+use std::path::Path;
+
+use crate::nls::tr;
+
+/// An exclusive advisory lock on a file, held until dropped.
+///
+/// Other programs are only kept out if they take the same lock, as another
+/// run with `lock` does; writing the file is not prevented.
+pub(crate) struct FileLock {
+    file: File,
+}
+
+impl FileLock {
+    /// Locks `file`, opened from `path` and shown as `shown`, failing with
+    /// [`io::ErrorKind::WouldBlock`] rather than waiting if another program
+    /// holds the lock, or if the file was replaced while it was locked, as
+    /// another run converting it does.
+    pub(crate) fn acquire(file: File, path: &Path, shown: &Path) -> io::Result<FileLock> {
+        lock_file(&file)?;
+        let lock = FileLock { file };
+        if !same_file(&lock.metadata()?, &fs::metadata(path)?) {
+            return Err(io::Error::new(
+                io::ErrorKind::WouldBlock,
+                tr!(
+                    "'{}' was replaced while it was being locked",
+                    shown.display()
+                ),
+            ));
+        }
+        Ok(lock)
+    }
+
+    /// The metadata of the file locked, which is the file converted.
+    pub(crate) fn metadata(&self) -> io::Result<Metadata> {
+        self.file.metadata()
+    }
+}
+
+/// `flock`, the same lock as `flock(1)` takes.
+#[cfg(not(windows))]
+fn lock_file(file: &File) -> io::Result<()> {
+    use crate::nls::gettext;
+    use std::fs::TryLockError;
+
+    file.try_lock().map_err(|e| match e {
+        TryLockError::WouldBlock => io::Error::new(
+            io::ErrorKind::WouldBlock,
+            gettext("locked by another program"),
+        ),
+        TryLockError::Error(e) => e,
+    })
+}
+
+/// Locks one byte far past the end of any file with `LockFileEx`. A lock on
+/// the content itself would keep every other handle, even one of this
+/// process, from reading it, while this range is only looked at by those
+/// who lock it too.
+#[cfg(windows)]
+fn lock_file(file: &File) -> io::Result<()> {
+    use std::os::windows::io::AsRawHandle;
+    use winapi::um::fileapi::LockFileEx;
+    use winapi::um::minwinbase::{LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, OVERLAPPED};
+
+    unsafe {
+        let mut overlapped: OVERLAPPED = std::mem::zeroed();
+        overlapped.u.s_mut().Offset = u32::MAX;
+        overlapped.u.s_mut().OffsetHigh = u32::MAX >> 1;
+        let flags = LOCKFILE_EXCLUSIVE_LOCK | LOCKFILE_FAIL_IMMEDIATELY;
+        if LockFileEx(file.as_raw_handle() as _, flags, 0, 1, 0, &mut overlapped) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+/// Whether `a` and `b` are of the same file. Windows does not tell without
+/// unstable APIs, so there a file counts as the same.
+fn same_file(a: &Metadata, b: &Metadata) -> bool {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        a.dev() == b.dev() && a.ino() == b.ino()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (a, b);
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::is_locked;
+    use crate::{ConversionMode, ConversionOptions};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    fn scratch(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("dos2unix-{}-{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn acquire(path: &Path) -> io::Result<FileLock> {
+        FileLock::acquire(File::open(path)?, path, path)
+    }
+
+    #[test]
+    fn a_second_holder_is_refused_until_the_first_lets_go() {
+        let dir = scratch("lock");
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"one\r\n").unwrap();
+        let first = acquire(&path).unwrap();
+        let second = acquire(&path).map(drop);
+        drop(first);
+        let third = acquire(&path).map(drop);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(is_locked(&second.unwrap_err()));
+        third.unwrap();
+    }
+
+    #[test]
+    fn a_second_holder_with_retries_waits_for_the_first() {
+        let dir = scratch("lock-wait");
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"one\r\n").unwrap();
+        let first = acquire(&path).unwrap();
+        let holder = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(50));
+            drop(first);
+        });
+        let options = ConversionOptions {
+            retries: 100,
+            retry_delay: Duration::from_millis(10),
+            ..ConversionOptions::new(ConversionMode::ToUnix)
+        };
+        let second = crate::retry(&path, &options, || acquire(&path)).map(drop);
+        holder.join().unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        second.unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_file_replaced_before_it_is_locked_is_refused() {
+        let dir = scratch("lock-replaced");
+        let path = dir.join("notes.txt");
+        fs::write(&path, b"one\r\n").unwrap();
+        let opened = File::open(&path).unwrap();
+        fs::write(dir.join("new.txt"), b"one\n").unwrap();
+        fs::rename(dir.join("new.txt"), &path).unwrap();
+        let replaced = FileLock::acquire(opened, &path, Path::new("notes.txt")).map(drop);
+        fs::remove_dir_all(&dir).unwrap();
+        let error = replaced.unwrap_err();
+        assert!(is_locked(&error));
+        assert_eq!(
+            error.to_string(),
+            "'notes.txt' was replaced while it was being locked"
+        );
+    }
+}
@@ -0,0 +1,85 @@
+//! Recognition of common binary file formats by their extension or leading
+//! bytes, so that such files can be skipped without being read in full.
+
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::Path;
+
+/// Number of bytes read from the start of a file to look for a signature.
+const HEADER_LEN: u64 = 64;
+
+/// Signatures that cannot plausibly start a text file, with the name of the
+/// format they identify.
+const SIGNATURES: &[(&[u8], &str)] = &[
+    (b"\x89PNG\r\n\x1a\n", "PNG image"),
+    (b"\xFF\xD8\xFF", "JPEG image"),
+    (b"GIF87a", "GIF image"),
+    (b"GIF89a", "GIF image"),
+    (b"II*\x00", "TIFF image"),
+    (b"MM\x00*", "TIFF image"),
+    (b"\x00\x00\x01\x00", "Windows icon"),
+    (b"%PDF-", "PDF document"),
+    (b"PK\x03\x04", "ZIP archive"),
+    (b"PK\x05\x06", "ZIP archive"),
+    (b"\x1F\x8B", "gzip archive"),
+    (b"BZh", "bzip2 archive"),
+    (b"\xFD7zXZ\x00", "xz archive"),
+    (b"\x28\xB5\x2F\xFD", "Zstandard archive"),
+    (b"7z\xBC\xAF\x27\x1C", "7-Zip archive"),
+    (b"Rar!\x1A\x07", "RAR archive"),
+    (b"\x7FELF", "ELF executable"),
+    (b"\xFE\xED\xFA\xCE", "Mach-O executable"),
+    (b"\xFE\xED\xFA\xCF", "Mach-O executable"),
+    (b"\xCE\xFA\xED\xFE", "Mach-O executable"),
+    (b"\xCF\xFA\xED\xFE", "Mach-O executable"),
+    (b"\xCA\xFE\xBA\xBE", "Java class or Mach-O universal binary"),
+    (b"\x00asm", "WebAssembly module"),
+    (b"SQLite format 3\x00", "SQLite database"),
+    (b"OggS\x00", "Ogg media"),
+    (b"fLaC", "FLAC audio"),
+    (b"\x1A\x45\xDF\xA3", "Matroska/WebM video"),
+];
+
+/// File extensions of common binary formats, skipped unless told otherwise.
+pub const DEFAULT_SKIP_EXTENSIONS: &[&str] = &[
+    "png", "jpg", "jpeg", "gif", "bmp", "ico", "tif", "tiff", "webp", "pdf", "zip", "gz", "tgz",
+    "bz2", "xz", "zst", "7z", "rar", "jar", "war", "class", "exe", "dll", "so", "dylib", "o", "a",
+    "obj", "lib", "bin", "iso", "dmg", "mp3", "mp4", "mkv", "avi", "mov", "wav", "flac", "ogg",
+    "woff", "woff2", "ttf", "otf", "eot", "sqlite", "db", "pyc",
+];
+
+/// Returns the extension of `path` if it is one of `extensions`, compared
+/// case-insensitively.
+pub(crate) fn skipped_extension<'a>(path: &'a Path, extensions: &[String]) -> Option<&'a str> {
+    let extension = path.extension()?.to_str()?;
+    extensions
+        .iter()
+        .any(|skipped| skipped.eq_ignore_ascii_case(extension))
+        .then_some(extension)
+}
+
+/// Returns the name of the binary file format `header` starts with, if it
+/// is a well-known one.
+pub fn detect_file_type(header: &[u8]) -> Option<&'static str> {
+    if let Some(&(_, name)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| header.starts_with(signature))
+    {
+        return Some(name);
+    }
+    // "MZ" alone could start a text file, but the header of a DOS or Windows
+    // executable is padded with NUL bytes.
+    if header.starts_with(b"MZ") && header.contains(&0) {
+        return Some("DOS/Windows executable");
+    }
+    None
+}
+
+/// Reads the start of `file` and returns the name of its binary file format,
+/// if it is a well-known one. The file is left at its start again.
+pub(crate) fn sniff_file(mut file: &File) -> io::Result<Option<&'static str>> {
+    let mut header = Vec::with_capacity(HEADER_LEN as usize);
+    file.take(HEADER_LEN).read_to_end(&mut header)?;
+    file.seek(SeekFrom::Start(0))?;
+    Ok(detect_file_type(&header))
+}
@@ -0,0 +1,668 @@
+//! Translation of messages and help text into the language of the user's
+//! locale, from the gettext catalogs in `po/`, as GNU dos2unix does.
+//!
+//! Messages are marked with [`gettext`] and [`ngettext`], or with the
+//! [`tr!`] and [`trn!`] macros for those with placeholders, where the catalog
+//! extraction of `tests/messages.rs` finds them. A placeholder is `{}`, or
+//! `{0}`, `{1}` and so on in translations that need the values in another
+//! order.
+
+use std::collections::HashMap;
+use std::env;
+use std::fmt::{Display, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::OnceLock;
+
+/// The catalogs built in, by language.
+const CATALOGS: [(&str, &str); 2] = [
+    ("de", include_str!("../po/de.po")),
+    ("nl", include_str!("../po/nl.po")),
+];
+
+/// The catalog of the locale, once [`localize_messages`] has looked for it.
+static CATALOG: OnceLock<Option<Catalog>> = OnceLock::new();
+/// Whether messages are translated, which output read by programs turns off.
+static ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// The translations of one language.
+struct Catalog {
+    /// The forms of the translation of each message: one, or one for each
+    /// plural form of the language.
+    messages: HashMap<String, Vec<String>>,
+    /// Which plural form goes with a count.
+    plural: Plural,
+}
+
+/// Translates the messages of conversions, and the other messages of the
+/// command-line tools, into the language of the locale set by the
+/// `LANGUAGE`, `LC_ALL`, `LC_MESSAGES` and `LANG` environment variables, as
+/// GNU gettext picks it. Catalogs for German and Dutch are built in.
+///
+/// Messages are in English until this is called, as those of a C program
+/// are until it sets its locale, and stay in English if there is no catalog
+/// for the locale. Returns whether there is one.
+pub fn localize_messages() -> bool {
+    CATALOG
+        .get_or_init(|| requested_languages().iter().find_map(|lang| load(lang)))
+        .is_some()
+}
+
+/// Leaves messages in English from now on, for runs whose output is read by
+/// programs.
+pub(crate) fn disable() {
+    ENABLED.store(false, Ordering::Relaxed);
+}
+
+/// The languages asked for, most wanted first, as GNU gettext reads them:
+/// the list in `LANGUAGE`, then the locale of messages. A locale that is not
+/// set, `C` or `POSIX` asks for untranslated messages, whatever `LANGUAGE`
+/// says.
+fn requested_languages() -> Vec<String> {
+    let locale = ["LC_ALL", "LC_MESSAGES", "LANG"]
+        .into_iter()
+        .filter_map(|name| env::var(name).ok())
+        .find(|value| !value.is_empty())
+        .unwrap_or_default();
+    if matches!(language_name(&locale), "" | "C" | "POSIX") {
+        return Vec::new();
+    }
+    env::var("LANGUAGE")
+        .unwrap_or_default()
+        .split(':')
+        .chain([locale.as_str()])
+        .filter(|language| !language.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// The language and territory of `locale`, without its character set and
+/// modifier, such as `de_AT` for `de_AT.UTF-8@euro`.
+fn language_name(locale: &str) -> &str {
+    locale.split(['.', '@']).next().unwrap_or("")
+}
+
+/// The built-in catalog for `language`, or for the language alone if there
+/// is none for its territory, as `de` is for `de_CH`.
+fn load(language: &str) -> Option<Catalog> {
+    let name = language_name(language);
+    let base = name.split('_').next().unwrap_or(name);
+    [name, base]
+        .into_iter()
+        .find_map(|wanted| CATALOGS.iter().find(|(code, _)| *code == wanted))
+        .map(|(_, po)| parse_po(po))
+}
+
+fn catalog() -> Option<&'static Catalog> {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return None;
+    }
+    CATALOG.get()?.as_ref()
+}
+
+/// The translation of `msgid`, or `msgid` itself if there is none.
+pub(crate) fn gettext(msgid: &'static str) -> &'static str {
+    catalog()
+        .and_then(|catalog| catalog.messages.get(msgid))
+        .and_then(|forms| forms.first())
+        .map_or(msgid, String::as_str)
+}
+
+/// The translation of `msgid` in the plural form that goes with `n`, or
+/// `msgid` for 1 and `plural` for other counts if there is none.
+pub(crate) fn ngettext(msgid: &'static str, plural: &'static str, n: usize) -> &'static str {
+    let untranslated = if n == 1 { msgid } else { plural };
+    let Some(catalog) = catalog() else {
+        return untranslated;
+    };
+    catalog
+        .messages
+        .get(msgid)
+        .and_then(|forms| forms.get(catalog.plural.eval(n as u64) as usize))
+        .map_or(untranslated, String::as_str)
+}
+
+/// `template` with its placeholders replaced by `args`: `{}` by the next
+/// one, and `{0}`, `{1}` and so on by the one at that index. `{{` and `}}`
+/// stand for braces.
+pub(crate) fn format(template: &str, args: &[&dyn Display]) -> String {
+    let mut formatted = String::with_capacity(template.len());
+    let mut next = 0;
+    let mut rest = template;
+    while let Some(brace) = rest.find(['{', '}']) {
+        formatted.push_str(&rest[..brace]);
+        rest = &rest[brace..];
+        if rest.starts_with("{{") || rest.starts_with("}}") {
+            formatted.push_str(&rest[..1]);
+            rest = &rest[2..];
+            continue;
+        }
+        match rest.strip_prefix('{').and_then(|rest| rest.split_once('}')) {
+            Some((index, after)) if index.bytes().all(|b| b.is_ascii_digit()) => {
+                let index = if index.is_empty() {
+                    next += 1;
+                    Some(next - 1)
+                } else {
+                    index.parse().ok()
+                };
+                if let Some(arg) = index.and_then(|index| args.get(index)) {
+                    let _ = write!(formatted, "{}", arg);
+                }
+                rest = after;
+            }
+            _ => {
+                formatted.push_str(&rest[..1]);
+                rest = &rest[1..];
+            }
+        }
+    }
+    formatted.push_str(rest);
+    formatted
+}
+
+/// Translates the message given as a literal and fills in its placeholders
+/// with the arguments that follow.
+macro_rules! tr {
+    ($msgid:literal $(, $arg:expr)* $(,)?) => {
+        $crate::nls::format(
+            $crate::nls::gettext($msgid),
+            &[$(&$arg as &dyn ::std::fmt::Display),*],
+        )
+    };
+}
+
+/// Translates the message given as a singular and a plural literal in the
+/// form that goes with the count that follows, and fills in its
+/// placeholders with the arguments after the count.
+macro_rules! trn {
+    ($msgid:literal, $plural:literal, $n:expr $(, $arg:expr)* $(,)?) => {
+        $crate::nls::format(
+            $crate::nls::ngettext($msgid, $plural, $n),
+            &[$(&$arg as &dyn ::std::fmt::Display),*],
+        )
+    };
+}
+
+pub(crate) use {tr, trn};
+
+/// The entry of a `.po` file being read.
+#[derive(Default)]
+struct Entry {
+    msgid: Option<String>,
+    msgstr: Vec<String>,
+    /// Whether the translation is marked as a guess still to be checked.
+    fuzzy: bool,
+    /// Whether the message has a context, which none of ours have.
+    context: bool,
+}
+
+/// Which string of an entry the lines of a `.po` file continue.
+#[derive(Copy, Clone)]
+enum Field {
+    Msgid,
+    Msgstr(usize),
+    Other,
+}
+
+/// Reads the translations of the `.po` file `po`, leaving out those that
+/// are missing or marked fuzzy, and the plural formula of its header.
+fn parse_po(po: &str) -> Catalog {
+    let mut catalog = Catalog {
+        messages: HashMap::new(),
+        plural: Plural::default(),
+    };
+    let mut entry = Entry::default();
+    let mut field = Field::Other;
+    for line in po.lines().map(str::trim) {
+        // A comment or a new message ends the entry before it
+        let starts_entry = line.starts_with('#') || line.starts_with("msgid ");
+        if line.is_empty() || (starts_entry && !entry.msgstr.is_empty()) {
+            finish_entry(&mut catalog, std::mem::take(&mut entry));
+        }
+        if line.starts_with("#,") {
+            entry.fuzzy |= line.split([',', ' ']).any(|flag| flag == "fuzzy");
+            continue;
+        }
+        let (keyword, value) = match line.split_once(' ') {
+            Some((keyword, value)) if !line.starts_with('"') => (keyword, value),
+            _ => ("", line),
+        };
+        field = match keyword {
+            "" => field,
+            "msgid" => Field::Msgid,
+            "msgstr" => Field::Msgstr(0),
+            "msgctxt" => {
+                entry.context = true;
+                Field::Other
+            }
+            _ => match keyword
+                .strip_prefix("msgstr[")
+                .and_then(|index| index.strip_suffix(']'))
+            {
+                Some(index) => index.parse().map_or(Field::Other, Field::Msgstr),
+                None => Field::Other,
+            },
+        };
+        let Some(text) = value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+        else {
+            continue;
+        };
+        let text = unescape(text);
+        match field {
+            Field::Msgid => entry.msgid.get_or_insert_with(String::new).push_str(&text),
+            Field::Msgstr(index) => {
+                if entry.msgstr.len() <= index {
+                    entry.msgstr.resize(index + 1, String::new());
+                }
+                entry.msgstr[index].push_str(&text);
+            }
+            Field::Other => {}
+        }
+    }
+    finish_entry(&mut catalog, entry);
+    catalog
+}
+
+/// Adds the translation of `entry` to `catalog`, or takes the plural
+/// formula from it if it is the header.
+fn finish_entry(catalog: &mut Catalog, entry: Entry) {
+    let Some(msgid) = entry.msgid else {
+        return;
+    };
+    if msgid.is_empty() {
+        let formula = entry.msgstr.first().and_then(|header| {
+            let forms = header
+                .lines()
+                .find_map(|line| line.strip_prefix("Plural-Forms:"))?;
+            let formula = forms.split_once("plural=")?.1;
+            Plural::parse(formula.split(';').next()?)
+        });
+        if let Some(plural) = formula {
+            catalog.plural = plural;
+        }
+    } else if !entry.fuzzy
+        && !entry.context
+        && !entry.msgstr.is_empty()
+        && entry.msgstr.iter().all(|form| !form.is_empty())
+    {
+        catalog.messages.insert(msgid, entry.msgstr);
+    }
+}
+
+/// Undoes the backslash escapes of a `.po` string.
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// The formula of a `Plural-Forms` header, such as `n != 1`, which gives
+/// the plural form that goes with a count `n`. It is a C expression.
+enum Plural {
+    Count,
+    Number(u64),
+    Not(Box<Plural>),
+    Operation(&'static str, Box<Plural>, Box<Plural>),
+    Choice(Box<Plural>, Box<Plural>, Box<Plural>),
+}
+
+/// Binary operators of plural formulas, from the loosest to the tightest
+/// binding.
+const OPERATORS: [&[&str]; 6] = [
+    &["||"],
+    &["&&"],
+    &["==", "!="],
+    &["<=", ">=", "<", ">"],
+    &["+", "-"],
+    &["*", "/", "%"],
+];
+
+/// The other symbols of plural formulas.
+const SYMBOLS: [&str; 5] = ["!", "?", ":", "(", ")"];
+
+#[derive(Copy, Clone, PartialEq)]
+enum Token {
+    Count,
+    Number(u64),
+    Symbol(&'static str),
+}
+
+impl Default for Plural {
+    /// The formula of English, and of German and Dutch.
+    fn default() -> Plural {
+        Plural::Operation("!=", Box::new(Plural::Count), Box::new(Plural::Number(1)))
+    }
+}
+
+impl Plural {
+    fn parse(formula: &str) -> Option<Plural> {
+        let tokens = tokenize(formula)?;
+        let mut parser = PluralParser {
+            tokens: &tokens,
+            next: 0,
+        };
+        let plural = parser.choice()?;
+        (parser.next == tokens.len()).then_some(plural)
+    }
+
+    fn eval(&self, n: u64) -> u64 {
+        match self {
+            Plural::Count => n,
+            Plural::Number(number) => *number,
+            Plural::Not(operand) => (operand.eval(n) == 0) as u64,
+            Plural::Choice(condition, then, otherwise) => {
+                if condition.eval(n) != 0 {
+                    then.eval(n)
+                } else {
+                    otherwise.eval(n)
+                }
+            }
+            Plural::Operation(operator, left, right) => {
+                let (left, right) = (left.eval(n), right.eval(n));
+                match *operator {
+                    "||" => (left != 0 || right != 0) as u64,
+                    "&&" => (left != 0 && right != 0) as u64,
+                    "==" => (left == right) as u64,
+                    "!=" => (left != right) as u64,
+                    "<=" => (left <= right) as u64,
+                    ">=" => (left >= right) as u64,
+                    "<" => (left < right) as u64,
+                    ">" => (left > right) as u64,
+                    "+" => left.wrapping_add(right),
+                    "-" => left.wrapping_sub(right),
+                    "*" => left.wrapping_mul(right),
+                    "/" => left.checked_div(right).unwrap_or(0),
+                    _ => left.checked_rem(right).unwrap_or(0),
+                }
+            }
+        }
+    }
+}
+
+/// Splits a plural formula into tokens, or gives `None` if it has anything
+/// else in it.
+fn tokenize(formula: &str) -> Option<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = formula.trim_start();
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let (token, len) = if digits > 0 {
+            (Token::Number(rest[..digits].parse().ok()?), digits)
+        } else if rest.starts_with('n') {
+            (Token::Count, 1)
+        } else {
+            // Two-character operators come first, so that `<=` is not `<`
+            let symbol = OPERATORS
+                .iter()
+                .flat_map(|level| level.iter())
+                .chain(SYMBOLS.iter())
+                .copied()
+                .filter(|symbol| rest.starts_with(symbol))
+                .max_by_key(|symbol| symbol.len())?;
+            (Token::Symbol(symbol), symbol.len())
+        };
+        tokens.push(token);
+        rest = rest[len..].trim_start();
+    }
+    Some(tokens)
+}
+
+/// A recursive-descent parser of the tokens of a plural formula.
+struct PluralParser<'a> {
+    tokens: &'a [Token],
+    next: usize,
+}
+
+impl PluralParser<'_> {
+    /// Takes the next token if it is `symbol`.
+    fn eat(&mut self, symbol: &'static str) -> bool {
+        let found = self.tokens.get(self.next) == Some(&Token::Symbol(symbol));
+        self.next += found as usize;
+        found
+    }
+
+    /// A conditional expression, `condition ? then : otherwise`, or any
+    /// expression binding tighter.
+    fn choice(&mut self) -> Option<Plural> {
+        let condition = self.operation(0)?;
+        if !self.eat("?") {
+            return Some(condition);
+        }
+        let then = self.choice()?;
+        if !self.eat(":") {
+            return None;
+        }
+        let otherwise = self.choice()?;
+        Some(Plural::Choice(
+            Box::new(condition),
+            Box::new(then),
+            Box::new(otherwise),
+        ))
+    }
+
+    /// An expression of the operators of `OPERATORS[level]`, or of those
+    /// binding tighter.
+    fn operation(&mut self, level: usize) -> Option<Plural> {
+        let Some(operators) = OPERATORS.get(level) else {
+            return self.operand();
+        };
+        let mut left = self.operation(level + 1)?;
+        while let Some(&Token::Symbol(operator)) = self.tokens.get(self.next) {
+            if !operators.contains(&operator) {
+                break;
+            }
+            self.next += 1;
+            let right = self.operation(level + 1)?;
+            left = Plural::Operation(operator, Box::new(left), Box::new(right));
+        }
+        Some(left)
+    }
+
+    /// `n`, a number, a negation or an expression in parentheses.
+    fn operand(&mut self) -> Option<Plural> {
+        if self.eat("!") {
+            return Some(Plural::Not(Box::new(self.operand()?)));
+        }
+        if self.eat("(") {
+            let inner = self.choice()?;
+            return self.eat(")").then_some(inner);
+        }
+        let token = *self.tokens.get(self.next)?;
+        self.next += 1;
+        match token {
+            Token::Count => Some(Plural::Count),
+            Token::Number(number) => Some(Plural::Number(number)),
+            Token::Symbol(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The plural forms `formula` gives for each of `counts`.
+    fn forms(formula: &str, counts: &[u64]) -> Vec<u64> {
+        let plural = Plural::parse(formula).unwrap();
+        counts.iter().map(|&n| plural.eval(n)).collect()
+    }
+
+    fn translation<'a>(catalog: &'a Catalog, msgid: &str) -> Option<&'a [String]> {
+        catalog.messages.get(msgid).map(Vec::as_slice)
+    }
+
+    #[test]
+    fn escapes_are_undone() {
+        let catalog = parse_po(concat!(
+            "msgid \"say \\\"{}\\\"\\n\"\n",
+            "msgstr \"sag \\\"{}\\\"\\n\\ttab\\\\\"\n",
+        ));
+        assert_eq!(
+            translation(&catalog, "say \"{}\"\n"),
+            Some(&[String::from("sag \"{}\"\n\ttab\\")][..])
+        );
+        assert_eq!(unescape("a\\rb\\"), "a\rb");
+    }
+
+    #[test]
+    fn strings_continue_over_lines() {
+        let catalog = parse_po(concat!(
+            "# A comment\n",
+            "#: src/cli.rs\n",
+            "msgid \"\"\n",
+            "\"first \"\n",
+            "\"second\"\n",
+            "msgstr \"\"\n",
+            "\"erste \"\n",
+            "  \"zweite\"\n",
+            "msgid \"next\"\n",
+            "msgstr \"nächste\"\n",
+        ));
+        assert_eq!(
+            translation(&catalog, "first second"),
+            Some(&[String::from("erste zweite")][..])
+        );
+        assert_eq!(
+            translation(&catalog, "next"),
+            Some(&[String::from("nächste")][..])
+        );
+    }
+
+    #[test]
+    fn messages_with_a_context_fuzzy_or_missing_translations_are_left_out() {
+        let catalog = parse_po(concat!(
+            "msgctxt \"menu\"\n",
+            "msgid \"open\"\n",
+            "msgstr \"öffnen\"\n",
+            "\n",
+            "#, fuzzy, c-format\n",
+            "msgid \"guess\"\n",
+            "msgstr \"Vermutung\"\n",
+            "\n",
+            "msgid \"missing\"\n",
+            "msgstr \"\"\n",
+            "\n",
+            "msgid \"{} file\"\n",
+            "msgid_plural \"{} files\"\n",
+            "msgstr[0] \"{} Datei\"\n",
+            "msgstr[1] \"\"\n",
+            "\n",
+            "msgid \"kept\"\n",
+            "msgstr \"behalten\"\n",
+        ));
+        assert_eq!(catalog.messages.len(), 1);
+        assert!(translation(&catalog, "kept").is_some());
+    }
+
+    #[test]
+    fn plural_forms_come_from_the_header() {
+        let catalog = parse_po(concat!(
+            "msgid \"\"\n",
+            "msgstr \"\"\n",
+            "\"Content-Type: text/plain; charset=UTF-8\\n\"\n",
+            "\"Plural-Forms: nplurals=3; plural=(n==1 ? 0 : n%10>=2 && n%10<=4 && \"\n",
+            "\"(n%100<10 || n%100>=20) ? 1 : 2);\\n\"\n",
+            "\n",
+            "msgid \"{} file\"\n",
+            "msgid_plural \"{} files\"\n",
+            "msgstr[0] \"{} plik\"\n",
+            "msgstr[1] \"{} pliki\"\n",
+            "msgstr[2] \"{} plików\"\n",
+        ));
+        let plural = [1, 2, 5, 22].map(|n| catalog.plural.eval(n));
+        assert_eq!(plural, [0, 1, 2, 1]);
+        assert_eq!(translation(&catalog, "{} file").map(<[_]>::len), Some(3));
+    }
+
+    #[test]
+    fn germanic_plurals_tell_one_from_the_rest() {
+        assert_eq!(forms("n != 1", &[0, 1, 2, 11]), [1, 0, 1, 1]);
+        assert_eq!(forms(" (n!=1) ", &[0, 1, 2]), [1, 0, 1]);
+        assert_eq!(Plural::default().eval(1), 0);
+        assert_eq!(Plural::default().eval(7), 1);
+    }
+
+    #[test]
+    fn slavic_plurals_chain_choices() {
+        let polish = "(n==1 ? 0 : n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2)";
+        let counts = [0, 1, 2, 4, 5, 11, 12, 14, 21, 22, 25, 102, 112];
+        assert_eq!(
+            forms(polish, &counts),
+            [2, 0, 1, 1, 2, 2, 2, 2, 2, 1, 2, 1, 2]
+        );
+        let russian = "n%10==1 && n%100!=11 ? 0 : \
+                       n%10>=2 && n%10<=4 && (n%100<10 || n%100>=20) ? 1 : 2";
+        assert_eq!(
+            forms(russian, &counts),
+            [2, 0, 1, 1, 2, 2, 2, 2, 0, 1, 2, 1, 2]
+        );
+    }
+
+    #[test]
+    fn arithmetic_does_not_fail() {
+        assert_eq!(forms("n / 0 + n % 0", &[5]), [0]);
+        assert_eq!(forms("0 - 1 > n", &[5]), [1]);
+        assert_eq!(forms("!n || !(n - 1)", &[0, 1, 2]), [1, 1, 0]);
+    }
+
+    #[test]
+    fn malformed_formulas_are_refused() {
+        for formula in [
+            "",
+            "n !",
+            "n ? 1",
+            "(n != 1",
+            "n != 1)",
+            "n x 1",
+            "n != 99999999999999999999999",
+            "n == 1 ? : 2",
+        ] {
+            assert!(Plural::parse(formula).is_none(), "{formula:?}");
+        }
+    }
+
+    #[test]
+    fn malformed_catalogs_fall_back() {
+        let catalog = parse_po(concat!(
+            "msgid \"\"\n",
+            "msgstr \"Plural-Forms: nplurals=2; plural=n ? ? 1;\\n\"\n",
+            "\n",
+            "msgid \"unterminated\n",
+            "msgstr \"offen\"\n",
+            "\n",
+            "msgid \"index\"\n",
+            "msgstr[x] \"Index\"\n",
+            "\"stray\"\n",
+            "\n",
+            "msgid \"escape\"\n",
+            "msgstr \"\\\"\n",
+            "\n",
+            "msgid_plural\n",
+            "msgid \"after\"\n",
+            "msgstr \"danach\"\n",
+        ));
+        assert_eq!(catalog.plural.eval(1), 0);
+        assert_eq!(catalog.plural.eval(2), 1);
+        assert!(translation(&catalog, "unterminated").is_none());
+        assert!(translation(&catalog, "index").is_none());
+        assert!(translation(&catalog, "escape").is_none());
+        assert_eq!(
+            translation(&catalog, "after"),
+            Some(&[String::from("danach")][..])
+        );
+    }
+}
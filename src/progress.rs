@@ -0,0 +1,144 @@
+//! The progress bar shown on a terminal while converting many or large
+//! files, so that long runs give some sign of how far they have got.
+
+use std::fs;
+use std::io::{self, IsTerminal, Write};
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+/// Number of files above which a run shows a progress bar.
+const PROGRESS_MIN_FILES: usize = 20;
+/// Total size of the files above which a run shows a progress bar, however
+/// few they are.
+const PROGRESS_MIN_BYTES: u64 = 64 * 1024 * 1024;
+
+/// Width of the bar itself, in characters.
+const BAR_WIDTH: usize = 24;
+/// Width the whole line is kept within, as the width of the terminal is
+/// not known.
+const LINE_WIDTH: usize = 79;
+/// How often the bar is redrawn at most, unless it was cleared.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A progress bar on stderr, weighted by the size of the files, with the
+/// number of files done, the time left and the file being converted.
+pub(crate) struct ProgressBar {
+    /// Size of each file, as it was before converting.
+    sizes: Vec<u64>,
+    files_done: usize,
+    bytes: u64,
+    bytes_done: u64,
+    started: Instant,
+    drawn: Option<Instant>,
+    current: String,
+}
+
+impl ProgressBar {
+    /// A progress bar for converting `paths`, if stderr is a terminal and
+    /// there are more than [`PROGRESS_MIN_FILES`] of them or they add up to
+    /// more than [`PROGRESS_MIN_BYTES`].
+    pub(crate) fn for_files<'a>(paths: impl Iterator<Item = &'a Path>) -> Option<ProgressBar> {
+        if !io::stderr().is_terminal() {
+            return None;
+        }
+        let sizes: Vec<u64> = paths.map(file_size).collect();
+        let bytes = sizes.iter().sum();
+        (sizes.len() > PROGRESS_MIN_FILES || bytes > PROGRESS_MIN_BYTES).then(|| ProgressBar {
+            sizes,
+            files_done: 0,
+            bytes,
+            bytes_done: 0,
+            started: Instant::now(),
+            drawn: None,
+            current: String::new(),
+        })
+    }
+
+    /// Shows `path` as the file being converted.
+    pub(crate) fn start_file(&mut self, path: &Path) {
+        self.current = path.display().to_string();
+        self.draw();
+    }
+
+    /// Counts the file at `index` of the paths the bar was made for as done.
+    pub(crate) fn finish_file(&mut self, index: usize) {
+        self.files_done += 1;
+        self.bytes_done += self.sizes[index];
+        self.current.clear();
+        self.draw();
+    }
+
+    /// Removes the bar from the terminal, before a message is printed. It
+    /// comes back with the next update.
+    pub(crate) fn clear(&mut self) {
+        if self.drawn.take().is_some() {
+            eprint!("\r{:1$}\r", "", LINE_WIDTH);
+        }
+    }
+
+    fn draw(&mut self) {
+        let now = Instant::now();
+        if self
+            .drawn
+            .is_some_and(|drawn| now - drawn < REDRAW_INTERVAL)
+        {
+            return;
+        }
+        // Sizes stand for the work done, but a run of empty files still moves
+        let (done, total) = if self.bytes > 0 {
+            (self.bytes_done as f64, self.bytes as f64)
+        } else {
+            (self.files_done as f64, self.sizes.len().max(1) as f64)
+        };
+        let fraction = done / total;
+        let filled = (fraction * BAR_WIDTH as f64) as usize;
+        let eta = if done > 0.0 {
+            let elapsed = self.started.elapsed().as_secs_f64();
+            format_duration(elapsed * (total - done) / done)
+        } else {
+            String::from("--:--")
+        };
+        let mut line = format!(
+            "[{}{}] {}/{} ETA {} ",
+            "#".repeat(filled),
+            "-".repeat(BAR_WIDTH - filled),
+            self.files_done,
+            self.sizes.len(),
+            eta
+        );
+        let room = LINE_WIDTH.saturating_sub(line.chars().count());
+        let name_len = self.current.chars().count();
+        if name_len > room {
+            // The end of a path tells more about the file than its start
+            line.push_str("...");
+            line.extend(self.current.chars().skip(name_len + 3 - room.max(3)));
+        } else {
+            line.push_str(&self.current);
+        }
+        eprint!("\r{:1$}", line, LINE_WIDTH);
+        let _ = io::stderr().flush();
+        self.drawn = Some(now);
+    }
+}
+
+impl Drop for ProgressBar {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// Size of the file at `path`, or 0 if it cannot be told.
+fn file_size(path: &Path) -> u64 {
+    fs::metadata(path).map_or(0, |metadata| metadata.len())
+}
+
+/// `seconds` as minutes and seconds, or hours, minutes and seconds.
+fn format_duration(seconds: f64) -> String {
+    let seconds = seconds.round() as u64;
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if hours > 0 {
+        format!("{}:{:02}:{:02}", hours, minutes, seconds)
+    } else {
+        format!("{}:{:02}", minutes, seconds)
+    }
+}
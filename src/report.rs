@@ -0,0 +1,57 @@
+//! Where the messages of a conversion go, such as the files converted and,
+//! with a `verbose` of 3 or more, each line break.
+
+use std::fmt;
+
+/// Receives the messages a conversion reports, which by default are written
+/// to stderr by [`StderrReporter`]. Set [`ConversionOptions::reporter`] to
+/// collect them, log them or leave them out instead.
+///
+/// Errors are returned rather than reported, although a `verbose` of 2 or
+/// more also reports those that stop a file from being converted, such as
+/// binary content.
+///
+/// [`ConversionOptions::reporter`]: crate::ConversionOptions::reporter
+pub trait Reporter: Send + Sync {
+    /// Receives `message`, which starts with the program name, as in
+    /// `dos2unix: converting file notes.txt to Unix format...`. `level` is
+    /// the `verbose` setting it was reported at: 1 for the message of each
+    /// file, 2 for the details of a file, 3 for one a line.
+    fn report(&self, level: usize, message: &str);
+}
+
+impl fmt::Debug for dyn Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Reporter")
+    }
+}
+
+/// The default [`Reporter`], which writes each message to stderr on a line
+/// of its own.
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StderrReporter;
+
+impl Reporter for StderrReporter {
+    fn report(&self, _level: usize, message: &str) {
+        eprintln!("{}", message);
+    }
+}
+
+/// A [`Reporter`] that passes each message on to the `tracing` facade, as
+/// an `INFO` event for a `level` of 1, a `DEBUG` event for a `level` of 2
+/// and a `TRACE` event above that, with a target of `dos2unix_lib`. The
+/// events of a file are within a `convert` span with the `path` of the file.
+#[cfg(feature = "tracing")]
+#[derive(Copy, Clone, Debug, Default)]
+pub struct TracingReporter;
+
+#[cfg(feature = "tracing")]
+impl Reporter for TracingReporter {
+    fn report(&self, level: usize, message: &str) {
+        match level {
+            0 | 1 => tracing::info!(target: "dos2unix_lib", "{}", message),
+            2 => tracing::debug!(target: "dos2unix_lib", "{}", message),
+            _ => tracing::trace!(target: "dos2unix_lib", "{}", message),
+        }
+    }
+}
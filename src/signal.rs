@@ -0,0 +1,102 @@
+//! Stopping a run on Ctrl-C or `SIGTERM` without leaving temporary files or
+//! half-replaced output behind.
+//!
+//! The handler only records the signal. Conversions check for it between
+//! chunks and before they replace their output, and fail with
+//! [`ErrorCode::Interrupted`], which removes their temporary file as it is
+//! dropped; the command line stops starting new files. A second signal ends
+//! the process at once, as if no handler were installed.
+
+use std::io;
+use std::sync::atomic::{AtomicI32, Ordering};
+
+use crate::error::{coded, ErrorCode};
+use crate::nls::gettext;
+
+/// The number of the signal received, or 0 while there is none.
+static RECEIVED: AtomicI32 = AtomicI32::new(0);
+
+/// The number of `SIGINT`, which Ctrl-C and Ctrl-Break count as on Windows.
+#[cfg(windows)]
+const SIGINT: i32 = 2;
+
+/// Whether a signal to stop was received.
+pub(crate) fn interrupted() -> bool {
+    RECEIVED.load(Ordering::SeqCst) != 0
+}
+
+/// Fails with [`ErrorCode::Interrupted`] once a signal to stop was received.
+pub(crate) fn check() -> io::Result<()> {
+    if interrupted() {
+        return Err(coded(
+            io::ErrorKind::Interrupted,
+            ErrorCode::Interrupted,
+            gettext("interrupted").into(),
+        ));
+    }
+    Ok(())
+}
+
+/// The exit status for the signal received, 128 plus its number as shells
+/// report it, such as 130 for Ctrl-C, or `None` if there was none.
+pub(crate) fn exit_status() -> Option<i32> {
+    match RECEIVED.load(Ordering::SeqCst) {
+        0 => None,
+        signal => Some(128 + signal),
+    }
+}
+
+/// Handles `SIGINT` and `SIGTERM` by recording them, unless they were
+/// ignored when the process started, as for a background job.
+///
+/// The handler is installed without `SA_RESTART`, so that a read from the
+/// terminal, such as for `--interactive`, is interrupted too.
+#[cfg(unix)]
+pub(crate) fn install() {
+    extern "C" fn handle(signal: libc::c_int) {
+        if RECEIVED.swap(signal, Ordering::SeqCst) != 0 {
+            // Both are async-signal-safe
+            unsafe {
+                libc::signal(signal, libc::SIG_DFL);
+                libc::raise(signal);
+            }
+        }
+    }
+    for signal in [libc::SIGINT, libc::SIGTERM] {
+        unsafe {
+            let mut previous: libc::sigaction = std::mem::zeroed();
+            if libc::sigaction(signal, std::ptr::null(), &mut previous) != 0
+                || previous.sa_sigaction == libc::SIG_IGN
+            {
+                continue;
+            }
+            let mut action: libc::sigaction = std::mem::zeroed();
+            action.sa_sigaction = handle as extern "C" fn(libc::c_int) as libc::sighandler_t;
+            libc::sigemptyset(&mut action.sa_mask);
+            libc::sigaction(signal, &action, std::ptr::null_mut());
+        }
+    }
+}
+
+/// Handles Ctrl-C and Ctrl-Break by recording them. Closing the console
+/// still ends the process, which Windows does not wait for.
+#[cfg(windows)]
+pub(crate) fn install() {
+    use winapi::shared::minwindef::{BOOL, DWORD, FALSE, TRUE};
+    use winapi::um::consoleapi::SetConsoleCtrlHandler;
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    unsafe extern "system" fn handle(event: DWORD) -> BOOL {
+        match event {
+            // FALSE leaves a second one to the default handler, which exits
+            CTRL_C_EVENT | CTRL_BREAK_EVENT if RECEIVED.swap(SIGINT, Ordering::SeqCst) == 0 => TRUE,
+            _ => FALSE,
+        }
+    }
+    unsafe {
+        SetConsoleCtrlHandler(Some(handle), TRUE);
+    }
+}
+
+#[cfg(not(any(unix, windows)))]
+pub(crate) fn install() {}
@@ -0,0 +1,790 @@
+//! Chunked conversion of files too large to read into memory whole.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::mem;
+use std::path::Path;
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::Duration;
+
+use crate::binary::{BinaryPolicy, BinaryScan};
+use crate::bom::BomKind;
+use crate::codepage;
+use crate::detect::{
+    count_unicode_breaks, detect_line_endings, LineEndingScanner, LineEndingStats,
+};
+use crate::error::{coded, ErrorCode};
+use crate::signal;
+use crate::temp::TempFile;
+use crate::{
+    binary_found, convert_chunk, convert_scanning, convert_with_options, finish_conversion,
+    mixed_line_endings_message, ConversionOptions, ProcessedFile, Progress,
+};
+
+/// Size in bytes above which
+/// [`process_file_with_options`](crate::process_file_with_options) converts
+/// a file in chunks instead of reading it into memory whole.
+pub const STREAM_THRESHOLD: u64 = 64 * 1024 * 1024;
+
+/// The default of [`ConversionOptions::buffer_size`].
+pub const DEFAULT_BUFFER_SIZE: usize = 1024 * 1024;
+
+/// How many chunks [`convert_pipe`] lets pile up between its threads.
+const PIPE_DEPTH: usize = 4;
+
+/// How often [`convert_pipe`] looks for a signal to stop while it waits for
+/// input, which may not come for a long time.
+const SIGNAL_POLL: Duration = Duration::from_millis(100);
+
+/// Whether a conversion with `options` can be done a chunk at a time.
+/// Guessing the line break with `auto`, validating UTF-8, transcoding, and
+/// judging binary files by the validity of their text all need the whole
+/// input, and rewriting a file through its own descriptor with `in_place`
+/// would overwrite input not read yet.
+pub(crate) fn supports(options: &ConversionOptions) -> bool {
+    !options.auto
+        && !options.check_utf8
+        && !options.in_place
+        && options.bom.is_none()
+        && options.from_encoding.is_none()
+        && options.to_encoding.is_none()
+        && !options.binary_policy.utf8_aware
+}
+
+/// Whether [`process_file_with_options`](crate::process_file_with_options)
+/// converts a file of `len` bytes a chunk at a time: when asked to with
+/// `stream`, or when it is larger than [`STREAM_THRESHOLD`], and `options`
+/// allow it.
+pub(crate) fn streams(options: &ConversionOptions, len: u64) -> bool {
+    (options.stream || len > STREAM_THRESHOLD) && supports(options)
+}
+
+/// A file converted by [`convert_file`], whose output is in a temporary file
+/// that has not replaced the output file yet.
+pub(crate) struct Streamed {
+    /// Line break counts of the input.
+    pub(crate) stats: LineEndingStats,
+    /// Whether the conversion changed the content.
+    pub(crate) changed: bool,
+    pub(crate) temp: TempFile,
+}
+
+/// Converts `input` with `options` a chunk at a time, writing the result to
+/// a temporary file beside `output_path`.
+///
+/// UTF-16 and UTF-32 input gives `None`, and is left to the conversion in
+/// memory.
+pub(crate) fn convert_file(
+    input: &File,
+    output_path: &Path,
+    options: &ConversionOptions,
+) -> io::Result<Option<Streamed>> {
+    let mut temp = TempFile::create(output_path)?;
+    let mut buffer = vec![0; options.buffer_size.max(1)];
+    let mut data = Vec::with_capacity(buffer.len());
+    let converted = convert_stream(
+        &mut data,
+        &mut |data| fill(input, &mut buffer, data),
+        &mut |output| temp.file().write_all(output),
+        options,
+    )?;
+    Ok(converted.map(|(stats, changed)| Streamed {
+        stats,
+        changed,
+        temp,
+    }))
+}
+
+/// The line breaks of `input`, and whether it looks binary by
+/// `policy`, found a chunk at a time without converting it, so that a file
+/// of any size is judged in little memory. A file with a UTF-16 or UTF-32
+/// BOM, or a `utf8_aware` policy, which judges the text as a whole, gives
+/// `None`.
+pub(crate) fn scan_file(
+    input: &File,
+    policy: BinaryPolicy,
+) -> io::Result<Option<(LineEndingStats, bool)>> {
+    if policy.utf8_aware {
+        return Ok(None);
+    }
+    let mut buffer = vec![0; DEFAULT_BUFFER_SIZE];
+    let mut data = Vec::with_capacity(buffer.len());
+    let mut eof = false;
+    while !eof && data.len() < 4 {
+        eof = fill(input, &mut buffer, &mut data)?;
+    }
+    let bom = BomKind::detect(&data);
+    if bom.is_some_and(|bom| bom.unit_size() > 1) {
+        return Ok(None);
+    }
+    let mut scanner = LineEndingScanner::new(bom);
+    let mut unicode = 0;
+    let mut scan = BinaryScan::new(policy);
+    let mut binary = false;
+    let mut start = bom.map_or(0, |bom| bom.bytes().len());
+    loop {
+        let end = if eof {
+            data.len()
+        } else {
+            split_point(&data).max(start)
+        };
+        let chunk = &data[start..end];
+        scanner.feed(chunk.iter().map(|&byte| byte as u32));
+        unicode += count_unicode_breaks(chunk);
+        binary = binary
+            || chunk
+                .iter()
+                .enumerate()
+                .any(|(idx, &byte)| scan.check(byte, idx));
+        scan.advance(chunk.len());
+        if eof {
+            break;
+        }
+        data.drain(..end);
+        start = 0;
+        eof = fill(input, &mut buffer, &mut data)?;
+    }
+    let mut stats = scanner.finish();
+    stats.unicode = unicode;
+    Ok(Some((stats, binary)))
+}
+
+/// Converts the input that `read` gives a chunk at a time, handing the
+/// output to `write`, and returns the line break counts of the input and
+/// whether the conversion changed it.
+///
+/// `read` appends the next piece of the input to `data` and tells whether
+/// the input has ended. UTF-16 and UTF-32 input gives `None` with nothing
+/// written, leaving what was read of it in `data`.
+fn convert_stream(
+    data: &mut Vec<u8>,
+    read: &mut dyn FnMut(&mut Vec<u8>) -> io::Result<bool>,
+    write: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    options: &ConversionOptions,
+) -> io::Result<Option<(LineEndingStats, bool)>> {
+    let ConversionOptions {
+        conversion_mode,
+        force,
+        verbose,
+        ..
+    } = *options;
+    let mut output = Vec::with_capacity(options.buffer_size);
+
+    let mut eof = false;
+    while !eof && data.len() < 4 {
+        eof = read(data)?;
+    }
+    let bom = BomKind::detect(data);
+    if bom.is_some_and(|bom| bom.unit_size() > 1) {
+        return Ok(None);
+    }
+    let writes_bom = options.writes_utf8_bom(bom.is_some());
+    if writes_bom {
+        write(BomKind::Utf8.bytes())?;
+    }
+    let mut changed = writes_bom != bom.is_some();
+    let table = match bom {
+        None => options.char_conversion.table(conversion_mode),
+        Some(_) => None,
+    };
+
+    let mut scanner = LineEndingScanner::new(bom);
+    let mut unicode = 0;
+    let mut scan = if force && verbose < 2 {
+        BinaryScan::finished()
+    } else {
+        BinaryScan::new(options.binary_policy)
+    };
+    let mut progress = Progress::new();
+    let mut start = bom.map_or(0, |bom| bom.bytes().len());
+    if options.threads > 1 && verbose < 3 {
+        data.drain(..start);
+        let mut merged = Merged {
+            scanner,
+            unicode,
+            scan,
+            progress,
+            changed,
+        };
+        convert_parallel(data, eof, read, write, options, table.as_ref(), &mut merged)?;
+        let Merged {
+            scanner,
+            unicode,
+            progress,
+            changed,
+            ..
+        } = merged;
+        return finish_stream(write, options, scanner, unicode, progress, changed);
+    }
+    loop {
+        let end = if eof {
+            data.len()
+        } else {
+            split_point(data).max(start)
+        };
+        let chunk = &data[start..end];
+
+        output.clear();
+        let first_line = scanner.line();
+        convert_scanning(
+            chunk,
+            &mut output,
+            options,
+            &mut progress,
+            &mut scan,
+            first_line,
+        )?;
+        scanner.feed(chunk.iter().map(|&byte| byte as u32));
+        unicode += count_unicode_breaks(chunk);
+        if let Some(table) = &table {
+            codepage::translate(&mut output, table);
+        }
+        changed |= output != chunk;
+        write(&output)?;
+
+        if eof {
+            break;
+        }
+        // Keep what the chunk left out for the next one
+        data.drain(..end);
+        start = 0;
+        eof = read(data)?;
+    }
+    finish_stream(write, options, scanner, unicode, progress, changed)
+}
+
+/// Ends a conversion by [`convert_stream`], writing what
+/// [`finish_conversion`] adds, and checks the whole input for `strict`.
+fn finish_stream(
+    write: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    options: &ConversionOptions,
+    scanner: LineEndingScanner,
+    unicode: usize,
+    progress: Progress<u8>,
+    mut changed: bool,
+) -> io::Result<Option<(LineEndingStats, bool)>> {
+    let mut output = Vec::new();
+    finish_conversion(&mut output, options, progress);
+    changed |= !output.is_empty();
+    write(&output)?;
+
+    let mut stats = scanner.finish();
+    stats.unicode = unicode;
+    if options.strict {
+        if let Some(line) = stats.first_mixed_line {
+            return Err(coded(
+                io::ErrorKind::Other,
+                ErrorCode::MixedLineEndings,
+                format!("{}: {}", options.progname, mixed_line_endings_message(line)),
+            ));
+        }
+    }
+    Ok(Some((stats, changed)))
+}
+
+/// What [`convert_stream`] has found and done so far, for
+/// [`convert_parallel`] to add each piece to.
+struct Merged {
+    scanner: LineEndingScanner,
+    unicode: usize,
+    scan: BinaryScan,
+    progress: Progress<u8>,
+    changed: bool,
+}
+
+/// A chunk of the input converted by one of the threads of
+/// [`convert_parallel`] on its own, as if it were a whole input.
+struct Piece {
+    input: Vec<u8>,
+    output: Vec<u8>,
+    scanner: LineEndingScanner,
+    unicode: usize,
+    progress: Progress<u8>,
+    /// Whether the input holds a control character that may make it binary,
+    /// for the binary scan to look at in order.
+    suspect: bool,
+}
+
+/// The loop of [`convert_stream`] for `options.threads` above 1: chunks
+/// are handed out to that many threads to convert, and put back in order
+/// as they come back.
+///
+/// Chunks split after an LF, or within a line, before a CR or UTF-8
+/// sequence, as [`split_point`] decides. No line break then straddles two
+/// chunks, and nothing a chunk converts to depends on the chunk before it,
+/// so each is converted from scratch and only the counts are carried over.
+/// The binary scan, which needs the NULs of all earlier chunks, is left for
+/// the chunks with control characters, done here in order.
+fn convert_parallel(
+    data: &mut Vec<u8>,
+    mut eof: bool,
+    read: &mut dyn FnMut(&mut Vec<u8>) -> io::Result<bool>,
+    write: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    options: &ConversionOptions,
+    table: Option<&[u8; 256]>,
+    merged: &mut Merged,
+) -> io::Result<()> {
+    let threads = options.threads;
+    let scans = !(options.force && options.verbose < 2);
+    let (chunk_sender, chunk_receiver) = mpsc::sync_channel::<(usize, Vec<u8>)>(threads);
+    let chunk_receiver = Mutex::new(chunk_receiver);
+    let (piece_sender, piece_receiver) = mpsc::channel();
+    thread::scope(|scope| {
+        // Dropped on an early return, letting the threads finish
+        let chunk_sender = chunk_sender;
+        for _ in 0..threads {
+            let piece_sender = piece_sender.clone();
+            let chunk_receiver = &chunk_receiver;
+            scope.spawn(move || loop {
+                let next = chunk_receiver.lock().map(|receiver| receiver.recv());
+                let Ok(Ok((index, chunk))) = next else {
+                    break;
+                };
+                let piece = convert_piece(chunk, options, table, scans);
+                if piece_sender.send((index, piece)).is_err() {
+                    break;
+                }
+            });
+        }
+        drop(piece_sender);
+
+        // Pieces that came back ahead of an earlier one
+        let mut pending = BTreeMap::new();
+        let mut sent = 0;
+        let mut written = 0;
+        loop {
+            // Keep every thread busy, with as many chunks again waiting
+            while !eof && sent - written < 2 * threads {
+                let end = split_point(data);
+                let rest = data.split_off(end);
+                let chunk = mem::replace(data, rest);
+                eof = read(data)?;
+                if !chunk.is_empty() {
+                    chunk_sender
+                        .send((sent, chunk))
+                        .expect("conversion thread panicked");
+                    sent += 1;
+                }
+            }
+            if eof && !data.is_empty() {
+                chunk_sender
+                    .send((sent, mem::take(data)))
+                    .expect("conversion thread panicked");
+                sent += 1;
+            }
+            if written == sent {
+                return Ok(());
+            }
+            while !pending.contains_key(&written) {
+                let (index, piece) = piece_receiver.recv().expect("conversion thread panicked");
+                pending.insert(index, piece);
+            }
+            let piece = pending.remove(&written).expect("piece is pending");
+            merge_piece(piece, options, write, merged)?;
+            written += 1;
+        }
+    })
+}
+
+/// Converts `chunk` for [`convert_parallel`], looking for control
+/// characters with `scans`.
+fn convert_piece(
+    chunk: Vec<u8>,
+    options: &ConversionOptions,
+    table: Option<&[u8; 256]>,
+    scans: bool,
+) -> Piece {
+    let mut output = Vec::with_capacity(chunk.len());
+    let mut progress = Progress::new();
+    convert_chunk(
+        &chunk,
+        &mut output,
+        options,
+        &mut progress,
+        &mut BinaryScan::finished(),
+    );
+    if let Some(table) = table {
+        codepage::translate(&mut output, table);
+    }
+    let mut scanner = LineEndingScanner::new(None);
+    scanner.feed(chunk.iter().map(|&byte| byte as u32));
+    let policy = &options.binary_policy;
+    let suspect = scans
+        && chunk
+            .iter()
+            .any(|&byte| byte == 0 || (byte < 32 && !policy.allows_control(byte)));
+    Piece {
+        unicode: count_unicode_breaks(&chunk),
+        input: chunk,
+        output,
+        scanner,
+        progress,
+        suspect,
+    }
+}
+
+/// Adds `piece`, the next one of the input, to what [`convert_parallel`]
+/// has `merged`, and writes its output.
+fn merge_piece(
+    piece: Piece,
+    options: &ConversionOptions,
+    write: &mut dyn FnMut(&[u8]) -> io::Result<()>,
+    merged: &mut Merged,
+) -> io::Result<()> {
+    if piece.suspect {
+        for (idx, &byte) in piece.input.iter().enumerate() {
+            if merged.scan.check(byte, idx) {
+                let line_number = merged.scanner.line()
+                    + piece.input[..idx]
+                        .iter()
+                        .filter(|&&byte| byte == b'\n')
+                        .count();
+                let ConversionOptions {
+                    force,
+                    verbose,
+                    ref reporter,
+                    ref progname,
+                    ..
+                } = *options;
+                let value = byte as u32;
+                binary_found(value, line_number, force, verbose, &**reporter, progname)?;
+            }
+        }
+    }
+    merged.scan.advance(piece.input.len());
+    merged.scanner.append(piece.scanner);
+    merged.unicode += piece.unicode;
+    let progress = &mut merged.progress;
+    progress.line_number += piece.progress.line_number - 1;
+    progress.converted += piece.progress.converted;
+    progress.prev = piece.progress.prev.or(progress.prev);
+    merged.changed |= piece.output != piece.input;
+    write(&piece.output)
+}
+
+/// Converts all of `input` to `output` for filter mode, with a thread each
+/// for reading and writing so that neither waits on the other, and memory
+/// use stays within a few chunks however long the input is.
+///
+/// Unlike a file, the input cannot be read again, so with options that
+/// [`supports`] rejects, or UTF-16 or UTF-32 input, the rest of it is read
+/// and converted in memory. Output converted before an error, such as
+/// binary content further on, has already been written.
+///
+/// Stdin and stdout need no switching to binary mode on Windows: Rust reads
+/// and writes their handles directly rather than through the C runtime,
+/// whose text mode would turn LF into CRLF.
+pub(crate) fn convert_pipe(
+    mut input: impl Read + Send + 'static,
+    mut output: impl Write + Send,
+    options: &ConversionOptions,
+) -> io::Result<ProcessedFile> {
+    // Reading ahead to keep more threads busy would hold back the output
+    // of input already given
+    let options = &ConversionOptions {
+        threads: 1,
+        ..options.clone()
+    };
+    let buffer_size = options.buffer_size.max(1);
+    let (input_sender, input_receiver) = mpsc::sync_channel(PIPE_DEPTH);
+    let (output_sender, output_receiver) = mpsc::sync_channel::<Vec<u8>>(PIPE_DEPTH);
+    // Not joined: it may be blocked reading a terminal when the conversion
+    // fails, and ends by itself once the receiver is gone
+    thread::spawn(move || {
+        let mut buffer = vec![0; buffer_size];
+        loop {
+            let piece = match input.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(read) => Ok(buffer[..read].to_vec()),
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => match signal::check() {
+                    Ok(()) => continue,
+                    Err(e) => Err(e),
+                },
+                Err(e) => Err(e),
+            };
+            let failed = piece.is_err();
+            if input_sender.send(piece).is_err() || failed {
+                break;
+            }
+        }
+    });
+    // The signal may have gone to this thread, whose wait it does not end
+    let mut read = |data: &mut Vec<u8>| loop {
+        match input_receiver.recv_timeout(SIGNAL_POLL) {
+            Ok(piece) => {
+                data.extend_from_slice(&piece?);
+                return Ok(false);
+            }
+            Err(RecvTimeoutError::Timeout) => signal::check()?,
+            Err(RecvTimeoutError::Disconnected) => return Ok(true),
+        }
+    };
+
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || {
+            for piece in output_receiver {
+                output.write_all(&piece)?;
+                output.flush()?;
+            }
+            Ok(())
+        });
+        let mut write = |piece: &[u8]| {
+            output_sender
+                .send(piece.to_vec())
+                .map_err(|_| io::Error::from(io::ErrorKind::BrokenPipe))
+        };
+        let mut convert = || {
+            let mut data = Vec::new();
+            if supports(options) {
+                if let Some((stats, changed)) =
+                    convert_stream(&mut data, &mut read, &mut write, options)?
+                {
+                    return Ok(ProcessedFile { stats, changed });
+                }
+            }
+            while !read(&mut data)? {}
+            let converted = convert_with_options(&data, options)?;
+            write(&converted)?;
+            Ok(ProcessedFile {
+                stats: detect_line_endings(&data),
+                changed: converted != data,
+            })
+        };
+        let converted = convert();
+        drop(output_sender);
+        // An error writing explains a failure to hand over the output
+        writer
+            .join()
+            .expect("writer thread panicked")
+            .and(converted)
+    })
+}
+
+/// Reads up to a chunk of `input` through `buffer` and appends it to `data`.
+/// Returns whether the end of the input was reached, and fails once the run
+/// is interrupted.
+fn fill(mut input: &File, buffer: &mut [u8], data: &mut Vec<u8>) -> io::Result<bool> {
+    let mut filled = 0;
+    while filled < buffer.len() {
+        signal::check()?;
+        match input.read(&mut buffer[filled..]) {
+            Ok(0) => break,
+            Ok(read) => filled += read,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e),
+        }
+    }
+    data.extend_from_slice(&buffer[..filled]);
+    Ok(filled < buffer.len())
+}
+
+/// Where to end a chunk of `data` so that no line break is split: after its
+/// last LF, or, within a line longer than a chunk, before a trailing CR or
+/// UTF-8 sequence, which may turn out to be a CRLF or a Unicode line break.
+fn split_point(data: &[u8]) -> usize {
+    match data.iter().rposition(|&byte| byte == b'\n') {
+        Some(idx) => idx + 1,
+        None => tail_start(data),
+    }
+}
+
+/// Where the tail of `data` that may start a line break begins: a trailing
+/// CR or UTF-8 sequence, which what follows may make a CRLF or a Unicode
+/// line break. The length of `data` if there is none.
+pub(crate) fn tail_start(data: &[u8]) -> usize {
+    let continuation = data
+        .iter()
+        .rev()
+        .take(3)
+        .take_while(|&&byte| (0x80..0xC0).contains(&byte))
+        .count();
+    let end = data.len() - continuation;
+    match end.checked_sub(1).map(|idx| data[idx]) {
+        Some(byte) if byte >= 0xC0 => end - 1,
+        Some(b'\r') if continuation == 0 => end - 1,
+        _ => end,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{process_file_with_options, ConversionMode};
+    use std::fs;
+
+    /// Inputs with CRLFs, lone CRs and multi-byte UTF-8, including Unicode
+    /// line breaks, for chunk boundaries to fall anywhere in.
+    const SAMPLES: &[&str] = &[
+        "one\r\ntwo\r\n\r\nthree\r\n",
+        "lone\rcr\r\rand\r\n\n\r",
+        "caf\u{e9}\r\n\u{2028}na\u{ef}ve\u{85}\r\n\u{20ac}\r\r\n\u{2029}",
+        "no line break at the end\r",
+    ];
+
+    /// Options for each mode, plain and with the options that rewrite more.
+    fn variants() -> Vec<ConversionOptions> {
+        let mut variants = Vec::new();
+        for mode in [
+            ConversionMode::ToUnix,
+            ConversionMode::ToDos,
+            ConversionMode::ToMac,
+        ] {
+            let plain = ConversionOptions::new(mode);
+            variants.push(ConversionOptions {
+                normalize: true,
+                add_eol: true,
+                ..plain.clone()
+            });
+            variants.push(ConversionOptions {
+                strip_cr: true,
+                unicode_breaks: true,
+                ..plain.clone()
+            });
+            variants.push(plain);
+        }
+        variants
+    }
+
+    /// Converts `input` with [`convert_stream`], reading `first` bytes of it
+    /// and then `options.buffer_size` bytes at a time, as [`fill`] does.
+    fn streamed(input: &[u8], first: usize, options: &ConversionOptions) -> Vec<u8> {
+        let mut rest = input;
+        let mut size = first;
+        let mut read = |data: &mut Vec<u8>| {
+            let len = size.min(rest.len());
+            data.extend_from_slice(&rest[..len]);
+            rest = &rest[len..];
+            size = options.buffer_size;
+            Ok(rest.is_empty())
+        };
+        let mut output = Vec::new();
+        let mut write = |piece: &[u8]| {
+            output.extend_from_slice(piece);
+            Ok(())
+        };
+        convert_stream(&mut Vec::new(), &mut read, &mut write, options)
+            .unwrap()
+            .expect("UTF-8 input is streamed");
+        output
+    }
+
+    /// Checks that streaming each sample with `threads` gives what the
+    /// conversion in memory does, for chunk sizes of 1 to 8 bytes and a
+    /// first chunk ending at every offset.
+    fn assert_streams_like_memory(threads: usize) {
+        for options in variants() {
+            for sample in SAMPLES {
+                let input = sample.as_bytes();
+                let expected = convert_with_options(input, &options).unwrap();
+                for buffer_size in 1..=8 {
+                    let options = ConversionOptions {
+                        buffer_size,
+                        threads,
+                        ..options.clone()
+                    };
+                    for first in 0..=input.len() {
+                        assert_eq!(
+                            streamed(input, first, &options),
+                            expected,
+                            "{:?} with {:?}, chunks of {} after {}",
+                            sample,
+                            options.conversion_mode,
+                            buffer_size,
+                            first
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn chunk_boundaries_do_not_change_the_output() {
+        assert_streams_like_memory(1);
+    }
+
+    #[test]
+    fn chunks_converted_in_parallel_are_merged_in_order() {
+        assert_streams_like_memory(2);
+        assert_streams_like_memory(4);
+    }
+
+    #[test]
+    fn parallel_pieces_count_line_breaks_across_boundaries() {
+        let input = b"a\r\nb\rc\r\n\r\nd\n\r";
+        let expected = detect_line_endings(input);
+        let options = ConversionOptions {
+            buffer_size: 1,
+            threads: 3,
+            ..ConversionOptions::new(ConversionMode::ToUnix)
+        };
+        let mut rest = &input[..];
+        let mut read = |data: &mut Vec<u8>| {
+            data.extend_from_slice(&rest[..1]);
+            rest = &rest[1..];
+            Ok(rest.is_empty())
+        };
+        let (stats, changed) =
+            convert_stream(&mut Vec::new(), &mut read, &mut |_| Ok(()), &options)
+                .unwrap()
+                .unwrap();
+        assert!(changed);
+        assert_eq!(
+            (stats.crlf, stats.lf, stats.cr),
+            (expected.crlf, expected.lf, expected.cr)
+        );
+        assert_eq!(stats.first_mixed_line, expected.first_mixed_line);
+    }
+
+    #[test]
+    fn split_point_holds_back_a_trailing_cr() {
+        assert_eq!(split_point(b"one\r\ntwo\r"), 5);
+        assert_eq!(split_point(b"a long line\r"), 11);
+        assert_eq!(split_point(b"caf\xc3"), 3);
+        assert_eq!(split_point(b"a long line"), 11);
+    }
+
+    #[test]
+    fn files_above_the_threshold_are_streamed() {
+        let options = ConversionOptions::new(ConversionMode::ToUnix);
+        assert!(!streams(&options, STREAM_THRESHOLD));
+        assert!(streams(&options, STREAM_THRESHOLD + 1));
+        let asked = ConversionOptions {
+            stream: true,
+            ..options.clone()
+        };
+        assert!(streams(&asked, 0));
+        // Rewriting the file through its own descriptor cannot stream
+        let in_place = ConversionOptions {
+            in_place: true,
+            ..asked
+        };
+        assert!(!streams(&in_place, STREAM_THRESHOLD + 1));
+    }
+
+    #[test]
+    fn a_file_above_the_threshold_converts_like_a_small_one() {
+        let dir = std::env::temp_dir().join(format!("dos2unix-stream-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let (input_path, output_path) = (dir.join("large.txt"), dir.join("large.out"));
+        // A CRLF split by the end of each chunk read
+        let mut line = vec![b'x'; DEFAULT_BUFFER_SIZE - 1];
+        line.extend_from_slice(b"\r\n");
+        let mut content = line.repeat(STREAM_THRESHOLD as usize / line.len() + 1);
+        content.extend_from_slice("caf\u{e9}\r".as_bytes());
+        fs::write(&input_path, &content).unwrap();
+
+        let options = ConversionOptions::new(ConversionMode::ToUnix);
+        let result = process_file_with_options(&input_path, Some(&output_path), &options);
+        let output = fs::read(&output_path);
+        fs::remove_dir_all(&dir).unwrap();
+        assert!(result.unwrap().changed);
+        assert_eq!(
+            output.unwrap(),
+            convert_with_options(&content, &options).unwrap()
+        );
+    }
+}
@@ -0,0 +1,86 @@
+use std::fmt;
+
+use crate::nls::{tr, trn};
+
+/// What happened to a single file during a batch run.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum FileOutcome {
+    /// At least one line break was rewritten.
+    Converted,
+    /// The file already had the target line endings.
+    AlreadyClean,
+    /// The file was detected as binary and left alone.
+    SkippedBinary,
+    /// Another program had the file locked, and it was left alone.
+    SkippedLocked,
+    /// The file could not be read or written.
+    Error,
+}
+
+/// Totals for a batch of files, built by [`record`](Summary::record)ing the
+/// outcome of each one.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+    pub converted: usize,
+    pub already_clean: usize,
+    pub skipped_binary: usize,
+    pub skipped_locked: usize,
+    pub errors: usize,
+}
+
+impl Summary {
+    pub fn record(&mut self, outcome: FileOutcome) {
+        match outcome {
+            FileOutcome::Converted => self.converted += 1,
+            FileOutcome::AlreadyClean => self.already_clean += 1,
+            FileOutcome::SkippedBinary => self.skipped_binary += 1,
+            FileOutcome::SkippedLocked => self.skipped_locked += 1,
+            FileOutcome::Error => self.errors += 1,
+        }
+    }
+
+    /// Number of files recorded.
+    pub fn total(&self) -> usize {
+        self.converted
+            + self.already_clean
+            + self.skipped_binary
+            + self.skipped_locked
+            + self.errors
+    }
+}
+
+impl fmt::Display for Summary {
+    /// Formats as e.g. "42 converted, 10 already clean, 3 skipped binaries, 1 error",
+    /// with the files skipped as locked, if any, before the errors.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, {}, {}, ",
+            tr!("{} converted", self.converted),
+            tr!("{} already clean", self.already_clean),
+            trn!(
+                "{} skipped binary",
+                "{} skipped binaries",
+                self.skipped_binary,
+                self.skipped_binary
+            ),
+        )?;
+        if self.skipped_locked > 0 {
+            write!(
+                f,
+                "{}, ",
+                trn!(
+                    "{} locked file skipped",
+                    "{} locked files skipped",
+                    self.skipped_locked,
+                    self.skipped_locked
+                )
+            )?;
+        }
+        write!(
+            f,
+            "{}",
+            trn!("{} error", "{} errors", self.errors, self.errors)
+        )
+    }
+}
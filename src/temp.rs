@@ -0,0 +1,162 @@
+//! The temporary file a conversion is written to before it replaces the
+//! output file.
+
+use std::collections::hash_map::RandomState;
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::hash::{BuildHasher, Hasher};
+use std::io;
+use std::path::{Path, PathBuf};
+
+use crate::error::{coded, ErrorCode};
+use crate::nls::tr;
+
+/// How many names to try before giving up on finding an unused one.
+const ATTEMPTS: usize = 100;
+
+/// A new file next to the output file, removed again when dropped unless
+/// [`TempFile::persist`] moved it into place. Persisting can be tried again
+/// after it fails.
+///
+/// Keeping it in the output's directory makes the final rename atomic, and
+/// creating it exclusively under a random name means no existing file is
+/// ever overwritten.
+pub(crate) struct TempFile {
+    path: PathBuf,
+    file: Option<File>,
+}
+
+impl TempFile {
+    /// Creates a file named `<name>.dos2unix.XXXXXX` beside `target`.
+    pub(crate) fn create(target: &Path) -> io::Result<TempFile> {
+        let file_name = target.file_name().unwrap_or(target.as_os_str());
+        for _ in 0..ATTEMPTS {
+            let mut name = OsString::from(file_name);
+            name.push(".dos2unix.");
+            name.push(random_suffix());
+            let path = target.with_file_name(name);
+            match File::options().write(true).create_new(true).open(&path) {
+                Ok(file) => {
+                    return Ok(TempFile {
+                        path,
+                        file: Some(file),
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                // Such as a directory that cannot be written to, which the
+                // error of the operating system does not name
+                Err(e) => {
+                    let message = tr!(
+                        "cannot create a temporary file in '{}': {}",
+                        parent_dir(target).display(),
+                        e
+                    );
+                    return Err(coded(e.kind(), ErrorCode::of(&e), message));
+                }
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            tr!(
+                "no unused temporary file name next to '{}'",
+                target.display()
+            ),
+        ))
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub(crate) fn file(&mut self) -> &mut File {
+        self.file
+            .as_mut()
+            .expect("temporary file is open until persisted")
+    }
+
+    /// Closes the file and renames it to `target`, replacing that file.
+    ///
+    /// The temporary file normally lives beside `target`, but a rename can
+    /// still fail with `EXDEV` when the two are on different file systems,
+    /// as with some bind and overlay mounts. The content is then copied
+    /// over `target` and flushed to disk instead, which is not atomic but
+    /// still leaves the temporary file to be cleaned up.
+    pub(crate) fn persist(&mut self, target: &Path) -> io::Result<()> {
+        // Windows cannot rename a file that is still open.
+        drop(self.file.take());
+        match fs::rename(&self.path, target) {
+            Ok(()) => {
+                self.path = PathBuf::new();
+                Ok(())
+            }
+            Err(e) if e.kind() == io::ErrorKind::CrossesDevices => {
+                fs::copy(&self.path, target)?;
+                File::open(target)?.sync_all()
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Closes the file and moves it to `target` unless a file is there,
+    /// which is checked in the same step as the move: the file is linked
+    /// to `target`, which fails if it exists, and then removed. Where hard
+    /// links cannot be made, the content is copied to a newly created
+    /// `target` instead.
+    pub(crate) fn persist_new(&mut self, target: &Path) -> io::Result<()> {
+        drop(self.file.take());
+        match fs::hard_link(&self.path, target) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => Err(e),
+            Err(_) => {
+                let mut output = File::options().write(true).create_new(true).open(target)?;
+                io::copy(&mut File::open(&self.path)?, &mut output)?;
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        if !self.path.as_os_str().is_empty() {
+            drop(self.file.take());
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Flushes the directory entry of `path` to disk, so that a rename to it
+/// survives a crash. Only Unix needs, and allows, syncing a directory.
+pub(crate) fn sync_parent(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        File::open(parent_dir(path))?.sync_all()
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = path;
+        Ok(())
+    }
+}
+
+/// The directory `path` is in, which is `.` for a bare file name.
+fn parent_dir(path: &Path) -> &Path {
+    match path.parent() {
+        Some(parent) if !parent.as_os_str().is_empty() => parent,
+        _ => Path::new("."),
+    }
+}
+
+/// Six random alphanumeric characters. `RandomState` is seeded from the
+/// operating system's random source, so no extra dependency is needed.
+fn random_suffix() -> String {
+    const CHARS: &[u8] = b"abcdefghijklmnopqrstuvwxyzABCDEFGHIJKLMNOPQRSTUVWXYZ0123456789";
+    let mut bits = RandomState::new().build_hasher().finish();
+    (0..6)
+        .map(|_| {
+            let c = CHARS[(bits % CHARS.len() as u64) as usize];
+            bits /= CHARS.len() as u64;
+            c as char
+        })
+        .collect()
+}
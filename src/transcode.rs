@@ -0,0 +1,171 @@
+//! Character set conversion for `--from-encoding` and `--to-encoding`.
+//!
+//! The Unicode encodings a BOM can announce are handled natively. Any other
+//! encoding known to `encoding_rs`, such as Shift_JIS or Windows-1251, is
+//! available with the `encoding` feature.
+
+use std::io;
+
+use crate::error::{coded, ErrorCode};
+use crate::nls::tr;
+use crate::units::{decode_units, CodeUnit};
+use crate::BomKind;
+
+/// Whether `label` names an encoding this build can convert from and to.
+pub(crate) fn is_supported(label: &str) -> bool {
+    BomKind::from_name(label).is_some() || external::lookup(label).is_some()
+}
+
+/// Decodes `content` from the encoding named `label`, dropping a leading BOM
+/// of that encoding.
+pub(crate) fn decode(content: &[u8], label: &str, progname: &str) -> io::Result<String> {
+    fn decode_wide<T: CodeUnit>(body: &[u8], little_endian: bool) -> Option<String> {
+        let (units, rest) = decode_units::<T>(body, little_endian);
+        T::decode(&units).filter(|_| rest.is_empty())
+    }
+
+    let text = match BomKind::from_name(label) {
+        Some(encoding) => {
+            let body = content.strip_prefix(encoding.bytes()).unwrap_or(content);
+            match encoding.unit_size() {
+                1 => String::from_utf8(body.to_vec()).ok(),
+                2 => decode_wide::<u16>(body, encoding.is_little_endian()),
+                _ => decode_wide::<u32>(body, encoding.is_little_endian()),
+            }
+        }
+        None => external::decode(content, label, progname)?,
+    };
+    text.ok_or_else(|| {
+        coded(
+            io::ErrorKind::Other,
+            ErrorCode::InvalidEncoding,
+            tr!("{}: {} input is not valid", progname, label),
+        )
+    })
+}
+
+/// Appends `text` encoded in the encoding named `label` to `output`. A BOM
+/// is written first if `with_bom` is set and the encoding has one.
+pub(crate) fn encode(
+    text: &str,
+    label: &str,
+    with_bom: bool,
+    output: &mut Vec<u8>,
+    progname: &str,
+) -> io::Result<()> {
+    match BomKind::from_name(label) {
+        Some(encoding) => {
+            if with_bom {
+                output.extend_from_slice(encoding.bytes());
+            }
+            encoding.encode(text, output);
+            Ok(())
+        }
+        None => external::encode(text, label, output, progname),
+    }
+}
+
+#[cfg(feature = "encoding")]
+mod external {
+    use std::io;
+
+    use crate::error::{coded, ErrorCode};
+    use crate::nls::tr;
+
+    use encoding_rs::Encoding;
+
+    pub(super) fn lookup(label: &str) -> Option<&'static Encoding> {
+        Encoding::for_label(label.as_bytes())
+    }
+
+    fn unknown(label: &str, progname: &str) -> io::Error {
+        coded(
+            io::ErrorKind::Other,
+            ErrorCode::UnknownEncoding,
+            tr!("{}: unknown encoding '{}'", progname, label),
+        )
+    }
+
+    pub(super) fn decode(
+        content: &[u8],
+        label: &str,
+        progname: &str,
+    ) -> io::Result<Option<String>> {
+        let encoding = lookup(label).ok_or_else(|| unknown(label, progname))?;
+        let (text, had_errors) = encoding.decode_with_bom_removal(content);
+        Ok(Some(text.into_owned()).filter(|_| !had_errors))
+    }
+
+    pub(super) fn encode(
+        text: &str,
+        label: &str,
+        output: &mut Vec<u8>,
+        progname: &str,
+    ) -> io::Result<()> {
+        let encoding = lookup(label).ok_or_else(|| unknown(label, progname))?;
+        // encoding_rs writes UTF-8 when asked for an encoding it can only
+        // decode, such as UTF-16 without a byte order.
+        if encoding.output_encoding() != encoding {
+            return Err(coded(
+                io::ErrorKind::Other,
+                ErrorCode::UnsupportedEncoding,
+                tr!("{}: cannot write {} output", progname, encoding.name()),
+            ));
+        }
+        let (bytes, _, had_unmappable) = encoding.encode(text);
+        if had_unmappable {
+            return Err(coded(
+                io::ErrorKind::Other,
+                ErrorCode::Unmappable,
+                tr!(
+                    "{}: text cannot be represented in {}",
+                    progname,
+                    encoding.name()
+                ),
+            ));
+        }
+        output.extend_from_slice(&bytes);
+        Ok(())
+    }
+}
+
+#[cfg(not(feature = "encoding"))]
+mod external {
+    use std::io;
+
+    use crate::error::{coded, ErrorCode};
+    use crate::nls::tr;
+
+    pub(super) fn lookup(_label: &str) -> Option<()> {
+        None
+    }
+
+    fn unsupported(label: &str, progname: &str) -> io::Error {
+        coded(
+            io::ErrorKind::Unsupported,
+            ErrorCode::UnsupportedEncoding,
+            tr!(
+                "{}: encoding '{}' is not supported by this build (enable the `encoding` feature)",
+                progname,
+                label
+            ),
+        )
+    }
+
+    pub(super) fn decode(
+        _content: &[u8],
+        label: &str,
+        progname: &str,
+    ) -> io::Result<Option<String>> {
+        Err(unsupported(label, progname))
+    }
+
+    pub(super) fn encode(
+        _text: &str,
+        label: &str,
+        _output: &mut Vec<u8>,
+        progname: &str,
+    ) -> io::Result<()> {
+        Err(unsupported(label, progname))
+    }
+}
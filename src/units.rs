@@ -0,0 +1,154 @@
+//! Code units of the encodings a BOM can announce, so the conversion loop can
+//! work on UTF-16 and UTF-32 text without splitting multi-byte units.
+
+/// A code unit of UTF-8 (`u8`), UTF-16 (`u16`) or UTF-32 (`u32`) text.
+pub(crate) trait CodeUnit: Copy + Eq {
+    fn from_ascii(byte: u8) -> Self;
+    fn value(self) -> u32;
+    fn from_bytes(bytes: &[u8], little_endian: bool) -> Self;
+    fn write_bytes(self, little_endian: bool, output: &mut Vec<u8>);
+    /// Decodes a sequence of units into a string, or `None` if it is not
+    /// valid in its encoding.
+    fn decode(units: &[Self]) -> Option<String>;
+    /// Whether `units` are well-formed, except possibly for an incomplete
+    /// sequence at the end, as left by cutting a sample out of a file.
+    fn is_valid_prefix(units: &[Self]) -> bool;
+    /// Length in units of the NEL, LINE SEPARATOR or PARAGRAPH SEPARATOR
+    /// character at the start of `units`, or 0 if there is none.
+    fn unicode_break_len(units: &[Self]) -> usize {
+        match units.first().map(|unit| unit.value()) {
+            Some(0x85 | 0x2028 | 0x2029) => 1,
+            _ => 0,
+        }
+    }
+}
+
+impl CodeUnit for u8 {
+    fn from_ascii(byte: u8) -> Self {
+        byte
+    }
+
+    fn value(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bytes(bytes: &[u8], _little_endian: bool) -> Self {
+        bytes[0]
+    }
+
+    fn write_bytes(self, _little_endian: bool, output: &mut Vec<u8>) {
+        output.push(self);
+    }
+
+    fn decode(units: &[Self]) -> Option<String> {
+        String::from_utf8(units.to_vec()).ok()
+    }
+
+    fn is_valid_prefix(units: &[Self]) -> bool {
+        match std::str::from_utf8(units) {
+            Ok(_) => true,
+            Err(e) => e.error_len().is_none(),
+        }
+    }
+
+    fn unicode_break_len(units: &[Self]) -> usize {
+        match units {
+            [0xC2, 0x85, ..] => 2,
+            [0xE2, 0x80, 0xA8 | 0xA9, ..] => 3,
+            _ => 0,
+        }
+    }
+}
+
+impl CodeUnit for u16 {
+    fn from_ascii(byte: u8) -> Self {
+        byte as u16
+    }
+
+    fn value(self) -> u32 {
+        self as u32
+    }
+
+    fn from_bytes(bytes: &[u8], little_endian: bool) -> Self {
+        let bytes = [bytes[0], bytes[1]];
+        if little_endian {
+            u16::from_le_bytes(bytes)
+        } else {
+            u16::from_be_bytes(bytes)
+        }
+    }
+
+    fn write_bytes(self, little_endian: bool, output: &mut Vec<u8>) {
+        if little_endian {
+            output.extend_from_slice(&self.to_le_bytes());
+        } else {
+            output.extend_from_slice(&self.to_be_bytes());
+        }
+    }
+
+    fn decode(units: &[Self]) -> Option<String> {
+        String::from_utf16(units).ok()
+    }
+
+    fn is_valid_prefix(units: &[Self]) -> bool {
+        let units = match units.last() {
+            Some(0xD800..=0xDBFF) => &units[..units.len() - 1],
+            _ => units,
+        };
+        char::decode_utf16(units.iter().copied()).all(|c| c.is_ok())
+    }
+}
+
+impl CodeUnit for u32 {
+    fn from_ascii(byte: u8) -> Self {
+        byte as u32
+    }
+
+    fn value(self) -> u32 {
+        self
+    }
+
+    fn from_bytes(bytes: &[u8], little_endian: bool) -> Self {
+        let bytes = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if little_endian {
+            u32::from_le_bytes(bytes)
+        } else {
+            u32::from_be_bytes(bytes)
+        }
+    }
+
+    fn write_bytes(self, little_endian: bool, output: &mut Vec<u8>) {
+        if little_endian {
+            output.extend_from_slice(&self.to_le_bytes());
+        } else {
+            output.extend_from_slice(&self.to_be_bytes());
+        }
+    }
+
+    fn decode(units: &[Self]) -> Option<String> {
+        units.iter().map(|&unit| char::from_u32(unit)).collect()
+    }
+
+    fn is_valid_prefix(units: &[Self]) -> bool {
+        units.iter().all(|&unit| char::from_u32(unit).is_some())
+    }
+}
+
+/// Decodes code units from `bytes`. Trailing bytes that do not make up a
+/// whole unit are returned separately so they can be passed through
+/// unchanged.
+pub(crate) fn decode_units<T: CodeUnit>(bytes: &[u8], little_endian: bool) -> (Vec<T>, &[u8]) {
+    let chunks = bytes.chunks_exact(std::mem::size_of::<T>());
+    let rest = chunks.remainder();
+    let units = chunks
+        .map(|unit| T::from_bytes(unit, little_endian))
+        .collect();
+    (units, rest)
+}
+
+pub(crate) fn encode_units<T: CodeUnit>(units: &[T], little_endian: bool, output: &mut Vec<u8>) {
+    output.reserve(std::mem::size_of_val(units));
+    for &unit in units {
+        unit.write_bytes(little_endian, output);
+    }
+}
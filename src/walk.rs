@@ -0,0 +1,139 @@
+//! Directory traversal for `--recursive` and `--ext`, built on the `ignore`
+//! crate that ripgrep uses.
+
+use std::io;
+use std::path::{Path, PathBuf};
+
+use ignore::WalkBuilder;
+
+use crate::glob::matches_path;
+
+/// The `--include`, `--exclude` and `--ext` filters. Patterns are matched
+/// against paths relative to the directory being searched as described at
+/// [`matches_path`].
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PathFilter {
+    /// If not empty, only files matching one of these are visited.
+    /// Directories are always searched.
+    pub(crate) include: Vec<String>,
+    /// Files and whole directories matching one of these are skipped.
+    pub(crate) exclude: Vec<String>,
+    /// If not empty, only files with one of these extensions are visited,
+    /// compared case-insensitively on Windows only.
+    pub(crate) extensions: Vec<String>,
+    /// Skip what `.gitignore` and `.ignore` files, `.git/info/exclude` and
+    /// the global git excludes file ignore, as well as `.git` directories.
+    /// As with git, `.gitignore` files only count inside a repository.
+    pub(crate) ignore_files: bool,
+    /// Also visit hidden files and directories, whose names start with a
+    /// `.`. On Windows, files with the hidden attribute count too.
+    pub(crate) hidden: bool,
+    /// Also visit symbolic links to files, which are otherwise skipped as
+    /// converting through one would replace the link with a regular file.
+    pub(crate) symlinks: bool,
+}
+
+impl PathFilter {
+    fn admits(&self, relative: &Path, is_dir: bool) -> bool {
+        let matches = |pattern: &String| matches_path(pattern, relative, is_dir);
+        !self.exclude.iter().any(matches)
+            && (is_dir
+                || ((self.include.is_empty() || self.include.iter().any(matches))
+                    && (self.extensions.is_empty() || self.has_extension(relative))))
+    }
+
+    fn has_extension(&self, path: &Path) -> bool {
+        let Some(extension) = path.extension().and_then(|extension| extension.to_str()) else {
+            return false;
+        };
+        self.extensions.iter().any(|listed| {
+            if cfg!(windows) {
+                listed.eq_ignore_ascii_case(extension)
+            } else {
+                listed == extension
+            }
+        })
+    }
+}
+
+/// Calls `visit` with every regular file that `filter` admits in the
+/// directory `root` and its subdirectories, in sorted order. With a
+/// `max_depth`, files further down are left out: a depth of 1 means just
+/// the files directly in `root`. `on_error` is called with each path that cannot be read, which
+/// does not stop the traversal.
+///
+/// Symbolic links to directories are followed, except for one leading back
+/// to a directory it is in, which would loop forever. Symbolic links to
+/// files are only visited if `filter` asks for them.
+pub(crate) fn walk_files(
+    root: &Path,
+    filter: &PathFilter,
+    max_depth: Option<usize>,
+    visit: &mut dyn FnMut(&Path),
+    on_error: &mut dyn FnMut(&Path, io::Error),
+) {
+    let ignore_files = filter.ignore_files;
+    let entry_filter = filter.clone();
+    let entry_root = root.to_path_buf();
+    let walker = WalkBuilder::new(root)
+        .standard_filters(false)
+        .hidden(!filter.hidden)
+        .ignore(ignore_files)
+        .git_ignore(ignore_files)
+        .git_global(ignore_files)
+        .git_exclude(ignore_files)
+        .parents(ignore_files)
+        .follow_links(true)
+        .max_depth(max_depth)
+        .sort_by_file_path(|a, b| a.cmp(b))
+        .filter_entry(move |entry| {
+            let is_dir = entry
+                .file_type()
+                .is_some_and(|file_type| file_type.is_dir());
+            if entry.depth() == 0 {
+                return true;
+            }
+            if ignore_files && is_dir && entry.file_name() == ".git" {
+                return false;
+            }
+            let relative = entry
+                .path()
+                .strip_prefix(&entry_root)
+                .unwrap_or(entry.path());
+            entry_filter.admits(relative, is_dir)
+        })
+        .build();
+    for entry in walker {
+        match entry {
+            Ok(entry) => {
+                let is_file = entry
+                    .file_type()
+                    .is_some_and(|file_type| file_type.is_file());
+                if is_file && (filter.symlinks || !entry.path_is_symlink()) {
+                    visit(entry.path());
+                }
+            }
+            Err(e) => {
+                if let Some((path, e)) = io_error(e) {
+                    on_error(path.as_deref().unwrap_or(root), e);
+                }
+            }
+        }
+    }
+}
+
+/// The path and I/O error a traversal error is about. Other errors, such as
+/// a malformed pattern in an ignore file or a symbolic link loop, are not
+/// worth failing the run for, and give `None`.
+fn io_error(error: ignore::Error) -> Option<(Option<PathBuf>, io::Error)> {
+    match error {
+        ignore::Error::WithPath { path, err } => {
+            io_error(*err).map(|(inner, e)| (inner.or(Some(path)), e))
+        }
+        ignore::Error::WithDepth { err, .. } | ignore::Error::WithLineNumber { err, .. } => {
+            io_error(*err)
+        }
+        ignore::Error::Io(e) => Some((None, e)),
+        _ => None,
+    }
+}
@@ -0,0 +1,457 @@
+//! A [`Write`] adapter that converts line endings as they are written.
+
+use std::io::{self, Write};
+use std::mem;
+
+use crate::binary::BinaryScan;
+use crate::bom::BomKind;
+use crate::codepage;
+use crate::detect::LineEndingScanner;
+use crate::error::{coded, ErrorCode};
+use crate::stream::{supports, tail_start};
+use crate::{
+    convert_scanning, convert_with_options, finish_conversion, ConversionMode, ConversionOptions,
+    Progress,
+};
+
+/// A [`Write`] adapter that converts what is written to it on its way to
+/// `inner`, as [`convert_with_options`] converts the whole of it.
+///
+/// Bytes may be written in chunks of any size; a CRLF pair split across two
+/// `write` calls is still converted as one line break. Because a trailing CR
+/// can only be resolved once the next byte is known, it is held back until
+/// more data arrives or the writer is finished, as is the first few bytes,
+/// which may be a BOM, and a trailing UTF-8 sequence. Input in UTF-16 or
+/// UTF-32, and options that need the whole input, such as `auto` or
+/// `strict`, have everything held back until the writer is finished. Call
+/// [`finish`](Self::finish) to convert what is held back and get `inner`
+/// back. Dropping the writer finishes it on a best-effort basis, ignoring
+/// errors.
+///
+/// Binary input fails the write it is found in, and every call after it,
+/// unless `force` is set.
+///
+/// As with [`io::BufWriter`], bytes that `write` accepts are converted even
+/// if `inner` fails to take their output. That output is kept and written
+/// first by the next call, which reports the error if it persists, so that
+/// nothing is written twice when a failed write is tried again.
+pub struct ConvertingWriter<W: Write> {
+    inner: Option<W>,
+    options: ConversionOptions,
+    state: State,
+    /// Input not converted yet.
+    held: Vec<u8>,
+    /// Converted output not written to `inner` yet.
+    pending: Vec<u8>,
+    finished: bool,
+    /// The error that stopped the conversion, given again by every call.
+    failure: Option<io::Error>,
+}
+
+/// How far a [`ConvertingWriter`] has got with its input.
+enum State {
+    /// Too little has been written to tell whether it starts with a BOM.
+    Start,
+    /// Converting a piece at a time, as a large file is.
+    Pieces(Box<Pieces>),
+    /// Holding all of the input, to convert it when finished.
+    Whole,
+}
+
+/// What a [`ConvertingWriter`] converting a piece at a time carries from one
+/// piece to the next.
+struct Pieces {
+    progress: Progress<u8>,
+    scan: BinaryScan,
+    /// Counts the lines, for binary input to be reported at its line.
+    scanner: LineEndingScanner,
+    table: Option<[u8; 256]>,
+}
+
+impl<W: Write> ConvertingWriter<W> {
+    pub fn new(inner: W, conversion_mode: ConversionMode) -> Self {
+        Self::with_add_eol(inner, conversion_mode, false)
+    }
+
+    /// Like [`new`](Self::new), but terminates an unterminated last line when
+    /// the writer is finished.
+    pub fn with_add_eol(inner: W, conversion_mode: ConversionMode, add_eol: bool) -> Self {
+        let options = ConversionOptions {
+            add_eol,
+            ..ConversionOptions::new(conversion_mode)
+        };
+        Self::with_options(inner, options)
+    }
+
+    /// Like [`new`](Self::new), but converts with every setting of
+    /// `options` that applies to content.
+    pub fn with_options(inner: W, options: ConversionOptions) -> Self {
+        ConvertingWriter {
+            inner: Some(inner),
+            options,
+            state: State::Start,
+            held: Vec::new(),
+            pending: Vec::new(),
+            finished: false,
+            failure: None,
+        }
+    }
+
+    pub fn get_ref(&self) -> &W {
+        self.inner.as_ref().unwrap()
+    }
+
+    pub fn get_mut(&mut self) -> &mut W {
+        self.inner.as_mut().unwrap()
+    }
+
+    /// Number of line breaks rewritten so far. Input held whole until the
+    /// writer is finished is not counted.
+    pub fn converted(&self) -> usize {
+        match &self.state {
+            State::Pieces(pieces) => pieces.progress.converted,
+            State::Start | State::Whole => 0,
+        }
+    }
+
+    /// Writes any held-back bytes, flushes, and returns the inner writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_tail()?;
+        let mut inner = self.inner.take().unwrap();
+        inner.flush()?;
+        Ok(inner)
+    }
+
+    fn write_tail(&mut self) -> io::Result<()> {
+        if !self.finished {
+            self.finished = true;
+            self.convert(&[], true)?;
+        }
+        self.write_pending()
+    }
+
+    /// Converts `input`, and with `end` all that is held back, adding the
+    /// output to what is pending.
+    fn convert(&mut self, input: &[u8], end: bool) -> io::Result<()> {
+        if let Some(e) = &self.failure {
+            return Err(coded(e.kind(), ErrorCode::of(e), e.to_string()));
+        }
+        let written = self.pending.len();
+        let result = self.convert_held(input, end);
+        if let Err(e) = &result {
+            // Nothing of the piece that failed goes out
+            self.pending.truncate(written);
+            self.failure = Some(coded(e.kind(), ErrorCode::of(e), e.to_string()));
+        }
+        result
+    }
+
+    fn convert_held(&mut self, input: &[u8], end: bool) -> io::Result<()> {
+        let options = &self.options;
+        self.held.extend_from_slice(input);
+        if let State::Start = self.state {
+            if self.held.len() < 4 && !end {
+                return Ok(());
+            }
+            self.state = start(&mut self.held, &mut self.pending, options);
+        }
+        match &mut self.state {
+            State::Start => unreachable!("the start of the input is known"),
+            State::Pieces(pieces) => {
+                let Pieces {
+                    progress,
+                    scan,
+                    scanner,
+                    table,
+                } = &mut **pieces;
+                let piece_end = if end {
+                    self.held.len()
+                } else {
+                    tail_start(&self.held)
+                };
+                let piece = &self.held[..piece_end];
+                let converted = self.pending.len();
+                let first_line = scanner.line();
+                convert_scanning(
+                    piece,
+                    &mut self.pending,
+                    options,
+                    progress,
+                    scan,
+                    first_line,
+                )?;
+                scanner.feed(piece.iter().map(|&byte| byte as u32));
+                if let Some(table) = table {
+                    codepage::translate(&mut self.pending[converted..], table);
+                }
+                self.held.drain(..piece_end);
+                if end {
+                    finish_conversion(
+                        &mut self.pending,
+                        options,
+                        mem::replace(progress, Progress::new()),
+                    );
+                }
+            }
+            State::Whole if end => {
+                let converted = convert_with_options(&self.held, options)?;
+                self.pending.extend_from_slice(&converted);
+                self.held.clear();
+            }
+            State::Whole => {}
+        }
+        Ok(())
+    }
+
+    /// Writes the output still pending to `inner`. What an error leaves
+    /// unwritten stays pending.
+    fn write_pending(&mut self) -> io::Result<()> {
+        let inner = self.inner.as_mut().unwrap();
+        let mut written = 0;
+        let result = loop {
+            if written == self.pending.len() {
+                break Ok(());
+            }
+            match inner.write(&self.pending[written..]) {
+                Ok(0) => break Err(io::ErrorKind::WriteZero.into()),
+                Ok(len) => written += len,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+                Err(e) => break Err(e),
+            }
+        };
+        self.pending.drain(..written);
+        result
+    }
+}
+
+/// How to go on once the start of the input, `held`, shows whether it has a
+/// BOM, which is dropped from it. The BOM to write is added to `pending`.
+fn start(held: &mut Vec<u8>, pending: &mut Vec<u8>, options: &ConversionOptions) -> State {
+    let bom = BomKind::detect(held);
+    // Converting by pieces cannot tell mixed line endings before the output
+    // has gone out, so `strict` holds it all
+    let wide = bom.is_some_and(|bom| bom.unit_size() > 1);
+    if wide || options.strict || !supports(options) {
+        return State::Whole;
+    }
+    if options.writes_utf8_bom(bom.is_some()) {
+        pending.extend_from_slice(BomKind::Utf8.bytes());
+    }
+    held.drain(..bom.map_or(0, |bom| bom.bytes().len()));
+    State::Pieces(Box::new(Pieces {
+        progress: Progress::new(),
+        scan: if options.force && options.verbose < 2 {
+            BinaryScan::finished()
+        } else {
+            BinaryScan::new(options.binary_policy)
+        },
+        scanner: LineEndingScanner::new(bom),
+        table: match bom {
+            None => options.char_conversion.table(options.conversion_mode),
+            Some(_) => None,
+        },
+    }))
+}
+
+impl<W: Write> Write for ConvertingWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.write_pending()?;
+        self.convert(buf, false)?;
+        // `buf` is taken from here on; an error is left to the next call
+        let _ = self.write_pending();
+        Ok(buf.len())
+    }
+
+    /// Writes any pending output and flushes the inner writer. A trailing CR
+    /// is still held back, since it may yet turn out to be the first half
+    /// of a CRLF pair.
+    fn flush(&mut self) -> io::Result<()> {
+        self.write_pending()?;
+        self.inner.as_mut().unwrap().flush()
+    }
+}
+
+impl<W: Write> Drop for ConvertingWriter<W> {
+    fn drop(&mut self) {
+        if self.inner.is_some() {
+            let _ = self.write_tail();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::CharConversion;
+
+    /// Writes each of `pieces` to a writer converting with `conversion_mode`
+    /// and returns what it wrote once finished.
+    fn written(conversion_mode: ConversionMode, pieces: &[&[u8]]) -> Vec<u8> {
+        let mut writer = ConvertingWriter::new(Vec::new(), conversion_mode);
+        for piece in pieces {
+            writer.write_all(piece).unwrap();
+        }
+        writer.finish().unwrap()
+    }
+
+    /// A writer that fails the write calls `failures` says, by number.
+    struct Failing {
+        output: Vec<u8>,
+        calls: usize,
+        failures: &'static [usize],
+    }
+
+    impl Write for Failing {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.calls += 1;
+            if self.failures.contains(&self.calls) {
+                return Err(io::Error::other("disk full"));
+            }
+            self.output.extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn crlf_split_across_writes_is_one_line_break() {
+        assert_eq!(written(ConversionMode::ToUnix, &[b"a\r", b"\nb"]), b"a\nb");
+        assert_eq!(written(ConversionMode::ToDos, &[b"a\r", b"\nb"]), b"a\r\nb");
+        // Mac mode leaves a CRLF alone, whole
+        assert_eq!(
+            written(ConversionMode::ToMac, &[b"a\r", b"\nb\n"]),
+            b"a\r\nb\r"
+        );
+    }
+
+    #[test]
+    fn trailing_cr_is_written_by_finish() {
+        let mut writer = ConvertingWriter::new(Vec::new(), ConversionMode::ToUnix);
+        writer.write_all(b"a\r\nb\r").unwrap();
+        writer.flush().unwrap();
+        assert_eq!(writer.get_ref(), b"a\nb");
+        assert_eq!(writer.finish().unwrap(), b"a\nb\r");
+    }
+
+    #[test]
+    fn add_eol_terminates_the_last_line() {
+        let mut writer = ConvertingWriter::with_add_eol(Vec::new(), ConversionMode::ToDos, true);
+        writer.write_all(b"a\nb").unwrap();
+        assert_eq!(writer.finish().unwrap(), b"a\r\nb\r\n");
+    }
+
+    #[test]
+    fn output_that_failed_to_go_out_is_written_once() {
+        let inner = Failing {
+            output: Vec::new(),
+            calls: 0,
+            failures: &[1],
+        };
+        let mut writer = ConvertingWriter::new(inner, ConversionMode::ToUnix);
+        assert_eq!(writer.write(b"a\r\nb").unwrap(), 4);
+        writer.write_all(b"\r\nc").unwrap();
+        assert_eq!(writer.finish().unwrap().output, b"a\nb\nc");
+    }
+
+    #[test]
+    fn a_failed_write_takes_nothing() {
+        let inner = Failing {
+            output: Vec::new(),
+            calls: 0,
+            failures: &[1, 2],
+        };
+        let mut writer = ConvertingWriter::new(inner, ConversionMode::ToUnix);
+        assert_eq!(writer.write(b"one\r\n").unwrap(), 5);
+        assert!(writer.write(b"b\r\n").is_err());
+        // Tried again, as a caller would after the error
+        writer.write_all(b"b\r\n").unwrap();
+        assert_eq!(writer.finish().unwrap().output, b"one\nb\n");
+    }
+
+    /// Writes `input` to a writer converting with `options`, split at
+    /// `split`, and returns what it wrote once finished.
+    fn written_with(
+        options: &ConversionOptions,
+        input: &[u8],
+        split: usize,
+    ) -> io::Result<Vec<u8>> {
+        let mut writer = ConvertingWriter::with_options(Vec::new(), options.clone());
+        writer.write_all(&input[..split])?;
+        writer.write_all(&input[split..])?;
+        writer.finish()
+    }
+
+    #[test]
+    fn every_option_converts_as_in_memory() {
+        let inputs: [&[u8]; 6] = [
+            b"one\r\ntwo\nthree\rfour",
+            b"\r\n\r\r\n\n\r",
+            "\u{feff}caf\u{e9}\r\nna\u{ef}ve\u{2028}end\u{85}\r".as_bytes(),
+            b"\xff\xfea\x00\r\x00\n\x00b\x00\n\x00",
+            b"latin \xe9\r\n",
+            b"bin\x00ary\r\n",
+        ];
+        let plain = ConversionOptions::new(ConversionMode::ToUnix);
+        let mut variants = Vec::new();
+        for conversion_mode in [
+            ConversionMode::ToUnix,
+            ConversionMode::ToDos,
+            ConversionMode::ToMac,
+        ] {
+            let base = ConversionOptions {
+                conversion_mode,
+                ..plain.clone()
+            };
+            variants.extend([
+                base.clone(),
+                ConversionOptions {
+                    normalize: true,
+                    add_eol: true,
+                    ..base.clone()
+                },
+                ConversionOptions {
+                    strip_cr: true,
+                    unicode_breaks: true,
+                    keep_bom: true,
+                    ..base.clone()
+                },
+                ConversionOptions {
+                    add_bom: true,
+                    force: true,
+                    char_conversion: CharConversion::SevenBit,
+                    ..base.clone()
+                },
+                ConversionOptions {
+                    strict: true,
+                    ..base
+                },
+            ]);
+        }
+        for options in &variants {
+            for input in inputs {
+                let expected = convert_with_options(input, options).map_err(|e| e.kind());
+                for split in 0..=input.len() {
+                    let output = written_with(options, input, split).map_err(|e| e.kind());
+                    assert_eq!(output, expected, "{:?} split at {}", input, split);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn binary_input_fails_every_call_after() {
+        let options = ConversionOptions::new(ConversionMode::ToUnix);
+        let mut writer = ConvertingWriter::with_options(Vec::new(), options);
+        writer.write_all(b"text\r\n").unwrap();
+        let error = writer.write(b"\x00").unwrap_err();
+        assert_eq!(ErrorCode::of(&error), ErrorCode::Binary);
+        assert!(writer.write(b"more").is_err());
+        assert_eq!(
+            ErrorCode::of(&writer.finish().unwrap_err()),
+            ErrorCode::Binary
+        );
+    }
+}
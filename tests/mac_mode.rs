@@ -0,0 +1,57 @@
+//! `unix2dos -m`, which writes Mac line endings instead of DOS ones.
+
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::process::{Command, Output, Stdio};
+
+/// Runs `unix2dos` with `args`, feeding it `input`, away from any
+/// configuration files or default options of the user.
+fn unix2dos(args: &[&str], input: &[u8]) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_unix2dos"))
+        .arg("--no-config")
+        .args(args)
+        .env_remove("UNIX2DOS_OPTS")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn mac_mode_writes_cr_line_breaks() {
+    let output = unix2dos(&["-m"], b"one\ntwo\nthree\n");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"one\rtwo\rthree\r");
+}
+
+#[test]
+fn mac_mode_converts_files() {
+    let dir = Path::new(env!("CARGO_TARGET_TMPDIR"));
+    let input = dir.join("mac_mode_input.txt");
+    let output = dir.join("mac_mode_output.txt");
+    fs::write(&input, b"one\ntwo\n").unwrap();
+
+    let result = unix2dos(
+        &[
+            "--mac",
+            "-n",
+            input.to_str().unwrap(),
+            output.to_str().unwrap(),
+        ],
+        b"",
+    );
+    assert!(result.status.success());
+    assert_eq!(fs::read(&output).unwrap(), b"one\rtwo\r");
+    assert_eq!(fs::read(&input).unwrap(), b"one\ntwo\n");
+}
+
+#[test]
+fn without_mac_mode_writes_crlf_line_breaks() {
+    let output = unix2dos(&[], b"one\ntwo\n");
+    assert!(output.status.success());
+    assert_eq!(output.stdout, b"one\r\ntwo\r\n");
+}
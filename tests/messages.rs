@@ -0,0 +1,391 @@
+//! The message catalogs in `po/`: the template `dos2unix.pot` extracted
+//! from the sources, and the translations merged with it.
+//!
+//! After changing messages, run `UPDATE_MESSAGES=1 cargo test --test
+//! messages` to extract them again and merge the translations, which adds
+//! the new messages untranslated and drops those that are gone.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// The functions and macros that mark messages, with whether they take a
+/// plural form after the message.
+const KEYWORDS: [(&str, bool); 4] = [
+    ("gettext(", false),
+    ("ngettext(", true),
+    ("tr!(", false),
+    ("trn!(", true),
+];
+
+/// Width gettext keeps the lines of catalogs within.
+const WIDTH: usize = 79;
+
+const TEMPLATE_HEADER: &str = r#"# Messages of the dos2unix tools, for translation.
+# Extracted by `UPDATE_MESSAGES=1 cargo test --test messages`; do not edit.
+msgid ""
+msgstr ""
+"Project-Id-Version: dos2unix\n"
+"Content-Type: text/plain; charset=UTF-8\n"
+"Content-Transfer-Encoding: 8bit\n"
+"Plural-Forms: nplurals=INTEGER; plural=EXPRESSION;\n"
+"#;
+
+/// A message marked for translation.
+struct Message {
+    msgid: String,
+    plural: Option<String>,
+    /// The files it is in.
+    files: Vec<String>,
+}
+
+/// An entry of a `.po` file.
+#[derive(Default)]
+struct Entry {
+    msgid: String,
+    plural: Option<String>,
+    msgstr: Vec<String>,
+    fuzzy: bool,
+}
+
+fn root() -> &'static Path {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+}
+
+/// The Rust files below `dir`, in order.
+fn source_files(dir: &Path, files: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            source_files(&path, files);
+        } else if path.extension().is_some_and(|extension| extension == "rs") {
+            files.push(path);
+        }
+    }
+}
+
+/// The messages marked in the sources, in the order they first appear.
+fn extract() -> Vec<Message> {
+    let mut files = Vec::new();
+    source_files(&root().join("src"), &mut files);
+    let mut messages: Vec<Message> = Vec::new();
+    for path in files {
+        let name = path
+            .strip_prefix(root())
+            .unwrap()
+            .to_string_lossy()
+            .replace('\\', "/");
+        let source = fs::read_to_string(&path).unwrap();
+        for (msgid, plural) in marked_messages(&source) {
+            match messages.iter_mut().find(|message| message.msgid == msgid) {
+                Some(message) => {
+                    assert_eq!(message.plural, plural, "{:?} in {}", msgid, name);
+                    if !message.files.contains(&name) {
+                        message.files.push(name.clone());
+                    }
+                }
+                None => messages.push(Message {
+                    msgid,
+                    plural,
+                    files: vec![name.clone()],
+                }),
+            }
+        }
+    }
+    messages
+}
+
+/// The literals passed to the keywords in `source`, with the plural forms
+/// of those that take one.
+fn marked_messages(source: &str) -> Vec<(String, Option<String>)> {
+    let mut messages = Vec::new();
+    for (keyword, takes_plural) in KEYWORDS {
+        for (start, _) in source.match_indices(keyword) {
+            // Not the end of a longer name, as gettext is of ngettext
+            let before = source[..start].chars().next_back();
+            if before.is_some_and(|c| c.is_alphanumeric() || c == '_') {
+                continue;
+            }
+            // Definitions and uses with an expression are not messages
+            let Some((msgid, rest)) = string_literal(&source[start + keyword.len()..]) else {
+                continue;
+            };
+            let plural = if takes_plural {
+                let rest = rest.trim_start().strip_prefix(',').unwrap();
+                Some(string_literal(rest).unwrap().0)
+            } else {
+                None
+            };
+            messages.push((start, msgid, plural));
+        }
+    }
+    messages.sort_by_key(|(start, _, _)| *start);
+    messages
+        .into_iter()
+        .map(|(_, msgid, plural)| (msgid, plural))
+        .collect()
+}
+
+/// The value of the Rust string literal at the start of `text`, after any
+/// whitespace, and the text after it.
+fn string_literal(text: &str) -> Option<(String, &str)> {
+    let text = text.trim_start().strip_prefix('"')?;
+    let mut value = String::new();
+    let mut chars = text.char_indices();
+    while let Some((index, c)) = chars.next() {
+        match c {
+            '"' => return Some((value, &text[index + 1..])),
+            '\\' => match chars.next().unwrap().1 {
+                'n' => value.push('\n'),
+                't' => value.push('\t'),
+                'r' => value.push('\r'),
+                '0' => value.push('\0'),
+                // A line continuation, which skips the indentation after it
+                '\n' => {
+                    let rest = chars.as_str();
+                    let skipped = rest.len() - rest.trim_start().len();
+                    for _ in 0..rest[..skipped].chars().count() {
+                        chars.next();
+                    }
+                }
+                other => value.push(other),
+            },
+            other => value.push(other),
+        }
+    }
+    None
+}
+
+/// `text` escaped for a `.po` file.
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+        .replace('\r', "\\r")
+}
+
+/// Undoes [`escape`].
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            unescaped.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => unescaped.push('\n'),
+            Some('t') => unescaped.push('\t'),
+            Some('r') => unescaped.push('\r'),
+            Some(other) => unescaped.push(other),
+            None => {}
+        }
+    }
+    unescaped
+}
+
+/// The `keyword` line of an entry with the string `text`, wrapped as
+/// gettext wraps it: after each line break, and at spaces to keep lines
+/// within [`WIDTH`].
+fn write_string(out: &mut String, keyword: &str, text: &str) {
+    let escaped = escape(text);
+    let multiline = text.trim_end_matches('\n').contains('\n');
+    if !multiline && keyword.len() + escaped.len() + 3 <= WIDTH {
+        out.push_str(&format!("{} \"{}\"\n", keyword, escaped));
+        return;
+    }
+    out.push_str(&format!("{} \"\"\n", keyword));
+    for line in escaped.split_inclusive("\\n") {
+        let mut chunk = String::new();
+        for word in line.split_inclusive(' ') {
+            if !chunk.is_empty() && chunk.len() + word.len() + 2 > WIDTH {
+                out.push_str(&format!("\"{}\"\n", chunk));
+                chunk.clear();
+            }
+            chunk.push_str(word);
+        }
+        out.push_str(&format!("\"{}\"\n", chunk));
+    }
+}
+
+/// The entries of the `.po` file `po`, the header first.
+fn parse_po(po: &str) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    for block in po.split("\n\n") {
+        let mut entry = Entry::default();
+        let mut current: Option<&mut String> = None;
+        for line in block.lines() {
+            if line.starts_with("#,") {
+                entry.fuzzy |= line.contains("fuzzy");
+                continue;
+            }
+            if line.starts_with('#') {
+                continue;
+            }
+            let (keyword, value) = match line.strip_prefix('"') {
+                Some(_) => ("", line),
+                None => line.split_once(' ').unwrap(),
+            };
+            let value = unescape(&value[1..value.len() - 1]);
+            current = match keyword {
+                "" => current,
+                "msgid" => Some(&mut entry.msgid),
+                "msgid_plural" => Some(entry.plural.insert(String::new())),
+                _ => {
+                    entry.msgstr.push(String::new());
+                    entry.msgstr.last_mut()
+                }
+            };
+            current.as_mut().unwrap().push_str(&value);
+        }
+        if !block.trim().is_empty() {
+            entries.push(entry);
+        }
+    }
+    entries
+}
+
+/// The template of the catalogs for `messages`.
+fn template(messages: &[Message]) -> String {
+    let mut pot = String::from(TEMPLATE_HEADER);
+    for message in messages {
+        write_entry(&mut pot, message, &[], false, 2);
+    }
+    pot
+}
+
+/// Appends the entry of `message` with the translation `msgstr`, which is
+/// empty while there is none, to `out`.
+fn write_entry(out: &mut String, message: &Message, msgstr: &[String], fuzzy: bool, forms: usize) {
+    out.push('\n');
+    out.push_str(&format!("#: {}\n", message.files.join(" ")));
+    if fuzzy {
+        out.push_str("#, fuzzy\n");
+    }
+    write_string(out, "msgid", &message.msgid);
+    match &message.plural {
+        Some(plural) => {
+            write_string(out, "msgid_plural", plural);
+            for form in 0..forms.max(msgstr.len()) {
+                let text = msgstr.get(form).map_or("", String::as_str);
+                write_string(out, &format!("msgstr[{}]", form), text);
+            }
+        }
+        None => write_string(out, "msgstr", msgstr.first().map_or("", String::as_str)),
+    }
+}
+
+/// The translation `po` brought up to date with `messages`: its header, and
+/// an entry for each message, with the translation `po` had for it.
+fn merge(messages: &[Message], po: &str) -> String {
+    let header_end = po.find("\n\n").map_or(po.len(), |end| end + 1);
+    let mut merged = po[..header_end].to_string();
+    let entries = parse_po(po);
+    let forms = entries[0]
+        .msgstr
+        .first()
+        .and_then(|header| header.split_once("nplurals="))
+        .and_then(|(_, rest)| rest.split(';').next()?.trim().parse().ok())
+        .unwrap_or(2);
+    let translations: BTreeMap<&str, &Entry> = entries[1..]
+        .iter()
+        .map(|entry| (entry.msgid.as_str(), entry))
+        .collect();
+    for message in messages {
+        match translations.get(message.msgid.as_str()) {
+            Some(entry) => write_entry(&mut merged, message, &entry.msgstr, entry.fuzzy, forms),
+            None => write_entry(&mut merged, message, &[], false, forms),
+        }
+    }
+    merged
+}
+
+/// The translations in `po/`, by path.
+fn translations() -> Vec<(PathBuf, String)> {
+    let mut translations: Vec<_> = fs::read_dir(root().join("po"))
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "po"))
+        .map(|path| {
+            let po = fs::read_to_string(&path).unwrap();
+            (path, po)
+        })
+        .collect();
+    translations.sort();
+    translations
+}
+
+#[test]
+fn catalogs_are_up_to_date() {
+    let messages = extract();
+    let update = env::var_os("UPDATE_MESSAGES").is_some();
+    let mut stale = Vec::new();
+    let pot_path = root().join("po").join("dos2unix.pot");
+    let mut catalogs = vec![(pot_path.clone(), template(&messages))];
+    for (path, po) in translations() {
+        catalogs.push((path, merge(&messages, &po)));
+    }
+    for (path, catalog) in catalogs {
+        if fs::read_to_string(&path).ok().as_deref() == Some(catalog.as_str()) {
+            continue;
+        }
+        if update {
+            fs::write(&path, catalog).unwrap();
+        } else {
+            stale.push(path.display().to_string());
+        }
+    }
+    assert!(
+        stale.is_empty(),
+        "out of date with the messages in the sources: {}; \
+         run `UPDATE_MESSAGES=1 cargo test --test messages`",
+        stale.join(", ")
+    );
+}
+
+/// The placeholders of `text`: how many `{}` there are, and which
+/// `{0}`, `{1}` and so on.
+fn placeholders(text: &str) -> (usize, Vec<usize>) {
+    let (mut sequential, mut indexed) = (0, Vec::new());
+    let text = text.replace("{{", "").replace("}}", "");
+    for (start, _) in text.match_indices('{') {
+        let inside = &text[start + 1..start + text[start..].find('}').unwrap()];
+        match inside {
+            "" => sequential += 1,
+            index => indexed.push(index.parse().unwrap()),
+        }
+    }
+    indexed.sort_unstable();
+    indexed.dedup();
+    (sequential, indexed)
+}
+
+#[test]
+fn translations_keep_placeholders() {
+    for (path, po) in translations() {
+        for entry in &parse_po(&po)[1..] {
+            let (expected, _) = placeholders(&entry.msgid);
+            for form in entry.msgstr.iter().filter(|form| !form.is_empty()) {
+                let (sequential, indexed) = placeholders(form);
+                let matches = if indexed.is_empty() {
+                    sequential == expected
+                } else {
+                    sequential == 0 && indexed == (0..expected).collect::<Vec<_>>()
+                };
+                assert!(
+                    matches,
+                    "{}: {:?} has other placeholders than {:?}",
+                    path.display(),
+                    form,
+                    entry.msgid
+                );
+            }
+        }
+    }
+}